@@ -10,6 +10,9 @@ create table if not exists public.books (
     active_at timestamp,
     is_kindle_unlimited boolean not null default false,
     binding_name text,
+    point integer,
+    author text,
+    store_links_checked_at timestamp,
     constraint books_pkey primary key (bookmeter_id)
 );
 create index if not exists books_kindle_id_index on public.books (kindle_id);
@@ -33,7 +36,41 @@ SELECT bookmeter_id,
    FROM books
   WHERE discount_rate IS NOT NULL AND discount_rate >= 0.15::double precision OR is_kindle_unlimited;
 
--- 中古本サイト (bookoff / valuebooks / netoff) の商品オファー
+-- 読書メーターが案内している外部書店 (Amazon / 楽天ブックス / honto など) へのリンク
+create table if not exists public.book_store_links (
+    bookmeter_id bigint not null,
+    store text not null,
+    url text not null,
+    updated_at timestamp not null,
+    constraint book_store_links_pkey primary key (bookmeter_id, store),
+    constraint book_store_links_bookmeter_id_fkey foreign key (bookmeter_id) references public.books (bookmeter_id) on delete cascade
+);
+
+-- 読書メーターから詳細を取得できなかった本 (指数バックオフで再取得する)
+create table if not exists public.fetch_failures (
+    bookmeter_id bigint not null,
+    error_kind text not null,
+    last_error text not null,
+    attempts integer not null,
+    next_attempt_at timestamp not null,
+    first_failed_at timestamp not null,
+    updated_at timestamp not null,
+    constraint fetch_failures_pkey primary key (bookmeter_id)
+);
+
+-- update_discounts の実行結果 (ユーザーが存在しない・非公開などの検知用)
+create table if not exists public.run_reports (
+    id bigserial not null,
+    user_id text not null,
+    outcome text not null,
+    error text,
+    started_at timestamp not null,
+    finished_at timestamp not null,
+    constraint run_reports_pkey primary key (id)
+);
+create index if not exists run_reports_user_id_index on public.run_reports (user_id, finished_at);
+
+-- 中古本サイト (bookoff / valuebooks / netoff / surugaya / mottainai / amazon_used) の商品オファー
 create table if not exists public.used_book_offers (
     bookmeter_id bigint not null,
     site text not null,
@@ -41,13 +78,72 @@ create table if not exists public.used_book_offers (
     product_url text,
     price integer,
     condition text,
+    condition_grade text not null default 'unknown',
     in_stock boolean not null default false,
+    stock_count integer,
+    listing_type text,
+    offer_count integer,
+    match_score real,
+    landed_price integer,
     updated_at timestamp not null,
     constraint used_book_offers_pkey primary key (bookmeter_id, site),
     constraint used_book_offers_bookmeter_id_fkey foreign key (bookmeter_id) references public.books (bookmeter_id) on delete cascade
 );
 create index if not exists used_book_offers_product_id_index on public.used_book_offers (product_id);
 
+-- 中古本オファーの状態ごとの価格・在庫 (バリューブックス・もったいない本舗など状態ごとに在庫を持つサイト)
+create table if not exists public.used_book_offer_conditions (
+    bookmeter_id bigint not null,
+    site text not null,
+    condition text not null,
+    grade text not null,
+    price integer,
+    stock_count integer,
+    in_stock boolean not null default false,
+    updated_at timestamp not null,
+    constraint used_book_offer_conditions_pkey primary key (bookmeter_id, site, condition),
+    constraint used_book_offer_conditions_offer_fkey foreign key (bookmeter_id, site) references public.used_book_offers (bookmeter_id, site) on delete cascade
+);
+
+-- 本ごとの中古本オファー取得の指定 (always: 書籍の形式に関わらず取得する / never: 取得しない)
+create table if not exists public.used_book_overrides (
+    bookmeter_id bigint not null,
+    lookup text not null,
+    updated_at timestamp not null,
+    constraint used_book_overrides_pkey primary key (bookmeter_id)
+);
+
+-- 本ごと・図書館ごとの蔵書・貸出状況 (カーリル互換 API から取得する)
+create table if not exists public.library_availabilities (
+    bookmeter_id bigint not null,
+    system_id text not null,
+    library text not null,
+    status text not null,
+    reserve_url text,
+    updated_at timestamp not null,
+    constraint library_availabilities_pkey primary key (bookmeter_id, system_id, library),
+    constraint library_availabilities_bookmeter_id_fkey foreign key (bookmeter_id) references public.books (bookmeter_id) on delete cascade
+);
+
+-- 中古本サイトごとの送料 (送料込みの価格の計算に使う。初期値は各サイトの案内に合わせて適宜更新する)
+create table if not exists public.shipping_fees (
+    site text not null,
+    fee integer not null,
+    free_threshold integer,
+    member_fee integer,
+    member_free_threshold integer,
+    is_member boolean not null default false,
+    constraint shipping_fees_pkey primary key (site)
+);
+insert into public.shipping_fees (site, fee, free_threshold, member_fee, member_free_threshold) values
+    ('bookoff', 440, 2000, null, null),
+    ('valuebooks', 350, 1500, null, null),
+    ('netoff', 440, 1500, null, null),
+    ('surugaya', 440, 1500, null, null),
+    ('mottainai', 440, 1500, null, null),
+    ('amazon_used', 350, null, null, null)
+on conflict (site) do nothing;
+
 -- 中古本オファーと書籍情報の結合ビュー (外部サービスから参照される)
 create or replace view public.used_book_offers_with_books as
 SELECT o.bookmeter_id,
@@ -56,8 +152,14 @@ SELECT o.bookmeter_id,
     o.price,
     o.condition,
     o.in_stock,
+    o.offer_count,
     o.updated_at,
     b.title,
-    b.amazon_url
+    b.amazon_url,
+    o.condition_grade,
+    o.landed_price,
+    o.stock_count,
+    o.listing_type,
+    o.match_score
    FROM used_book_offers o
      JOIN books b ON b.bookmeter_id = o.bookmeter_id;
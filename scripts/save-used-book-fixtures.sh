@@ -0,0 +1,56 @@
+#!/bin/bash
+# 中古本サイトの実際の検索結果と商品ページを tests/fixtures/used_book/ に保存する
+#
+# 使い方: scripts/save-used-book-fixtures.sh <site> [ISBN-13] [品切れの商品ページの URL]
+#
+# - {site}_search.html: ISBN の検索結果
+# - {site}_product.html: 検索結果の先頭の商品ページ
+# - {site}_search_empty.html: 該当なしの検索結果
+# - {site}_product_oos.html: 品切れの商品ページ (URL を指定した場合のみ)
+#
+# 保存後は各サイトのテスト (src/used_book/{site}.rs) の商品 ID・価格と
+# tests/mock_sites/mod.rs のパスを保存したページに合わせる。
+set -euo pipefail
+
+site="${1:?usage: $0 <surugaya|mottainai> [ISBN-13] [out-of-stock product URL]}"
+isbn="${2:-9784813705185}"
+oos_url="${3:-}"
+# どのサイトにも存在しない ISBN (該当なしの検索結果用)
+missing_isbn="9784000000001"
+dir="$(cd "$(dirname "$0")/.." && pwd)/tests/fixtures/used_book"
+
+fetch() {
+  curl -sSfL -m 30 "$1" \
+    -H 'accept-language: ja,en;q=0.9' \
+    -H 'user-agent: Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/130.0.0.0 Safari/537.36'
+}
+
+case "$site" in
+surugaya)
+  base="https://www.suruga-ya.jp"
+  search_url() { echo "$base/search?category=&search_word=$1"; }
+  product_pattern='/product/detail/[0-9A-Za-z]+'
+  ;;
+mottainai)
+  base="https://www.mottainaihonpo.com"
+  search_url() { echo "$base/search/?q=$1"; }
+  product_pattern='/item/[0-9]+/'
+  ;;
+*)
+  echo "unknown site: $site" >&2
+  exit 1
+  ;;
+esac
+
+fetch "$(search_url "$isbn")" >"$dir/${site}_search.html"
+path=$(grep -oE "$product_pattern" "$dir/${site}_search.html" | head -n 1 || true)
+if [ -z "$path" ]; then
+  echo "no product link found in the search result for $isbn" >&2
+  exit 1
+fi
+fetch "$base$path" >"$dir/${site}_product.html"
+fetch "$(search_url "$missing_isbn")" >"$dir/${site}_search_empty.html"
+if [ -n "$oos_url" ]; then
+  fetch "$oos_url" >"$dir/${site}_product_oos.html"
+fi
+echo "saved $site fixtures (product: $base$path)"
@@ -0,0 +1,343 @@
+//! 複数の本をまとめて買う場合の最安の買い方 (Kindle / 中古本サイト) を求める
+//!
+//! サイトごとの送料無料の金額があるため、1冊ずつ最安のサイトを選ぶより
+//! 同じサイトにまとめた方が安くなることがある。
+//! 使うサイトの組み合わせを全て試して各本を組み合わせ内の最安のサイトに割り当て、
+//! 最も安い割り当てから1冊ずつ買う先を入れ替える局所探索で改善する (厳密解は保証しない)。
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::shipping_fee;
+
+/// Kindle 版を表すチャネル名 (送料はかからない)
+pub const KINDLE_CHANNEL: &str = "kindle";
+
+/// 使うサイトの組み合わせを全て試すチャネル数の上限 (これを超える場合は全チャネルから探索を始める)
+const MAX_ENUMERATED_CHANNELS: usize = 12;
+
+/// 買う候補の本
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BasketBook {
+    pub bookmeter_id: i64,
+    pub title: String,
+    /// 買える先の一覧 (Kindle 版・在庫のある中古本オファー)。空の場合は買わない
+    pub options: Vec<PurchaseOption>,
+}
+
+/// 1冊を買える先1つ分
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PurchaseOption {
+    /// `kindle` または `used_book_offers.site` の値
+    pub channel: String,
+    /// 送料を除いた価格 (円)
+    pub price: i32,
+    pub url: String,
+    /// 新品 (`new`) / 中古 (`used`) のどちらの出品か (`used_book_offers.listing_type`。Kindle 版は None)
+    pub listing_type: Option<String>,
+}
+
+/// 最安の買い方
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BasketPlan {
+    /// チャネルごとの買い物リスト (チャネル名順)
+    pub orders: Vec<ChannelOrder>,
+    /// 買える先がなかった本
+    pub skipped: Vec<SkippedBook>,
+    /// 送料込みの合計 (円)
+    pub total: i32,
+}
+
+/// 1つのチャネル (Kindle または中古本サイト) でまとめて買う本
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelOrder {
+    pub channel: String,
+    pub items: Vec<BasketLine>,
+    pub subtotal: i32,
+    pub shipping: i32,
+    pub total: i32,
+}
+
+/// 買い物リストの1冊
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BasketLine {
+    pub bookmeter_id: i64,
+    pub title: String,
+    pub price: i32,
+    pub url: String,
+    /// 新品 (`new`) / 中古 (`used`) のどちらの出品か
+    pub listing_type: Option<String>,
+}
+
+/// 買える先がなかった本
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SkippedBook {
+    pub bookmeter_id: i64,
+    pub title: String,
+}
+
+/// 送料込みの合計が最も安くなるように、本ごとに買う先を選ぶ
+#[must_use]
+pub fn optimise(books: &[BasketBook], shipping_fees: &[shipping_fee::Model]) -> BasketPlan {
+    let fees: BTreeMap<&str, &shipping_fee::Model> = shipping_fees
+        .iter()
+        .map(|fee| (fee.site.as_str(), fee))
+        .collect();
+    let mut channels: Vec<&str> = books
+        .iter()
+        .flat_map(|book| book.options.iter().map(|option| option.channel.as_str()))
+        .collect();
+    channels.sort_unstable();
+    channels.dedup();
+
+    // 使うチャネルの組み合わせごとに、各本を組み合わせ内の最安のチャネルに割り当てる
+    let mut best = cheapest_assignment(books, |_| true);
+    let mut best_cost = total_cost(books, &best, &fees);
+    if channels.len() <= MAX_ENUMERATED_CHANNELS {
+        for mask in 1..(1u32 << channels.len()) {
+            let assignment = cheapest_assignment(books, |channel| {
+                channels
+                    .iter()
+                    .position(|c| *c == channel)
+                    .is_some_and(|i| mask & (1 << i) != 0)
+            });
+            // 組み合わせ内で買えない本が出る場合は候補にしない
+            let covers_all = books
+                .iter()
+                .zip(&assignment)
+                .all(|(book, choice)| book.options.is_empty() || choice.is_some());
+            if !covers_all {
+                continue;
+            }
+            let cost = total_cost(books, &assignment, &fees);
+            if cost < best_cost {
+                best = assignment;
+                best_cost = cost;
+            }
+        }
+    }
+
+    // 1冊ずつ買う先を入れ替えて安くなる限り続ける
+    let mut improved = true;
+    while improved {
+        improved = false;
+        for (book_index, book) in books.iter().enumerate() {
+            for option_index in 0..book.options.len() {
+                if best[book_index] == Some(option_index) {
+                    continue;
+                }
+                let mut candidate = best.clone();
+                candidate[book_index] = Some(option_index);
+                let cost = total_cost(books, &candidate, &fees);
+                if cost < best_cost {
+                    best = candidate;
+                    best_cost = cost;
+                    improved = true;
+                }
+            }
+        }
+    }
+
+    build_plan(books, &best, &fees)
+}
+
+/// `allowed` なチャネルのうち、本ごとに最安の買い先を選ぶ
+fn cheapest_assignment(books: &[BasketBook], allowed: impl Fn(&str) -> bool) -> Vec<Option<usize>> {
+    books
+        .iter()
+        .map(|book| {
+            book.options
+                .iter()
+                .enumerate()
+                .filter(|(_, option)| allowed(&option.channel))
+                .min_by_key(|(_, option)| option.price)
+                .map(|(index, _)| index)
+        })
+        .collect()
+}
+
+/// チャネルごとの小計
+fn subtotals<'a>(books: &'a [BasketBook], assignment: &[Option<usize>]) -> BTreeMap<&'a str, i32> {
+    let mut subtotals = BTreeMap::new();
+    for (book, choice) in books.iter().zip(assignment) {
+        if let Some(option) = choice.and_then(|index| book.options.get(index)) {
+            *subtotals.entry(option.channel.as_str()).or_insert(0) += option.price;
+        }
+    }
+    subtotals
+}
+
+/// チャネルの小計にかかる送料 (送料の設定がないチャネルは 0)
+fn shipping(fees: &BTreeMap<&str, &shipping_fee::Model>, channel: &str, subtotal: i32) -> i32 {
+    fees.get(channel).map_or(0, |fee| fee.fee_for(subtotal))
+}
+
+/// 送料込みの合計
+fn total_cost(
+    books: &[BasketBook],
+    assignment: &[Option<usize>],
+    fees: &BTreeMap<&str, &shipping_fee::Model>,
+) -> i32 {
+    subtotals(books, assignment)
+        .into_iter()
+        .map(|(channel, subtotal)| subtotal + shipping(fees, channel, subtotal))
+        .sum()
+}
+
+fn build_plan(
+    books: &[BasketBook],
+    assignment: &[Option<usize>],
+    fees: &BTreeMap<&str, &shipping_fee::Model>,
+) -> BasketPlan {
+    let mut items: BTreeMap<&str, Vec<BasketLine>> = BTreeMap::new();
+    let mut skipped = Vec::new();
+    for (book, choice) in books.iter().zip(assignment) {
+        match choice.and_then(|index| book.options.get(index)) {
+            Some(option) => items
+                .entry(option.channel.as_str())
+                .or_default()
+                .push(BasketLine {
+                    bookmeter_id: book.bookmeter_id,
+                    title: book.title.clone(),
+                    price: option.price,
+                    url: option.url.clone(),
+                    listing_type: option.listing_type.clone(),
+                }),
+            None => skipped.push(SkippedBook {
+                bookmeter_id: book.bookmeter_id,
+                title: book.title.clone(),
+            }),
+        }
+    }
+    let orders: Vec<ChannelOrder> = items
+        .into_iter()
+        .map(|(channel, items)| {
+            let subtotal = items.iter().map(|item| item.price).sum();
+            let shipping = shipping(fees, channel, subtotal);
+            ChannelOrder {
+                channel: channel.to_string(),
+                items,
+                subtotal,
+                shipping,
+                total: subtotal + shipping,
+            }
+        })
+        .collect();
+    let total = orders.iter().map(|order| order.total).sum();
+    BasketPlan {
+        orders,
+        skipped,
+        total,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fee(site: &str, fee: i32, free_threshold: Option<i32>) -> shipping_fee::Model {
+        shipping_fee::Model {
+            site: site.to_string(),
+            fee,
+            free_threshold,
+            member_fee: None,
+            member_free_threshold: None,
+            is_member: false,
+        }
+    }
+
+    fn option(channel: &str, price: i32) -> PurchaseOption {
+        PurchaseOption {
+            channel: channel.to_string(),
+            price,
+            url: format!("https://example.com/{channel}/{price}"),
+            listing_type: None,
+        }
+    }
+
+    fn book(bookmeter_id: i64, options: Vec<PurchaseOption>) -> BasketBook {
+        BasketBook {
+            bookmeter_id,
+            title: format!("book {bookmeter_id}"),
+            options,
+        }
+    }
+
+    fn channels_of(plan: &BasketPlan) -> Vec<(&str, Vec<i64>)> {
+        plan.orders
+            .iter()
+            .map(|order| {
+                (
+                    order.channel.as_str(),
+                    order.items.iter().map(|item| item.bookmeter_id).collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_optimise_prefers_kindle_over_cheap_item_with_shipping() {
+        // 110円 + 送料440円より Kindle 版 500円の方が安い
+        let books = vec![book(1, vec![option("bookoff", 110), option("kindle", 500)])];
+        let plan = optimise(&books, &[fee("bookoff", 440, Some(2000))]);
+        assert_eq!(channels_of(&plan), vec![("kindle", vec![1])]);
+        assert_eq!(plan.total, 500);
+    }
+
+    #[test]
+    fn test_optimise_consolidates_to_reach_free_shipping() {
+        // 1冊ずつ最安のサイトを選ぶと両サイトで送料がかかるが、
+        // netoff にまとめると1,500円以上で送料無料になる
+        let books = vec![
+            book(1, vec![option("bookoff", 700), option("netoff", 750)]),
+            book(2, vec![option("netoff", 800)]),
+        ];
+        let fees = [
+            fee("bookoff", 440, Some(2000)),
+            fee("netoff", 440, Some(1500)),
+        ];
+        let plan = optimise(&books, &fees);
+        assert_eq!(channels_of(&plan), vec![("netoff", vec![1, 2])]);
+        assert_eq!(plan.orders[0].shipping, 0);
+        assert_eq!(plan.total, 1550);
+    }
+
+    #[test]
+    fn test_optimise_keeps_listing_type() {
+        let new = PurchaseOption {
+            listing_type: Some("new".to_string()),
+            ..option("bookoff", 600)
+        };
+        let books = vec![book(1, vec![new, option("kindle", 700)])];
+        let plan = optimise(&books, &[]);
+        assert_eq!(channels_of(&plan), vec![("bookoff", vec![1])]);
+        assert_eq!(plan.orders[0].items[0].listing_type.as_deref(), Some("new"));
+    }
+
+    #[test]
+    fn test_optimise_skips_books_without_options() {
+        let books = vec![book(1, vec![option("kindle", 500)]), book(2, Vec::new())];
+        let plan = optimise(&books, &[]);
+        assert_eq!(channels_of(&plan), vec![("kindle", vec![1])]);
+        assert_eq!(
+            plan.skipped,
+            vec![SkippedBook {
+                bookmeter_id: 2,
+                title: "book 2".to_string(),
+            }]
+        );
+        assert_eq!(plan.total, 500);
+    }
+
+    #[test]
+    fn test_optimise_empty() {
+        let plan = optimise(&[], &[]);
+        assert!(plan.orders.is_empty());
+        assert_eq!(plan.total, 0);
+    }
+}
@@ -1,7 +1,12 @@
 use std::env;
+use std::path::PathBuf;
 use std::time::Duration;
 
-use bookmeter_discounts::BookMeterDiscounts;
+use bookmeter_discounts::fetcher;
+use bookmeter_discounts::parser_check::{self, CheckOutcome, ReferencePages};
+use bookmeter_discounts::snapshot::{Snapshot, SnapshotArchive};
+use bookmeter_discounts::used_book_override::UsedBookLookup;
+use bookmeter_discounts::{BookMeterDiscounts, WishlistError};
 use futures::TryStreamExt;
 use sea_orm::{ConnectOptions, Database};
 use tracing::{error, info};
@@ -19,6 +24,11 @@ async fn main() {
 
     info!("Starting bookmeter_discounts...");
 
+    // DB を使わないサブコマンド (`snapshots` / `check-parsers`)
+    if let Some(code) = run_offline_command().await {
+        std::process::exit(code);
+    }
+
     // メインの処理
     let user_id = match env::var("USER_ID") {
         Ok(v) => v,
@@ -59,6 +69,30 @@ async fn main() {
     };
     info!("Database connected");
     let bookmeter_discounts = BookMeterDiscounts::new(&user_id, db, get_amazon_page_interval);
+
+    // `failures` サブコマンド: 読書メーターから取得できずに止まっている本の一覧を表示する
+    if env::args().nth(1).as_deref() == Some("failures") {
+        print_fetch_failures(&bookmeter_discounts).await;
+        return;
+    }
+
+    // `used-override <bookmeter_id> <always|never|clear>` サブコマンド:
+    // 書籍の形式に関わらず中古本オファーを取得する・しない本を指定する
+    if env::args().nth(1).as_deref() == Some("used-override") {
+        set_used_book_override(&bookmeter_discounts).await;
+        return;
+    }
+
+    // `basket [bookmeter_id...]` サブコマンド: 指定した本 (省略時は全ての本) の最安の買い方を表示する
+    if env::args().nth(1).as_deref() == Some("basket") {
+        let bookmeter_ids: Vec<i64> = env::args()
+            .skip(2)
+            .filter_map(|id| id.parse().ok())
+            .collect();
+        print_basket(&bookmeter_discounts, &bookmeter_ids).await;
+        return;
+    }
+
     match bookmeter_discounts.update_and_get_discounts().await {
         Ok(mut stream) => {
             println!("Title\tURL\tDiscount Rate");
@@ -81,7 +115,12 @@ async fn main() {
             }
         }
         Err(e) => {
-            error!("Error\t{:?}", e);
+            if let Some(wishlist_error) = e.downcast_ref::<WishlistError>() {
+                // ユーザーIDの設定ミスなどで、ネットワークエラーとは対処が異なる
+                error!("Cannot read the wishlist of USER_ID={user_id}: {wishlist_error}");
+            } else {
+                error!("Error\t{:?}", e);
+            }
         }
     }
 
@@ -94,3 +133,217 @@ async fn main() {
         }
     }
 }
+
+/// DB を使わないサブコマンドを実行して終了コードを返す (該当しない場合は None)
+///
+/// - `snapshots [list|show <file>|reparse [file...]]`: 解析に失敗して保存したページの一覧表示・
+///   HTML の出力・現在のパーサーでの再解析
+/// - `check-parsers`: 取得元ごとの参照ページを本番と同じパーサーで解析し、欠けた項目を表示する
+///   (終了コードは 0: 全て正常 / 1: 欠けた項目がある / 2: 取得・解析に失敗した取得元がある)
+async fn run_offline_command() -> Option<i32> {
+    match env::args().nth(1).as_deref() {
+        Some("snapshots") => Some(snapshots()),
+        Some("check-parsers") => Some(check_parsers().await),
+        _ => None,
+    }
+}
+
+/// 取得失敗の記録を一覧表示する
+async fn print_fetch_failures(bookmeter_discounts: &BookMeterDiscounts) {
+    match bookmeter_discounts.get_fetch_failures().await {
+        Ok(failures) => {
+            println!("Bookmeter ID\tURL\tKind\tAttempts\tFirst Failed\tNext Attempt\tLast Error");
+            for failure in failures {
+                println!(
+                    "{}\thttps://bookmeter.com/books/{}\t{}\t{}\t{}\t{}\t{}",
+                    failure.bookmeter_id,
+                    failure.bookmeter_id,
+                    failure.error_kind,
+                    failure.attempts,
+                    failure.first_failed_at,
+                    failure.next_attempt_at,
+                    failure.last_error.replace(['\t', '\n'], " ")
+                );
+            }
+        }
+        Err(e) => {
+            error!("Failed to get fetch failures: {:?}", e);
+        }
+    }
+}
+
+/// コマンドライン引数から本ごとの中古本オファー取得の指定を保存する
+async fn set_used_book_override(bookmeter_discounts: &BookMeterDiscounts) {
+    let Some(bookmeter_id) = env::args().nth(2).and_then(|id| id.parse::<i64>().ok()) else {
+        error!("Usage: used-override <bookmeter_id> <always|never|clear>");
+        return;
+    };
+    let lookup = match env::args().nth(3).as_deref() {
+        Some("clear") => None,
+        Some(value) if UsedBookLookup::parse(value).is_some() => UsedBookLookup::parse(value),
+        _ => {
+            error!("Usage: used-override <bookmeter_id> <always|never|clear>");
+            return;
+        }
+    };
+    match bookmeter_discounts
+        .set_used_book_override(bookmeter_id, lookup)
+        .await
+    {
+        Ok(()) => info!(
+            "used book lookup of {bookmeter_id}: {}",
+            lookup.map_or("clear", UsedBookLookup::as_str)
+        ),
+        Err(e) => error!("Failed to set used book override: {:?}", e),
+    }
+}
+
+/// まとめ買いの買い物リストをチャネルごとに表示する
+async fn print_basket(bookmeter_discounts: &BookMeterDiscounts, bookmeter_ids: &[i64]) {
+    match bookmeter_discounts.plan_basket(bookmeter_ids).await {
+        Ok(plan) => {
+            println!("Channel\tTitle\tPrice\tURL");
+            for order in &plan.orders {
+                for item in &order.items {
+                    println!(
+                        "{}\t{}\t{}\t{}",
+                        order.channel, item.title, item.price, item.url
+                    );
+                }
+                println!(
+                    "{}\tsubtotal {} + shipping {}\t{}\t",
+                    order.channel, order.subtotal, order.shipping, order.total
+                );
+            }
+            for book in &plan.skipped {
+                println!(
+                    "-\t{}\t-\thttps://bookmeter.com/books/{}",
+                    book.title, book.bookmeter_id
+                );
+            }
+            println!("Total\t\t{}\t", plan.total);
+        }
+        Err(e) => {
+            error!("Failed to plan basket: {:?}", e);
+        }
+    }
+}
+
+/// 保存したスナップショットを操作し、終了コードを返す (再解析で失敗が残った場合も 1)
+fn snapshots() -> i32 {
+    let Some(archive) = SnapshotArchive::from_env() else {
+        error!("HTML_SNAPSHOT_DIR must be set");
+        return 1;
+    };
+    let files: Vec<PathBuf> = env::args().skip(3).map(PathBuf::from).collect();
+    let result = match env::args().nth(2).as_deref() {
+        None | Some("list") => print_snapshots(&archive),
+        Some("show") => {
+            let Some(file) = files.first() else {
+                error!("Usage: snapshots show <file>");
+                return 1;
+            };
+            Snapshot::load(file).map(|snapshot| {
+                println!("{}", snapshot.html);
+                true
+            })
+        }
+        Some("reparse") => reparse_snapshots(&archive, files),
+        Some(_) => {
+            error!("Usage: snapshots [list|show <file>|reparse [file...]]");
+            return 1;
+        }
+    };
+    match result {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(e) => {
+            error!("Failed to read snapshots: {:?}", e);
+            1
+        }
+    }
+}
+
+/// 保存したスナップショットを古い順に一覧表示する
+fn print_snapshots(archive: &SnapshotArchive) -> anyhow::Result<bool> {
+    println!("File\tCaptured At\tParser\tURL\tError");
+    for file in archive.files()? {
+        let snapshot = Snapshot::load(&file)?;
+        println!(
+            "{}\t{}\t{}\t{}\t{}",
+            file.display(),
+            snapshot.captured_at,
+            snapshot.parser,
+            snapshot.url,
+            snapshot.error.replace(['\t', '\n'], " ")
+        );
+    }
+    Ok(true)
+}
+
+/// 指定したスナップショット (省略時は全て) を現在のパーサーで解析し直し、全て成功したかを返す
+fn reparse_snapshots(archive: &SnapshotArchive, files: Vec<PathBuf>) -> anyhow::Result<bool> {
+    let files = if files.is_empty() {
+        archive.files()?
+    } else {
+        files
+    };
+    println!("Result\tFile\tParser\tDetail");
+    let mut all_ok = true;
+    for file in files {
+        let snapshot = Snapshot::load(&file)?;
+        match snapshot.reparse() {
+            Ok(summary) => println!("OK\t{}\t{}\t{summary}", file.display(), snapshot.parser),
+            Err(e) => {
+                all_ok = false;
+                println!(
+                    "ERR\t{}\t{}\t{}",
+                    file.display(),
+                    snapshot.parser,
+                    format!("{e:?}").replace(['\t', '\n'], " ")
+                );
+            }
+        }
+    }
+    Ok(all_ok)
+}
+
+/// 参照ページでパーサーを確認して結果を表示し、終了コードを返す
+async fn check_parsers() -> i32 {
+    let pages = match ReferencePages::from_env() {
+        Ok(pages) => pages,
+        Err(e) => {
+            error!("USER_ID must be set to a Bookmeter user ID: {:?}", e);
+            return 2;
+        }
+    };
+    let fetcher = match fetcher::from_env() {
+        Ok(fetcher) => fetcher,
+        Err(e) => {
+            error!("Failed to set up HTTP fetcher: {:?}", e);
+            return 2;
+        }
+    };
+    let checks = match parser_check::check_parsers(&pages, fetcher).await {
+        Ok(checks) => checks,
+        Err(e) => {
+            error!("Failed to check parsers: {:?}", e);
+            return 2;
+        }
+    };
+    println!("Source\tStatus\tURL\tDetail");
+    for check in &checks {
+        let detail = match &check.outcome {
+            CheckOutcome::Ok => String::new(),
+            CheckOutcome::Missing(fields) => fields.join(","),
+            CheckOutcome::Failed(e) => e.replace(['\t', '\n'], " "),
+        };
+        println!(
+            "{}\t{}\t{}\t{detail}",
+            check.source,
+            check.outcome.as_str(),
+            check.url
+        );
+    }
+    parser_check::exit_code(&checks)
+}
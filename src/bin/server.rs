@@ -1,11 +1,24 @@
 use std::env;
 use std::time::Duration;
 
-use axum::{routing::get, Json, Router};
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    routing::get,
+    Json, Router,
+};
+use bookmeter_discounts::basket::BasketPlan;
+use bookmeter_discounts::book_store_link::Model as BookStoreLink;
+use bookmeter_discounts::library_availability::Model as LibraryAvailability;
 use bookmeter_discounts::model::Model as Book;
+use bookmeter_discounts::recommendation::{ChannelRecommendation, RecommendationOptions};
+use bookmeter_discounts::run_report::Model as RunReport;
+use bookmeter_discounts::used_book::ConditionGrade;
+use bookmeter_discounts::used_book_offer::Model as UsedBookOffer;
 use bookmeter_discounts::BookMeterDiscounts;
 use futures::TryStreamExt;
-use sea_orm::{ConnectOptions, Database};
+use sea_orm::{ConnectOptions, Database, DatabaseConnection};
+use serde::{Deserialize, Serialize};
 use tokio::net::TcpListener;
 use tracing::info;
 use tracing_subscriber::EnvFilter;
@@ -22,7 +35,16 @@ async fn main() {
 
     info!("Starting server on 0.0.0.0:3000...");
 
-    let app = Router::new().route("/", get(get_books));
+    let app = Router::new()
+        .route("/", get(get_books))
+        .route("/books/{bookmeter_id}/store_links", get(get_store_links))
+        .route(
+            "/books/{bookmeter_id}/used_book_offers",
+            get(get_used_book_offers),
+        )
+        .route("/basket", get(get_basket))
+        .route("/recommendations", get(get_recommendations))
+        .route("/user_status", get(get_user_status));
     let listener = match TcpListener::bind("0.0.0.0:3000").await {
         Ok(l) => l,
         Err(e) => {
@@ -35,27 +57,74 @@ async fn main() {
     }
 }
 
-#[axum::debug_handler]
-async fn get_books() -> Json<Vec<Book>> {
-    let user_id = env::var("USER_ID").unwrap_or_default();
+/// `DATABASE_URL` の DB に接続する
+async fn connect_db() -> Option<DatabaseConnection> {
     let database_url = env::var("DATABASE_URL").unwrap_or_default();
 
     // DB接続にタイムアウトを設定
     let mut opt = ConnectOptions::new(&database_url);
     opt.connect_timeout(Duration::from_secs(10))
         .acquire_timeout(Duration::from_secs(10));
-    let db = match Database::connect(opt).await {
-        Ok(db) => db,
+    match Database::connect(opt).await {
+        Ok(db) => Some(db),
         Err(e) => {
             tracing::error!("Failed to connect to database: {e}");
-            return Json(Vec::new());
+            None
         }
+    }
+}
+
+/// 中古本オファーの絞り込み条件 (`?min_grade=very-good` など)
+#[derive(Debug, Deserialize)]
+struct GradeFilter {
+    min_grade: Option<ConditionGrade>,
+}
+
+/// 割引中の本と、その本の図書館の蔵書・貸出状況
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BookWithLibrary {
+    #[serde(flatten)]
+    book: Book,
+    library_availabilities: Vec<LibraryAvailability>,
+}
+
+#[axum::debug_handler]
+async fn get_books(Query(filter): Query<GradeFilter>) -> Json<Vec<BookWithLibrary>> {
+    let user_id = env::var("USER_ID").unwrap_or_default();
+    let Some(db) = connect_db().await else {
+        return Json(Vec::new());
     };
     let bookmeter_discounts_client = BookMeterDiscounts::new(&user_id, db, 0);
-    let stream_result = bookmeter_discounts_client.get_discounts(Some(100)).await;
+    let stream_result = bookmeter_discounts_client
+        .get_discounts(Some(100), filter.min_grade)
+        .await;
     match stream_result {
         Ok(stream) => match stream.try_collect::<Vec<_>>().await {
-            Ok(books) => Json(books),
+            Ok(books) => {
+                let ids: Vec<i64> = books.iter().map(|book| book.bookmeter_id).collect();
+                // 図書館の状況が取れなくても割引情報は返す
+                let availabilities = bookmeter_discounts_client
+                    .get_library_availabilities(&ids)
+                    .await
+                    .unwrap_or_else(|e| {
+                        tracing::error!("Failed to get library availabilities: {e:?}");
+                        Vec::new()
+                    });
+                Json(
+                    books
+                        .into_iter()
+                        .map(|book| BookWithLibrary {
+                            library_availabilities: availabilities
+                                .iter()
+                                .filter(|a| a.bookmeter_id == book.bookmeter_id)
+                                .cloned()
+                                .collect(),
+                            book,
+                        })
+                        .collect(),
+                )
+            }
             Err(e) => {
                 tracing::error!("Failed to collect books: {e:?}");
                 Json(Vec::new())
@@ -67,3 +136,165 @@ async fn get_books() -> Json<Vec<Book>> {
         }
     }
 }
+
+#[axum::debug_handler]
+async fn get_store_links(Path(bookmeter_id): Path<i64>) -> Json<Vec<BookStoreLink>> {
+    let user_id = env::var("USER_ID").unwrap_or_default();
+    let Some(db) = connect_db().await else {
+        return Json(Vec::new());
+    };
+    let bookmeter_discounts_client = BookMeterDiscounts::new(&user_id, db, 0);
+    match bookmeter_discounts_client
+        .get_store_links(bookmeter_id)
+        .await
+    {
+        Ok(links) => Json(links),
+        Err(e) => {
+            tracing::error!("Failed to get store links: {e:?}");
+            Json(Vec::new())
+        }
+    }
+}
+
+/// 中古本オファーと、残りわずか (最後の1冊など) かどうか
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UsedBookOfferWithStock {
+    #[serde(flatten)]
+    offer: UsedBookOffer,
+    low_stock: bool,
+}
+
+#[axum::debug_handler]
+async fn get_used_book_offers(
+    Path(bookmeter_id): Path<i64>,
+    Query(filter): Query<GradeFilter>,
+) -> Json<Vec<UsedBookOfferWithStock>> {
+    let user_id = env::var("USER_ID").unwrap_or_default();
+    let Some(db) = connect_db().await else {
+        return Json(Vec::new());
+    };
+    let bookmeter_discounts_client = BookMeterDiscounts::new(&user_id, db, 0);
+    match bookmeter_discounts_client
+        .get_used_book_offers(bookmeter_id, filter.min_grade)
+        .await
+    {
+        Ok(offers) => Json(
+            offers
+                .into_iter()
+                .map(|offer| UsedBookOfferWithStock {
+                    low_stock: offer.is_low_stock(),
+                    offer,
+                })
+                .collect(),
+        ),
+        Err(e) => {
+            tracing::error!("Failed to get used book offers: {e:?}");
+            Json(Vec::new())
+        }
+    }
+}
+
+/// まとめ買いの対象 (`?ids=123,456`。省略した場合は登録済みの全ての本)
+#[derive(Debug, Deserialize)]
+struct BasketQuery {
+    ids: Option<String>,
+}
+
+impl BasketQuery {
+    /// `ids` を本の ID の一覧にする (空の要素は無視する)
+    ///
+    /// 数値として読めない ID があればその ID を返す。全ての本の計画にならないよう、黙って捨てない。
+    fn bookmeter_ids(&self) -> Result<Vec<i64>, String> {
+        self.ids
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .map(|id| id.parse().map_err(|_| id.to_string()))
+            .collect()
+    }
+}
+
+/// 指定した本をまとめて買う最安の買い方を返す
+///
+/// `ids` に数値として読めない ID がある場合は 400 を返す。
+#[axum::debug_handler]
+async fn get_basket(
+    Query(query): Query<BasketQuery>,
+) -> Result<Json<Option<BasketPlan>>, (StatusCode, String)> {
+    let user_id = env::var("USER_ID").unwrap_or_default();
+    let bookmeter_ids = query
+        .bookmeter_ids()
+        .map_err(|id| (StatusCode::BAD_REQUEST, format!("invalid book id: {id}")))?;
+    let Some(db) = connect_db().await else {
+        return Ok(Json(None));
+    };
+    let bookmeter_discounts_client = BookMeterDiscounts::new(&user_id, db, 0);
+    match bookmeter_discounts_client.plan_basket(&bookmeter_ids).await {
+        Ok(plan) => Ok(Json(Some(plan))),
+        Err(e) => {
+            tracing::error!("Failed to plan basket: {e:?}");
+            Ok(Json(None))
+        }
+    }
+}
+
+/// 本ごとの最安の買い先と、2番目に安い買い先との差額を返す
+#[axum::debug_handler]
+async fn get_recommendations(
+    Query(options): Query<RecommendationOptions>,
+) -> Json<Vec<ChannelRecommendation>> {
+    let user_id = env::var("USER_ID").unwrap_or_default();
+    let Some(db) = connect_db().await else {
+        return Json(Vec::new());
+    };
+    let bookmeter_discounts_client = BookMeterDiscounts::new(&user_id, db, 0);
+    match bookmeter_discounts_client
+        .get_channel_recommendations(options)
+        .await
+    {
+        Ok(recommendations) => Json(recommendations),
+        Err(e) => {
+            tracing::error!("Failed to get recommendations: {e:?}");
+            Json(Vec::new())
+        }
+    }
+}
+
+/// 最新の実行結果 (ユーザーが存在しない・非公開などの状態) を返す
+#[axum::debug_handler]
+async fn get_user_status() -> Json<Option<RunReport>> {
+    let user_id = env::var("USER_ID").unwrap_or_default();
+    let Some(db) = connect_db().await else {
+        return Json(None);
+    };
+    let bookmeter_discounts_client = BookMeterDiscounts::new(&user_id, db, 0);
+    match bookmeter_discounts_client.get_latest_run_report().await {
+        Ok(report) => Json(report),
+        Err(e) => {
+            tracing::error!("Failed to get run report: {e:?}");
+            Json(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_basket_query_bookmeter_ids() {
+        let query = |ids: Option<&str>| BasketQuery {
+            ids: ids.map(str::to_string),
+        };
+        assert_eq!(query(None).bookmeter_ids(), Ok(Vec::new()));
+        assert_eq!(query(Some("123, 456,")).bookmeter_ids(), Ok(vec![123, 456]));
+        // 読めない ID を捨てると、全て読めない場合に全ての本の計画になってしまう
+        assert_eq!(
+            query(Some("123,abc")).bookmeter_ids(),
+            Err("abc".to_string())
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! パイプラインの段階ごとに対象にする書籍の形式 (`books.binding_name`) の設定
+
+use anyhow::Result;
+use sea_orm::{ColumnTrait, Condition};
+
+use crate::model;
+
+/// 対象にする書籍の形式
+///
+/// 環境変数 `{段階}_BINDING_NAMES` (カンマ区切り) を設定した場合はその形式だけを対象にし、
+/// `{段階}_EXCLUDED_BINDING_NAMES` (カンマ区切り。空文字列で除外なし) の形式を除外する。
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BindingNameFilter {
+    /// 対象にする形式 (None の場合は全ての形式)
+    pub included: Option<Vec<String>>,
+    /// 除外する形式
+    pub excluded: Vec<String>,
+    /// 形式が未取得 (NULL) の本も対象にするか
+    pub include_unknown: bool,
+}
+
+impl BindingNameFilter {
+    /// Kindle 版の取得 (段階名 `KINDLE`) の既定値: 全ての本
+    #[must_use]
+    pub fn kindle_default() -> Self {
+        Self {
+            included: None,
+            excluded: Vec::new(),
+            include_unknown: true,
+        }
+    }
+
+    /// Kindle 版の価格取得 (段階名 `PRICE`) の既定値: 全ての本
+    #[must_use]
+    pub fn price_default() -> Self {
+        Self::kindle_default()
+    }
+
+    /// 中古本オファーの取得 (段階名 `USED_BOOK`) の既定値: 漫画・ライトノベル以外の形式が分かっている本
+    #[must_use]
+    pub fn used_book_default() -> Self {
+        Self {
+            included: None,
+            excluded: vec!["コミック".to_string(), "ライトノベル".to_string()],
+            include_unknown: false,
+        }
+    }
+
+    /// 環境変数 `{stage}_BINDING_NAMES` / `{stage}_EXCLUDED_BINDING_NAMES` で既定値を上書きする
+    ///
+    /// # Errors
+    ///
+    /// 環境変数が UTF-8 でない場合にエラーを返す。
+    pub fn from_env(stage: &str, default: Self) -> Result<Self> {
+        let included = read_env(&format!("{stage}_BINDING_NAMES"))?;
+        let excluded = read_env(&format!("{stage}_EXCLUDED_BINDING_NAMES"))?;
+        Ok(default.with_values(included.as_deref(), excluded.as_deref()))
+    }
+
+    /// カンマ区切りの設定値で上書きする (None の項目は既定値のまま)
+    ///
+    /// 対象の形式を指定した場合、形式が未取得の本は対象外になる。
+    #[must_use]
+    pub fn with_values(mut self, included: Option<&str>, excluded: Option<&str>) -> Self {
+        if let Some(included) = included {
+            self.included = Some(split_names(included));
+            self.include_unknown = false;
+        }
+        if let Some(excluded) = excluded {
+            self.excluded = split_names(excluded);
+        }
+        self
+    }
+
+    /// `books` の絞り込み条件
+    #[must_use]
+    pub fn condition(&self) -> Condition {
+        if self.included.is_none() && self.excluded.is_empty() && self.include_unknown {
+            return Condition::all();
+        }
+        let mut known = Condition::all().add(model::Column::BindingName.is_not_null());
+        if let Some(included) = &self.included {
+            known = known.add(model::Column::BindingName.is_in(included.iter().cloned()));
+        }
+        if !self.excluded.is_empty() {
+            known = known.add(model::Column::BindingName.is_not_in(self.excluded.iter().cloned()));
+        }
+        if self.include_unknown {
+            Condition::any()
+                .add(known)
+                .add(model::Column::BindingName.is_null())
+        } else {
+            known
+        }
+    }
+}
+
+fn read_env(key: &str) -> Result<Option<String>> {
+    match std::env::var(key) {
+        Ok(v) => Ok(Some(v)),
+        Err(std::env::VarError::NotPresent) => Ok(None),
+        Err(e) => Err(anyhow::anyhow!("{key} is invalid: {e}")),
+    }
+}
+
+fn split_names(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sea_orm::{DbBackend, EntityTrait, QueryFilter, QueryTrait};
+
+    fn where_clause(filter: &BindingNameFilter) -> String {
+        let sql = model::Entity::find()
+            .filter(filter.condition())
+            .build(DbBackend::Postgres)
+            .to_string();
+        sql.split_once("WHERE ")
+            .map_or(String::new(), |(_, clause)| clause.to_string())
+    }
+
+    #[test]
+    fn test_used_book_default() {
+        assert_eq!(
+            where_clause(&BindingNameFilter::used_book_default()),
+            r#""books"."binding_name" IS NOT NULL AND "books"."binding_name" NOT IN ('コミック', 'ライトノベル')"#
+        );
+    }
+
+    #[test]
+    fn test_kindle_default_includes_unknown() {
+        assert_eq!(where_clause(&BindingNameFilter::kindle_default()), "TRUE");
+        let filter = BindingNameFilter::kindle_default().with_values(None, Some("雑誌"));
+        assert_eq!(
+            where_clause(&filter),
+            r#"("books"."binding_name" IS NOT NULL AND "books"."binding_name" NOT IN ('雑誌')) OR "books"."binding_name" IS NULL"#
+        );
+    }
+
+    #[test]
+    fn test_with_values() {
+        let filter =
+            BindingNameFilter::used_book_default().with_values(Some("コミック, 文庫"), Some(""));
+        assert_eq!(
+            filter,
+            BindingNameFilter {
+                included: Some(vec!["コミック".to_string(), "文庫".to_string()]),
+                excluded: Vec::new(),
+                include_unknown: false,
+            }
+        );
+        assert_eq!(
+            where_clause(&filter),
+            r#""books"."binding_name" IS NOT NULL AND "books"."binding_name" IN ('コミック', '文庫')"#
+        );
+    }
+
+    #[test]
+    fn test_with_values_exclude() {
+        let filter = BindingNameFilter::used_book_default().with_values(None, Some("雑誌,単行本"));
+        assert_eq!(filter.excluded, vec!["雑誌", "単行本"]);
+        assert_eq!(filter.included, None);
+    }
+}
@@ -0,0 +1,36 @@
+use sea_orm::{entity::prelude::*, ActiveValue::Set};
+use serde::{Deserialize, Serialize};
+
+use crate::bookmeter::ExternalBookStoreLink;
+
+/// 読書メーターが本ごとに案内している外部書店 (Amazon / 楽天ブックス / honto など) へのリンク
+#[derive(Clone, Debug, DeriveEntityModel, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[sea_orm(table_name = "book_store_links")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub bookmeter_id: i64,
+    /// 書店名 (`external_book_stores.json` の `name`。取れない場合は URL のホスト名)
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub store: String,
+    pub url: String,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+#[derive(Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModel {
+    /// 読書メーターから取得したリンクを `ActiveModel` に変換する
+    #[must_use]
+    pub fn from_link(bookmeter_id: i64, link: &ExternalBookStoreLink) -> Self {
+        ActiveModel {
+            bookmeter_id: Set(bookmeter_id),
+            store: Set(link.store.clone()),
+            url: Set(link.url.clone()),
+            updated_at: Set(chrono::Utc::now().naive_utc()),
+        }
+    }
+}
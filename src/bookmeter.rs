@@ -1,17 +1,149 @@
 use std::collections::BTreeSet;
+use std::env;
+use std::future::Future;
+use std::sync::Arc;
 use std::time::Duration;
 
+use crate::fetch_failure::{self, Entity as FetchFailure};
+use crate::fetcher::{FetchResponse, Fetcher, HttpStatusError, LiveFetcher};
+use crate::http_cache::HttpCache;
 use crate::model as Book;
 use anyhow::Result;
 use backon::{ExponentialBuilder, Retryable};
+use futures::{stream, StreamExt};
 use scraper::{Html, Selector};
-use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QuerySelect};
-use serde::Deserialize;
-use tokio::time::sleep;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, ModelTrait, QueryFilter,
+    QuerySelect,
+};
+use serde::{de::DeserializeOwned, Deserialize};
 use tracing::{info, warn};
 
 pub struct BookMeterClient {
     pub user_id: u32,
+    pub retry_policy: RetryPolicy,
+    /// 新しい本の詳細を同時に取得する件数の上限
+    pub concurrency: usize,
+    /// ページの取得方法 (本番では実際にリクエストを送り、テストでは記録済みのレスポンスを返す)
+    fetcher: Arc<dyn Fetcher>,
+    /// bookmeter.com へのリクエスト間隔を保つためのレートリミッター
+    rate_limiter: RateLimiter,
+}
+
+/// 同一ホストへのリクエストを一定間隔以上空けるためのレートリミッター
+///
+/// 並行に取得していても、リクエストの送信自体は `interval` ごとに1件までになる。
+struct RateLimiter {
+    interval: Duration,
+    next: tokio::sync::Mutex<tokio::time::Instant>,
+}
+
+impl RateLimiter {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            next: tokio::sync::Mutex::new(tokio::time::Instant::now()),
+        }
+    }
+
+    /// 前回のリクエストから `interval` 経つまで待つ
+    async fn acquire(&self) {
+        let mut next = self.next.lock().await;
+        tokio::time::sleep_until(*next).await;
+        *next = tokio::time::Instant::now() + self.interval;
+    }
+}
+
+/// 読書メーターへのリクエストのリトライ方針
+///
+/// 指数バックオフでリトライするが、回数と全体の期限で打ち切る。
+/// 404 / 410 は恒久的な失敗 (削除済みの本など) としてリトライしない。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// 最初のリクエストを除くリトライ回数の上限
+    pub max_times: usize,
+    /// 1回のリトライ待ち時間の上限
+    pub max_delay: Duration,
+    /// リトライ待ちを含めた1回の取得全体の期限
+    pub deadline: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_times: 5,
+            max_delay: Duration::from_mins(5),
+            deadline: Duration::from_mins(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// 環境変数 (`BOOKMETER_RETRY_MAX_TIMES` / `BOOKMETER_RETRY_MAX_DELAY_SECS` /
+    /// `BOOKMETER_RETRY_DEADLINE_SECS`) からリトライ方針を組み立てる
+    ///
+    /// 設定されていない値はデフォルト値を使う。
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a variable is set but is not a number.
+    pub fn from_env() -> Result<Self> {
+        let default = Self::default();
+        let max_times = match env::var("BOOKMETER_RETRY_MAX_TIMES") {
+            Ok(v) => v.parse()?,
+            Err(_) => default.max_times,
+        };
+        let max_delay = match env::var("BOOKMETER_RETRY_MAX_DELAY_SECS") {
+            Ok(v) => Duration::from_secs(v.parse()?),
+            Err(_) => default.max_delay,
+        };
+        let deadline = match env::var("BOOKMETER_RETRY_DEADLINE_SECS") {
+            Ok(v) => Duration::from_secs(v.parse()?),
+            Err(_) => default.deadline,
+        };
+        Ok(Self {
+            max_times,
+            max_delay,
+            deadline,
+        })
+    }
+
+    /// リトライ方針に従って `request` を実行する
+    ///
+    /// # Errors
+    ///
+    /// Returns the last error if the request keeps failing, fails permanently,
+    /// or the deadline is exceeded.
+    pub async fn run<T, F, Fut>(&self, request: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let backoff = ExponentialBuilder::default()
+            .with_max_delay(self.max_delay)
+            .with_max_times(self.max_times)
+            .with_total_delay(Some(self.deadline));
+        let retry = request
+            .retry(backoff)
+            .sleep(tokio::time::sleep)
+            .when(|e| !is_permanent_failure(e))
+            .notify(|e, dur| {
+                warn!("retrying after {:?} because {:?}", dur, e);
+            });
+        tokio::time::timeout(self.deadline, retry)
+            .await
+            .map_err(|_| anyhow::anyhow!("Bookmeter request timed out after {:?}", self.deadline))?
+    }
+}
+
+/// リトライしても結果が変わらない失敗 (HTTP 404 / 410) かどうか
+#[must_use]
+pub fn is_permanent_failure(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause
+            .downcast_ref::<HttpStatusError>()
+            .is_some_and(|e| e.status == 404 || e.status == 410)
+    })
 }
 
 #[derive(Clone, Debug)]
@@ -21,55 +153,81 @@ pub struct BookMeterBook {
     pub amazon_url: String,
     /// 書籍の形式 (コミック / ライトノベル / 文庫 / 新書 / 単行本 など)
     pub binding_name: Option<String>,
+    /// 著者名 (複数いる場合は最初の1人)
+    pub author: Option<String>,
+    /// 読書メーターが案内している外部書店へのリンク (Amazon を含む全書店)
+    pub store_links: Vec<ExternalBookStoreLink>,
+}
+
+/// 本ページから取得できる項目
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct BookPage {
+    pub title: String,
+    pub binding_name: Option<String>,
+    pub author: Option<String>,
+}
+
+/// 外部書店1件分のリンク
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExternalBookStoreLink {
+    /// 書店名 (Amazon / 楽天ブックス / honto / 紀伊國屋書店 など)
+    pub store: String,
+    pub url: String,
 }
 
 impl BookMeterBook {
     /// # Errors
     ///
     /// Returns an error if fetching the book title or Amazon URL fails.
-    pub async fn from_id(id: u32) -> Result<BookMeterBook> {
-        let doc = Self::get_book_page_with_retry(id).await?;
-        let html = Html::parse_document(&doc);
-        let title = Self::parse_title(&html, id)?;
-        let binding_name = Self::parse_binding_name(&html);
-        let amazon_url = Self::get_amazon_url(id).await?;
+    pub async fn from_id(client: &BookMeterClient, id: u32) -> Result<BookMeterBook> {
+        let doc = Self::get_book_page(client, id).await?;
+        let BookPage {
+            title,
+            binding_name,
+            author,
+        } = crate::snapshot::parse_or_record(
+            "bookmeter.book",
+            &format!("https://bookmeter.com/books/{id}"),
+            &doc,
+            |doc| Self::parse_page(doc, id),
+        )
+        .await?;
+        let store_links = Self::fetch_store_links(client, id).await?;
+        let amazon_url = Self::amazon_url_from_links(&store_links)
+            .ok_or_else(|| anyhow::anyhow!("Amazon URL not found"))?;
         Ok(BookMeterBook {
             id,
             title,
             amazon_url,
             binding_name,
+            author,
+            store_links,
         })
     }
 
+    /// 本ページをリトライ方針に従って取得する
+    ///
     /// # Errors
     ///
-    /// Returns an error if the HTTP request fails.
-    async fn get_book_page(id: u32) -> Result<String> {
-        let url = format!("https://bookmeter.com/books/{id}");
-        let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(30))
-            .build()?;
-        let doc = client.get(&url).send().await?.text().await?;
-        Ok(doc)
+    /// Returns an error if the HTTP request keeps failing or fails permanently.
+    pub(crate) async fn get_book_page(client: &BookMeterClient, id: u32) -> Result<String> {
+        client
+            .get_text(&format!("https://bookmeter.com/books/{id}"))
+            .await
     }
 
-    /// 本ページを指数バックオフでリトライしながら取得する
+    /// 本ページのHTMLからタイトル・形式・著者名を取得する
     ///
     /// # Errors
     ///
-    /// Returns an error if the HTTP request keeps failing.
-    async fn get_book_page_with_retry(id: u32) -> Result<String> {
-        { || Self::get_book_page(id) }
-            .retry(
-                ExponentialBuilder::default()
-                    .with_max_delay(Duration::from_hours(4))
-                    .without_max_times(),
-            )
-            .sleep(tokio::time::sleep)
-            .notify(|e, dur| {
-                warn!("retrying after {:?} because {:?}", dur, e);
-            })
-            .await
+    /// Returns an error if the title element is not found.
+    pub(crate) fn parse_page(doc: &str, id: u32) -> Result<BookPage> {
+        let html = Html::parse_document(doc);
+        Ok(BookPage {
+            title: Self::parse_title(&html, id)?,
+            binding_name: Self::parse_binding_name(&html),
+            author: Self::parse_author(&html),
+        })
     }
 
     /// # Errors
@@ -105,38 +263,90 @@ impl BookMeterBook {
             .filter(|name| !name.is_empty())
     }
 
-    /// 既存の本の形式だけを取得する (`binding_name` 未保持の本の補完用)
+    /// 本ページのHTMLから最初の著者名を取得する
+    ///
+    /// 著者の要素が見つからない場合や、内容が空の場合は `None` を返す。
+    #[must_use]
+    pub fn parse_author(html: &Html) -> Option<String> {
+        let selector = Selector::parse(".header__authors a").ok()?;
+        html.select(&selector)
+            .next()
+            .map(|e| e.text().collect::<String>().trim().to_string())
+            .filter(|author| !author.is_empty())
+    }
+
+    /// 既存の本の本ページを取得し直して解析する (`binding_name` / `author` 未保持の本の補完用)
     ///
     /// # Errors
     ///
-    /// Returns an error if the HTTP request fails after retries.
-    pub async fn fetch_binding_name(id: u32) -> Result<Option<String>> {
-        let doc = Self::get_book_page_with_retry(id).await?;
-        let html = Html::parse_document(&doc);
-        Ok(Self::parse_binding_name(&html))
+    /// Returns an error if the HTTP request fails after retries or the title is not found.
+    pub(crate) async fn fetch_page(client: &BookMeterClient, id: u32) -> Result<BookPage> {
+        let doc = Self::get_book_page(client, id).await?;
+        crate::snapshot::parse_or_record(
+            "bookmeter.book",
+            &format!("https://bookmeter.com/books/{id}"),
+            &doc,
+            |doc| Self::parse_page(doc, id),
+        )
+        .await
     }
 
+    /// 読書メーターが案内している外部書店へのリンクを全て取得する
+    ///
     /// # Errors
     ///
-    /// Returns an error if the HTTP request fails or no Amazon URL is found.
-    async fn get_amazon_url(id: u32) -> Result<String> {
-        let url = format!("https://bookmeter.com/api/v1/books/{id}/external_book_stores.json?");
-        let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(30))
-            .build()?;
-        let json: ExternalBookStores = client.get(&url).send().await?.json().await?;
-        for store in json.resources {
-            if store.url.contains("amazon") {
-                let url = store.url.trim().trim_matches('\'');
-                return Ok(url.to_string());
+    /// Returns an error if the HTTP request keeps failing or JSON decoding fails.
+    pub async fn fetch_store_links(
+        client: &BookMeterClient,
+        id: u32,
+    ) -> Result<Vec<ExternalBookStoreLink>> {
+        let json: ExternalBookStores = client
+            .get_json(&format!(
+                "https://bookmeter.com/api/v1/books/{id}/external_book_stores.json?"
+            ))
+            .await?;
+        Ok(Self::parse_store_links(json))
+    }
+
+    /// `external_book_stores.json` のレスポンスを書店名ごとのリンクに変換する
+    ///
+    /// URL が空のものや URL として解釈できないものは捨て、同じ書店が複数ある場合は最初のものを使う。
+    fn parse_store_links(json: ExternalBookStores) -> Vec<ExternalBookStoreLink> {
+        let mut links: Vec<ExternalBookStoreLink> = Vec::new();
+        for resource in json.resources {
+            let url = resource.url.trim().trim_matches('\'').to_string();
+            let Ok(parsed) = url::Url::parse(&url) else {
+                continue;
+            };
+            let store = match resource.name.as_deref().map(str::trim) {
+                Some(name) if !name.is_empty() => name.to_string(),
+                _ => match parsed.host_str() {
+                    Some(host) => host.to_string(),
+                    None => continue,
+                },
+            };
+            if links.iter().any(|link| link.store == store) {
+                continue;
             }
+            links.push(ExternalBookStoreLink { store, url });
         }
-        Err(anyhow::anyhow!("Amazon URL not found"))
+        links
+    }
+
+    /// 書店リンクのうち最初の Amazon の URL を返す
+    #[must_use]
+    pub fn amazon_url_from_links(links: &[ExternalBookStoreLink]) -> Option<String> {
+        links
+            .iter()
+            .find(|link| link.url.contains("amazon"))
+            .map(|link| link.url.clone())
     }
 }
 
 #[derive(Deserialize)]
 pub struct ExternalBookStore {
+    #[serde(default)]
+    name: Option<String>,
     url: String,
 }
 
@@ -146,34 +356,124 @@ pub struct ExternalBookStores {
 }
 
 impl BookMeterClient {
+    /// 同時取得数のデフォルト値
+    pub const DEFAULT_CONCURRENCY: usize = 4;
+    /// bookmeter.com へのリクエスト間隔のデフォルト値
+    pub const DEFAULT_REQUEST_INTERVAL: Duration = Duration::from_millis(500);
+
+    /// 実際にリクエストを送るクライアントを作る (取得方法は `with_fetcher` で差し替えられる)
     #[must_use]
-    pub fn new(user_id: u32) -> BookMeterClient {
-        BookMeterClient { user_id }
+    pub fn new(user_id: u32, retry_policy: RetryPolicy) -> BookMeterClient {
+        BookMeterClient {
+            user_id,
+            retry_policy,
+            concurrency: Self::DEFAULT_CONCURRENCY,
+            fetcher: Arc::new(LiveFetcher::new()),
+            rate_limiter: RateLimiter::new(Self::DEFAULT_REQUEST_INTERVAL),
+        }
+    }
+
+    /// 新しい本の詳細を同時に取得する件数の上限を設定する (0 は 1 として扱う)
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// ページの取得方法を差し替える
+    #[must_use]
+    pub fn with_fetcher(mut self, fetcher: Arc<dyn Fetcher>) -> Self {
+        self.fetcher = fetcher;
+        self
+    }
+
+    /// bookmeter.com へのリクエスト間隔を設定する
+    #[must_use]
+    pub fn with_request_interval(mut self, interval: Duration) -> Self {
+        self.rate_limiter = RateLimiter::new(interval);
+        self
+    }
+
+    /// 読書メーターのページをリトライ方針に従ってテキストで取得する
+    ///
+    /// HTTP キャッシュ (`HTTP_CACHE_DIR`) が有効な場合は TTL 内の保存済みの本文を使い、
+    /// 期限切れの場合は条件付きリクエストで確認する。
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the HTTP request keeps failing or fails permanently.
+    async fn get_text(&self, url: &str) -> Result<String> {
+        let Some(cache) = HttpCache::global() else {
+            return self
+                .retry_policy
+                .run(|| async { Ok(self.get(url).await?.body) })
+                .await;
+        };
+        if let Some(body) = cache.fresh(url).await {
+            return Ok(body);
+        }
+        self.retry_policy
+            .run(|| async {
+                self.rate_limiter.acquire().await;
+                cache.revalidate(self.fetcher.as_ref(), url).await
+            })
+            .await
+    }
+
+    /// 読書メーターの API をリトライ方針に従って JSON で取得する
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the HTTP request keeps failing, fails permanently or JSON decoding fails.
+    async fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T> {
+        Ok(serde_json::from_str(&self.get_text(url).await?)?)
+    }
+
+    /// 1回分の GET リクエスト (4xx / 5xx はエラーにする)
+    ///
+    /// 送信前にレートリミッターで前回のリクエストとの間隔を空ける。
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the HTTP request fails or the status is not successful.
+    async fn get(&self, url: &str) -> Result<FetchResponse> {
+        self.rate_limiter.acquire().await;
+        self.fetcher.get(url).await
     }
 
     /// 読書メーターのウィッシュリストにある本IDを全て取得する
     ///
     /// # Errors
     ///
-    /// Returns an error if fetching pages fails.
+    /// Returns an error if fetching pages fails or the first page has no books.
     pub async fn fetch_wishlist_ids(&self, max_page: u16) -> Result<BTreeSet<i64>> {
         let mut book_ids = BTreeSet::new();
         let mut page = 1;
         while page <= max_page {
             let html = self.get_book_page_html(page).await?;
-            let new_book_ids = BookMeterClient::get_book_ids_from_html(&html)?;
+            let new_book_ids = crate::snapshot::parse_or_record(
+                "bookmeter.wishlist",
+                &self.wishlist_url(page),
+                &html,
+                |html| BookMeterClient::parse_wishlist_page(html, page),
+            )
+            .await?;
             if new_book_ids.is_empty() {
                 break;
             }
             book_ids.extend(new_book_ids.into_iter().map(i64::from));
             page += 1;
-            sleep(Duration::from_secs(1)).await;
         }
         Ok(book_ids)
     }
 
     /// 与えられたIDのうちDBに未登録のものだけ詳細を取得する
     ///
+    /// 取得に失敗した本は `fetch_failures` に記録し、指数バックオフで決まる
+    /// 次回取得時刻 (`next_attempt_at`) を過ぎるまでスキップする。
+    /// 取得に成功した本は `fetch_failures` から削除する。
+    /// 詳細は最大 `concurrency` 件まで並行に取得する (リクエスト間隔はレートリミッターで保つ)。
+    ///
     /// # Errors
     ///
     /// Returns an error if querying the database fails or an ID overflows `u32`.
@@ -194,16 +494,58 @@ impl BookMeterClient {
             .await?
             .into_iter()
             .collect();
+        let backing_off: BTreeSet<i64> = FetchFailure::find()
+            .filter(fetch_failure::Column::BookmeterId.is_in(wishlist_ids.iter().copied()))
+            .filter(fetch_failure::Column::NextAttemptAt.gt(chrono::Utc::now().naive_utc()))
+            .select_only()
+            .column(fetch_failure::Column::BookmeterId)
+            .into_tuple::<i64>()
+            .all(db)
+            .await?
+            .into_iter()
+            .collect();
 
-        let mut book_results = Vec::new();
+        let mut targets = Vec::new();
         for &id in wishlist_ids.difference(&existing) {
-            let book_id = u32::try_from(id)?;
-            let book = BookMeterBook::from_id(book_id).await;
-            info!("got book_meter_book: {:?}", book);
-            book_results.push(book);
-            sleep(Duration::from_secs(1)).await;
+            if backing_off.contains(&id) {
+                info!("skip fetching book {id} until its next attempt time");
+                continue;
+            }
+            targets.push((id, u32::try_from(id)?));
         }
-        Ok(book_results.into_iter().filter_map(Result::ok).collect())
+        let book_results: Vec<(i64, Result<BookMeterBook>)> = stream::iter(targets)
+            .map(|(id, book_id)| async move {
+                let book = BookMeterBook::from_id(self, book_id).await;
+                info!("got book_meter_book: {:?}", book);
+                (id, book)
+            })
+            .buffer_unordered(self.concurrency)
+            .collect()
+            .await;
+
+        let mut books = Vec::new();
+        for (id, book) in book_results {
+            let failure = FetchFailure::find_by_id(id).one(db).await?;
+            match book {
+                Ok(book) => {
+                    if let Some(failure) = failure {
+                        failure.delete(db).await?;
+                    }
+                    books.push(book);
+                }
+                Err(e) => {
+                    warn!("failed to fetch book {id}: {e:?}");
+                    let is_new = failure.is_none();
+                    let active = fetch_failure::Model::record(failure, id, &e);
+                    if is_new {
+                        active.insert(db).await?;
+                    } else {
+                        active.update(db).await?;
+                    }
+                }
+            }
+        }
+        Ok(books)
     }
 
     /// 読書メーターの本IDをHTMLから取得する
@@ -213,7 +555,8 @@ impl BookMeterClient {
     /// # Errors
     ///
     /// Returns an error if the selector cannot be parsed or a book ID cannot be parsed.
-    fn get_book_ids_from_html(html: &Html) -> Result<BTreeSet<u32>> {
+    pub(crate) fn get_book_ids_from_html(html: &str) -> Result<BTreeSet<u32>> {
+        let html = Html::parse_document(html);
         let selector = Selector::parse(".detail__title > a")
             .map_err(|e| anyhow::anyhow!("Failed to parse selector: {e:?}"))?;
         let mut book_ids = BTreeSet::new();
@@ -232,23 +575,158 @@ impl BookMeterClient {
         Ok(book_ids)
     }
 
-    /// 読書メーターの読みたい本リストの指定したページのHTMLを取得する
+    /// 読みたい本リストの1ページ分の HTML から本 ID を取り出す
+    ///
+    /// 2ページ目以降が空の場合はリストの終わりとみなす。1ページ目が空でも、
+    /// 本文に「登録されている本はありません」のお知らせがあれば本が0冊のリストとして空を返す。
     ///
     /// # Errors
     ///
-    /// Returns an error if the HTTP request fails.
-    pub async fn get_book_page_html(&self, page: u16) -> Result<Html> {
-        let url: String = format!(
+    /// 1ページ目に本も空のリストのお知らせもない場合 (ページ構造の変更とみなす。放置すると
+    /// 全ての本がウィッシュリストから外れたものとして削除される) や、本のリンクを読めない場合に
+    /// エラーを返す。
+    pub fn parse_wishlist_page(html: &str, page: u16) -> Result<BTreeSet<u32>> {
+        let book_ids = Self::get_book_ids_from_html(html)?;
+        if page == 1
+            && book_ids.is_empty()
+            && !has_notice(&Html::parse_document(html), EMPTY_WISHLIST_NOTICE)
+        {
+            return Err(anyhow::anyhow!("No books found on the first wishlist page"));
+        }
+        Ok(book_ids)
+    }
+
+    /// 読みたい本リストの指定したページの URL
+    fn wishlist_url(&self, page: u16) -> String {
+        format!(
             "https://bookmeter.com/users/{}/books/wish?page={page}",
             self.user_id
-        );
-        let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(30))
-            .build()?;
-        let doc = client.get(&url).send().await?.text().await?;
-        let html = Html::parse_document(&doc);
-        Ok(html)
+        )
     }
+
+    /// 読書メーターの読みたい本リストの指定したページのHTMLを取得する
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`WishlistError`] if the user does not exist, is private or requires login,
+    /// or another error if the HTTP request keeps failing.
+    pub async fn get_book_page_html(&self, page: u16) -> Result<String> {
+        let url = self.wishlist_url(page);
+        let (final_url, doc) = self
+            .retry_policy
+            .run(|| async {
+                let response = self.get(&url).await?;
+                Ok((response.url, response.body))
+            })
+            .await
+            .map_err(|e| {
+                if is_permanent_failure(&e) {
+                    WishlistError::UserNotFound(self.user_id).into()
+                } else {
+                    e
+                }
+            })?;
+        check_wishlist_page(self.user_id, page, &final_url, &Html::parse_document(&doc))?;
+        Ok(doc)
+    }
+}
+
+/// 読みたい本リストを読めないユーザーを表すエラー
+///
+/// ページの取得自体は成功するため、放置すると「本が0冊のユーザー」として扱われてしまう。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WishlistError {
+    /// ユーザーが存在しない (退会済みなど。HTTP 404)
+    UserNotFound(u32),
+    /// 読書記録が非公開に設定されている
+    PrivateProfile(u32),
+    /// ログインページにリダイレクトされた
+    LoginRequired(u32),
+}
+
+impl WishlistError {
+    /// `run_reports.outcome` に記録する値
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WishlistError::UserNotFound(_) => "user_not_found",
+            WishlistError::PrivateProfile(_) => "private_profile",
+            WishlistError::LoginRequired(_) => "login_required",
+        }
+    }
+}
+
+impl std::fmt::Display for WishlistError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WishlistError::UserNotFound(user_id) => {
+                write!(f, "Bookmeter user {user_id} does not exist")
+            }
+            WishlistError::PrivateProfile(user_id) => {
+                write!(f, "Bookmeter user {user_id} has a private profile")
+            }
+            WishlistError::LoginRequired(user_id) => {
+                write!(f, "Bookmeter user {user_id} requires login to view")
+            }
+        }
+    }
+}
+
+impl std::error::Error for WishlistError {}
+
+/// 非公開ユーザーのページで本の一覧の代わりに表示される文言の一部
+const PRIVATE_PROFILE_NOTICE: &str = "非公開";
+
+/// 読みたい本が0冊のユーザーのページで本の一覧の代わりに表示される文言
+const EMPTY_WISHLIST_NOTICE: &str = "登録されている本はありません";
+
+/// 取得した読みたい本リストのページが、本当にそのユーザーのリストかどうかを確認する
+///
+/// 非公開かどうかは1ページ目だけで判定する。最後のページの次のページ (終端の確認用) は
+/// 本がなくて当然なので、メニューなどに「非公開」の文字があってもエラーにしない。
+///
+/// # Errors
+///
+/// Returns a [`WishlistError`] if the page is a login page or a private-profile notice.
+fn check_wishlist_page(
+    user_id: u32,
+    page: u16,
+    final_url: &str,
+    html: &Html,
+) -> Result<(), WishlistError> {
+    if url::Url::parse(final_url).is_ok_and(|url| url.path().starts_with("/login")) {
+        return Err(WishlistError::LoginRequired(user_id));
+    }
+    if page != 1 {
+        return Ok(());
+    }
+    let Ok(selector) = Selector::parse(".detail__title > a") else {
+        return Ok(());
+    };
+    if html.select(&selector).next().is_none() && has_notice(html, PRIVATE_PROFILE_NOTICE) {
+        return Err(WishlistError::PrivateProfile(user_id));
+    }
+    Ok(())
+}
+
+/// 本文中に `notice` を含むお知らせがあるかどうか
+///
+/// `<head>`・ヘッダー・ナビゲーション・フッター・リンクの中の文言 (「非公開設定について」の
+/// ようなメニューやヘルプへのリンク) は全ページに出るため数えない。
+fn has_notice(html: &Html, notice: &str) -> bool {
+    html.root_element().descendants().any(|node| {
+        node.value()
+            .as_text()
+            .is_some_and(|text| text.contains(notice))
+            && !node.ancestors().any(|ancestor| {
+                ancestor.value().as_element().is_some_and(|element| {
+                    matches!(
+                        element.name(),
+                        "head" | "header" | "nav" | "footer" | "a" | "script" | "noscript"
+                    )
+                })
+            })
+    })
 }
 
 #[cfg(test)]
@@ -309,6 +787,204 @@ mod tests {
         assert_eq!(parse_binding_name_from_fragment(fragment), None);
     }
 
+    #[test]
+    fn test_parse_author() {
+        let fragment = r#"
+            <ul class="header__authors">
+              <li><a href="/search?author=%E6%B1%90%E8%A6%8B%E5%A4%8F%E8%A1%9B">汐見夏衛</a></li>
+              <li><a href="/search?author=%E3%81%82%E3%82%8B%E4%BA%BA">ある人</a></li>
+            </ul>
+        "#;
+        let html = Html::parse_fragment(fragment);
+        assert_eq!(
+            BookMeterBook::parse_author(&html),
+            Some("汐見夏衛".to_string())
+        );
+        let html = Html::parse_fragment(r#"<ul class="header__authors"></ul>"#);
+        assert_eq!(BookMeterBook::parse_author(&html), None);
+    }
+
+    #[tokio::test]
+    async fn test_retry_policy_stops_after_max_times() {
+        let policy = RetryPolicy {
+            max_times: 2,
+            max_delay: Duration::from_millis(1),
+            deadline: Duration::from_secs(10),
+        };
+        let attempts = std::sync::atomic::AtomicUsize::new(0);
+        let result: Result<()> = policy
+            .run(|| async {
+                attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                Err(anyhow::anyhow!("temporary failure"))
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(std::sync::atomic::Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter_spaces_requests() {
+        let limiter = RateLimiter::new(Duration::from_millis(50));
+        let start = tokio::time::Instant::now();
+        futures::join!(limiter.acquire(), limiter.acquire(), limiter.acquire());
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn test_retry_policy_deadline() {
+        let policy = RetryPolicy {
+            max_times: usize::MAX,
+            max_delay: Duration::from_millis(1),
+            deadline: Duration::from_millis(50),
+        };
+        let result: Result<()> = policy
+            .run(|| async {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                Err(anyhow::anyhow!("temporary failure"))
+            })
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_check_wishlist_page() {
+        let wishlist = Html::parse_document(
+            r#"<ul><li><div class="detail__title"><a href="/books/123">本</a></div></li></ul>"#,
+        );
+        assert_eq!(
+            check_wishlist_page(
+                1,
+                1,
+                "https://bookmeter.com/users/1/books/wish?page=1",
+                &wishlist
+            ),
+            Ok(())
+        );
+
+        // 本が0冊のユーザーはエラーにしない
+        let empty =
+            Html::parse_document(r#"<div class="content">登録されている本はありません</div>"#);
+        assert_eq!(
+            check_wishlist_page(
+                1,
+                1,
+                "https://bookmeter.com/users/1/books/wish?page=1",
+                &empty
+            ),
+            Ok(())
+        );
+
+        let private = Html::parse_document(
+            r#"<div class="content">このユーザーは読書記録を非公開にしています</div>"#,
+        );
+        assert_eq!(
+            check_wishlist_page(
+                1,
+                1,
+                "https://bookmeter.com/users/1/books/wish?page=1",
+                &private
+            ),
+            Err(WishlistError::PrivateProfile(1))
+        );
+
+        let login = Html::parse_document(r#"<form action="/login"></form>"#);
+        assert_eq!(
+            check_wishlist_page(1, 1, "https://bookmeter.com/login", &login),
+            Err(WishlistError::LoginRequired(1))
+        );
+    }
+
+    #[test]
+    fn test_check_wishlist_page_ignores_menus_and_later_pages() {
+        let url = "https://bookmeter.com/users/1/books/wish?page=2";
+        // メニューやフッターのリンクにある「非公開」は非公開のお知らせではない
+        let empty_with_menus = Html::parse_document(
+            r#"<html><head><title>読みたい本 - 読書メーター</title></head><body>
+              <header><nav><a href="/settings/privacy">公開・非公開設定</a></nav></header>
+              <div class="content">登録されている本はありません</div>
+              <footer><a href="/help/private">非公開ユーザーについて</a></footer>
+            </body></html>"#,
+        );
+        assert_eq!(check_wishlist_page(1, 1, url, &empty_with_menus), Ok(()));
+
+        // 最後のページの次の空のページは、本文に「非公開」があっても1ページ目と同じ扱いにしない
+        let private = Html::parse_document(
+            r#"<div class="content">このユーザーは読書記録を非公開にしています</div>"#,
+        );
+        assert_eq!(check_wishlist_page(1, 2, url, &private), Ok(()));
+        assert_eq!(
+            check_wishlist_page(1, 1, url, &private),
+            Err(WishlistError::PrivateProfile(1))
+        );
+    }
+
+    #[test]
+    fn test_parse_wishlist_page() -> Result<()> {
+        let html = include_str!("../tests/fixtures/mock_sites/bookmeter_wishlist.html");
+        assert_eq!(
+            BookMeterClient::parse_wishlist_page(html, 1)?,
+            BTreeSet::from([999_999_801, 999_999_802])
+        );
+        // 2ページ目以降の空のページはリストの終わりだが、1ページ目が空なのは解析の失敗
+        let empty = "<html><body><div class=\"content\"></div></body></html>";
+        assert!(BookMeterClient::parse_wishlist_page(empty, 2)?.is_empty());
+        assert!(BookMeterClient::parse_wishlist_page(empty, 1).is_err());
+        // 空のリストのお知らせがあれば、本が0冊のユーザー
+        let no_books =
+            r#"<html><body><div class="content">登録されている本はありません</div></body></html>"#;
+        assert!(BookMeterClient::parse_wishlist_page(no_books, 1)?.is_empty());
+        // メニューのリンクの文言はお知らせとみなさない
+        let menu_only = r#"<html><body><nav><a href="/help">登録されている本はありませんか?</a></nav></body></html>"#;
+        assert!(BookMeterClient::parse_wishlist_page(menu_only, 1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_store_links() -> Result<()> {
+        let json: ExternalBookStores = serde_json::from_str(
+            r#"{"resources": [
+                {"name": "Amazon", "url": "'https://www.amazon.co.jp/dp/product/4167158054/ref=as_li_tf_tl'"},
+                {"name": "楽天ブックス", "url": "https://books.rakuten.co.jp/rb/1234/"},
+                {"name": "Amazon", "url": "https://www.amazon.co.jp/dp/B0CM5XVJ7Q"},
+                {"url": "https://honto.jp/netstore/pd-book_1234.html"},
+                {"name": "紀伊國屋書店", "url": ""}
+            ]}"#,
+        )?;
+        let links = BookMeterBook::parse_store_links(json);
+        assert_eq!(
+            links,
+            vec![
+                ExternalBookStoreLink {
+                    store: "Amazon".to_string(),
+                    url: "https://www.amazon.co.jp/dp/product/4167158054/ref=as_li_tf_tl"
+                        .to_string(),
+                },
+                ExternalBookStoreLink {
+                    store: "楽天ブックス".to_string(),
+                    url: "https://books.rakuten.co.jp/rb/1234/".to_string(),
+                },
+                ExternalBookStoreLink {
+                    store: "honto.jp".to_string(),
+                    url: "https://honto.jp/netstore/pd-book_1234.html".to_string(),
+                },
+            ]
+        );
+        assert_eq!(
+            BookMeterBook::amazon_url_from_links(&links),
+            Some("https://www.amazon.co.jp/dp/product/4167158054/ref=as_li_tf_tl".to_string())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_amazon_url_from_links_not_found() {
+        let links = vec![ExternalBookStoreLink {
+            store: "楽天ブックス".to_string(),
+            url: "https://books.rakuten.co.jp/rb/1234/".to_string(),
+        }];
+        assert_eq!(BookMeterBook::amazon_url_from_links(&links), None);
+    }
+
     #[test]
     fn test_parse_binding_name_empty() {
         // 要素はあるが内容が空の場合は未取得 (None) として扱う
@@ -0,0 +1,177 @@
+use sea_orm::{entity::prelude::*, ActiveValue::Set, IntoActiveModel};
+use serde::{Deserialize, Serialize};
+
+use crate::fetcher::HttpStatusError;
+
+/// 読書メーターから詳細を取得できなかった本 (次回以降に指数バックオフで再取得する)
+#[derive(Clone, Debug, DeriveEntityModel, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[sea_orm(table_name = "fetch_failures")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub bookmeter_id: i64,
+    /// `FetchErrorKind::as_str()` の値
+    pub error_kind: String,
+    /// 最後に発生したエラーの内容
+    pub last_error: String,
+    /// 連続で失敗した回数
+    pub attempts: i32,
+    /// この時刻を過ぎるまで再取得しない
+    pub next_attempt_at: chrono::NaiveDateTime,
+    pub first_failed_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+#[derive(Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+/// 取得失敗の種類
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FetchErrorKind {
+    /// 外部書店リンクに Amazon が含まれていない
+    AmazonUrlNotFound,
+    /// 本ページからタイトルが取れない (削除済みの本など)
+    TitleNotFound,
+    /// HTTP 404 / 410 (削除済みの本など、リトライしても取得できない)
+    NotFound,
+    /// HTTP リクエストの失敗
+    Http,
+    Other,
+}
+
+impl FetchErrorKind {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FetchErrorKind::AmazonUrlNotFound => "amazon_url_not_found",
+            FetchErrorKind::TitleNotFound => "title_not_found",
+            FetchErrorKind::NotFound => "not_found",
+            FetchErrorKind::Http => "http",
+            FetchErrorKind::Other => "other",
+        }
+    }
+
+    /// エラーの内容から失敗の種類を判定する
+    #[must_use]
+    pub fn classify(error: &anyhow::Error) -> Self {
+        if crate::bookmeter::is_permanent_failure(error) {
+            return FetchErrorKind::NotFound;
+        }
+        if error
+            .chain()
+            .any(|cause| cause.is::<reqwest::Error>() || cause.is::<HttpStatusError>())
+        {
+            return FetchErrorKind::Http;
+        }
+        let message = error.to_string();
+        if message.contains("Amazon URL not found") {
+            FetchErrorKind::AmazonUrlNotFound
+        } else if message.contains("title not found") {
+            FetchErrorKind::TitleNotFound
+        } else {
+            FetchErrorKind::Other
+        }
+    }
+}
+
+/// `attempts` 回連続で失敗した後、次の再取得までの待ち時間
+///
+/// 1時間から倍々で伸ばし、7日で頭打ちにする。
+#[must_use]
+pub fn backoff(attempts: i32) -> chrono::Duration {
+    const MAX_HOURS: i64 = 24 * 7;
+    let exponent = u32::try_from(attempts.saturating_sub(1))
+        .unwrap_or(0)
+        .min(16);
+    chrono::Duration::hours((1_i64 << exponent).min(MAX_HOURS))
+}
+
+impl Model {
+    /// 失敗を記録した新しい行、または既存の行の失敗回数を増やした `ActiveModel` を返す
+    #[must_use]
+    pub fn record(
+        existing: Option<Model>,
+        bookmeter_id: i64,
+        error: &anyhow::Error,
+    ) -> ActiveModel {
+        let now = chrono::Utc::now().naive_utc();
+        let (mut active, attempts) = match existing {
+            Some(model) => {
+                let attempts = model.attempts.saturating_add(1);
+                (model.into_active_model(), attempts)
+            }
+            None => (
+                ActiveModel {
+                    bookmeter_id: Set(bookmeter_id),
+                    first_failed_at: Set(now),
+                    ..Default::default()
+                },
+                1,
+            ),
+        };
+        active.error_kind = Set(FetchErrorKind::classify(error).as_str().to_string());
+        active.last_error = Set(format!("{error:?}"));
+        active.attempts = Set(attempts);
+        active.next_attempt_at = Set(now + backoff(attempts));
+        active.updated_at = Set(now);
+        active
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(1), chrono::Duration::hours(1));
+        assert_eq!(backoff(2), chrono::Duration::hours(2));
+        assert_eq!(backoff(5), chrono::Duration::hours(16));
+        assert_eq!(backoff(9), chrono::Duration::hours(24 * 7));
+        assert_eq!(backoff(1000), chrono::Duration::hours(24 * 7));
+        assert_eq!(backoff(0), chrono::Duration::hours(1));
+    }
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(
+            FetchErrorKind::classify(&anyhow::anyhow!("Amazon URL not found")),
+            FetchErrorKind::AmazonUrlNotFound
+        );
+        assert_eq!(
+            FetchErrorKind::classify(&anyhow::anyhow!("title not found: id=1")),
+            FetchErrorKind::TitleNotFound
+        );
+        assert_eq!(
+            FetchErrorKind::classify(&anyhow::anyhow!("something else")),
+            FetchErrorKind::Other
+        );
+    }
+
+    #[test]
+    fn test_record_increments_attempts() {
+        let error = anyhow::anyhow!("Amazon URL not found");
+        let first = Model::record(None, 1, &error);
+        assert_eq!(first.attempts, Set(1));
+        assert_eq!(
+            first.error_kind,
+            Set(FetchErrorKind::AmazonUrlNotFound.as_str().to_string())
+        );
+
+        let now = chrono::Utc::now().naive_utc();
+        let existing = Model {
+            bookmeter_id: 1,
+            error_kind: "other".to_string(),
+            last_error: "old".to_string(),
+            attempts: 3,
+            next_attempt_at: now,
+            first_failed_at: now,
+            updated_at: now,
+        };
+        let next = Model::record(Some(existing), 1, &error);
+        assert_eq!(next.attempts, Set(4));
+        assert_eq!(next.first_failed_at.try_as_ref(), Some(&now));
+    }
+}
@@ -0,0 +1,652 @@
+//! 読書メーター・Amazon・listasin・中古本サイトへの GET リクエストの送り先
+//!
+//! スクレイパーは全て [`Fetcher`] 経由でページを取得する。本番では [`LiveFetcher`] が実際に
+//! リクエストを送り、テストでは [`CassetteFetcher`] がディスクに保存したレスポンスを返すことで、
+//! パイプライン全体をネットワークなしで決まった結果になるように動かせる。
+//! [`RebasedFetcher`] はサイトごとのベース URL を差し替え、ローカルのスタブサーバーに向ける。
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use serde::{Deserialize, Serialize};
+
+use crate::kindle::Kindle;
+use crate::used_book::UsedBookStoreRegistry;
+
+/// GET リクエスト1件分
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FetchRequest {
+    pub url: String,
+    /// 条件付きリクエストの `If-None-Match`
+    pub if_none_match: Option<String>,
+    /// 条件付きリクエストの `If-Modified-Since`
+    pub if_modified_since: Option<String>,
+}
+
+impl FetchRequest {
+    /// 条件なしの GET リクエスト
+    #[must_use]
+    pub fn get(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            ..Self::default()
+        }
+    }
+}
+
+/// GET レスポンス1件分
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FetchResponse {
+    /// リダイレクト後の最終的な URL
+    pub url: String,
+    pub status: u16,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub body: String,
+}
+
+impl FetchResponse {
+    /// 本文だけを持つ 200 OK のレスポンス (リダイレクトなし)
+    #[must_use]
+    pub fn ok(url: &str, body: impl Into<String>) -> Self {
+        Self {
+            url: url.to_string(),
+            status: 200,
+            etag: None,
+            last_modified: None,
+            body: body.into(),
+        }
+    }
+
+    /// ステータスが 2xx かどうか
+    #[must_use]
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// ステータスが 2xx でない場合に [`HttpStatusError`] を返す
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`HttpStatusError`] if the status is not successful.
+    pub fn error_for_status(self) -> Result<Self> {
+        if self.is_success() {
+            Ok(self)
+        } else {
+            Err(HttpStatusError {
+                url: self.url,
+                status: self.status,
+            }
+            .into())
+        }
+    }
+}
+
+/// 4xx / 5xx などの成功でないステータスを表すエラー
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HttpStatusError {
+    pub url: String,
+    pub status: u16,
+}
+
+impl std::fmt::Display for HttpStatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HTTP status {} for {}", self.status, self.url)
+    }
+}
+
+impl std::error::Error for HttpStatusError {}
+
+/// ページの取得方法
+pub trait Fetcher: Send + Sync {
+    /// GET してレスポンスを返す
+    ///
+    /// ステータスが成功でなくてもエラーにはしない (呼び出し側で `error_for_status` を使う)。
+    /// リクエストを送れなかった場合はエラーを返す。
+    fn fetch<'a>(&'a self, request: &'a FetchRequest) -> BoxFuture<'a, Result<FetchResponse>>;
+}
+
+impl dyn Fetcher + '_ {
+    /// 条件なしで GET し、ステータスが成功のレスポンスを返す
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the status is not successful.
+    pub async fn get(&self, url: &str) -> Result<FetchResponse> {
+        self.fetch(&FetchRequest::get(url))
+            .await?
+            .error_for_status()
+    }
+
+    /// 条件なしで GET し、ステータスが成功の本文を返す
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the status is not successful.
+    pub async fn get_text(&self, url: &str) -> Result<String> {
+        Ok(self.get(url).await?.body)
+    }
+}
+
+/// 実際にリクエストを送る取得方法
+///
+/// Amazon の商品ページだけは `get-amazon-html.sh` (curl) で取得する。
+#[derive(Clone, Debug)]
+pub struct LiveFetcher {
+    client: reqwest::Client,
+}
+
+impl LiveFetcher {
+    /// ブラウザ UA を名乗らないと 403 を返すサイトがあるため、`get-amazon-html.sh` と同様の UA を付ける
+    const USER_AGENT: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/130.0.0.0 Safari/537.36";
+
+    /// 全リクエストで使い回す (コネクションプールを共有する) HTTP クライアントを組み立てる
+    ///
+    /// クライアントの設定に失敗した場合は警告を出して既定の設定のクライアントを使う。
+    #[must_use]
+    pub fn new() -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .user_agent(Self::USER_AGENT)
+            .default_headers({
+                let mut headers = reqwest::header::HeaderMap::new();
+                headers.insert(
+                    reqwest::header::ACCEPT_LANGUAGE,
+                    reqwest::header::HeaderValue::from_static("ja"),
+                );
+                headers
+            })
+            .build()
+            .unwrap_or_else(|e| {
+                tracing::warn!("failed to build HTTP client, using the default one: {e:?}");
+                reqwest::Client::default()
+            });
+        Self { client }
+    }
+
+    async fn send(&self, request: &FetchRequest) -> Result<FetchResponse> {
+        if is_amazon_url(&request.url) {
+            let id = Kindle::convert_amazon_url_to_id(&request.url)?;
+            let html = Kindle::get_html_by_amazon_id(&id).await?;
+            return Ok(FetchResponse::ok(&request.url, html));
+        }
+        let mut builder = self.client.get(&request.url);
+        if let Some(etag) = &request.if_none_match {
+            builder = builder.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &request.if_modified_since {
+            builder = builder.header(IF_MODIFIED_SINCE, last_modified);
+        }
+        let response = builder.send().await?;
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v: &reqwest::header::HeaderValue| v.to_str().ok())
+                .map(str::to_string)
+        };
+        let etag = header(ETAG);
+        let last_modified = header(LAST_MODIFIED);
+        Ok(FetchResponse {
+            url: response.url().to_string(),
+            status: response.status().as_u16(),
+            etag,
+            last_modified,
+            body: response.text().await?,
+        })
+    }
+}
+
+impl Default for LiveFetcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Fetcher for LiveFetcher {
+    fn fetch<'a>(&'a self, request: &'a FetchRequest) -> BoxFuture<'a, Result<FetchResponse>> {
+        Box::pin(self.send(request))
+    }
+}
+
+/// `get-amazon-html.sh` で取得する Amazon の商品ページかどうか
+fn is_amazon_url(url: &str) -> bool {
+    url::Url::parse(url).is_ok_and(|url| url.host_str() == Some("www.amazon.co.jp"))
+}
+
+/// レスポンスをディスクに記録し、記録したレスポンスを返す取得方法
+///
+/// URL ごとに1ファイル (`{URL のハッシュ}.json`) を保存する。記録したファイルは
+/// `url` / `status` / `body` を書き換えてテスト用に編集できる。
+#[derive(Clone)]
+pub struct CassetteFetcher {
+    dir: PathBuf,
+    /// 記録時に実際に取得する取得方法 (None の場合は記録済みのレスポンスだけを返す)
+    recorder: Option<Arc<dyn Fetcher>>,
+}
+
+impl CassetteFetcher {
+    /// 記録済みのレスポンスだけを返す (記録がない URL はエラー)
+    #[must_use]
+    pub fn replay(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            recorder: None,
+        }
+    }
+
+    /// `inner` で取得したレスポンスを記録してから返す
+    #[must_use]
+    pub fn record(dir: impl Into<PathBuf>, inner: Arc<dyn Fetcher>) -> Self {
+        Self {
+            dir: dir.into(),
+            recorder: Some(inner),
+        }
+    }
+
+    /// `url` のレスポンスとして `response` を記録する (テスト用のカセットを組み立てる場合など)
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be created or the file cannot be written.
+    pub async fn insert(&self, url: &str, response: &FetchResponse) -> Result<()> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let entry = CassetteEntry {
+            request_url: url.to_string(),
+            response: response.clone(),
+        };
+        tokio::fs::write(self.path_for(url), serde_json::to_vec_pretty(&entry)?).await?;
+        Ok(())
+    }
+
+    fn path_for(&self, url: &str) -> PathBuf {
+        self.dir.join(format!(
+            "{:016x}.json",
+            crate::http_cache::fnv1a(url.as_bytes())
+        ))
+    }
+
+    async fn load(&self, url: &str) -> Result<FetchResponse> {
+        let path = self.path_for(url);
+        let bytes = tokio::fs::read(&path).await.map_err(|e| {
+            anyhow::anyhow!("no recorded response for {url} ({}): {e}", path.display())
+        })?;
+        let entry: CassetteEntry = serde_json::from_slice(&bytes)?;
+        if entry.request_url != url {
+            return Err(anyhow::anyhow!(
+                "recorded response in {} is for {}, not {url}",
+                path.display(),
+                entry.request_url
+            ));
+        }
+        Ok(entry.response)
+    }
+
+    async fn send(&self, request: &FetchRequest) -> Result<FetchResponse> {
+        let Some(inner) = &self.recorder else {
+            return self.load(&request.url).await;
+        };
+        // 304 を記録しないよう、記録時は条件を付けずに取得する
+        let response = inner.fetch(&FetchRequest::get(&request.url)).await?;
+        self.insert(&request.url, &response).await?;
+        Ok(response)
+    }
+}
+
+impl Fetcher for CassetteFetcher {
+    fn fetch<'a>(&'a self, request: &'a FetchRequest) -> BoxFuture<'a, Result<FetchResponse>> {
+        Box::pin(self.send(request))
+    }
+}
+
+/// カセットのファイル1件分
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CassetteEntry {
+    request_url: String,
+    response: FetchResponse,
+}
+
+/// 中古本サイト以外でスクレイパーがページを取得するサイト
+///
+/// 中古本サイトのベース URL は各サイトの実装 ([`crate::used_book::UsedBookStore::default_base_url`]) が持つ。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Site {
+    Bookmeter,
+    Amazon,
+    Listasin,
+}
+
+impl Site {
+    pub const ALL: [Self; 3] = [Self::Bookmeter, Self::Amazon, Self::Listasin];
+
+    /// 本番のベース URL (末尾の `/` なし)
+    #[must_use]
+    pub const fn default_base_url(self) -> &'static str {
+        match self {
+            Self::Bookmeter => "https://bookmeter.com",
+            Self::Amazon => "https://www.amazon.co.jp",
+            Self::Listasin => "https://www.listasin.net",
+        }
+    }
+
+    /// ベース URL を上書きする環境変数
+    #[must_use]
+    pub const fn env_var(self) -> &'static str {
+        match self {
+            Self::Bookmeter => "BOOKMETER_BASE_URL",
+            Self::Amazon => "AMAZON_BASE_URL",
+            Self::Listasin => "LISTASIN_BASE_URL",
+        }
+    }
+}
+
+/// サイトごとのベース URL を差し替えてから `inner` で取得する取得方法
+///
+/// スクレイパーは本番の URL を組み立てたまま、リクエストだけを別のサーバー
+/// (テスト用のスタブサーバーなど) に送る。レスポンスの URL (リダイレクト後) は本番の
+/// ベース URL に戻すので、スクレイパーや DB からは本番と同じ URL に見える。
+#[derive(Clone)]
+pub struct RebasedFetcher {
+    inner: Arc<dyn Fetcher>,
+    /// 本番のベース URL と差し替え先のベース URL
+    base_urls: Vec<(String, String)>,
+}
+
+impl RebasedFetcher {
+    /// ベース URL を差し替えない状態で組み立てる
+    #[must_use]
+    pub fn new(inner: Arc<dyn Fetcher>) -> Self {
+        Self {
+            inner,
+            base_urls: Vec::new(),
+        }
+    }
+
+    /// 本番のベース URL `default_base_url` へのリクエストを `base_url`
+    /// (`http://127.0.0.1:8080/bookmeter` のようにパスを含めてもよい) に送る
+    #[must_use]
+    pub fn with_base_url(mut self, default_base_url: &str, base_url: &str) -> Self {
+        let default_base_url = default_base_url.trim_end_matches('/');
+        self.base_urls.retain(|(from, _)| from != default_base_url);
+        self.base_urls.push((
+            default_base_url.to_string(),
+            base_url.trim_end_matches('/').to_string(),
+        ));
+        self
+    }
+
+    /// 環境変数で上書きされたベース URL を使う
+    ///
+    /// [`Site`] は `BOOKMETER_BASE_URL` などの [`Site::env_var`]、中古本サイトは
+    /// [`crate::used_book::UsedBookStore::base_url_env_var`] (既定では `BOOKOFF_BASE_URL` のような `{サイトキー}_BASE_URL`) を読む。
+    #[must_use]
+    pub fn from_env(inner: Arc<dyn Fetcher>) -> Self {
+        let stores = UsedBookStoreRegistry::all();
+        Site::ALL
+            .into_iter()
+            .map(|site| (site.default_base_url(), site.env_var().to_string()))
+            .chain(
+                stores
+                    .iter()
+                    .map(|store| (store.default_base_url(), store.base_url_env_var())),
+            )
+            .fold(
+                Self::new(inner),
+                |fetcher, (default_base_url, env_var)| match std::env::var(&env_var) {
+                    Ok(base_url) if !base_url.is_empty() => {
+                        fetcher.with_base_url(default_base_url, &base_url)
+                    }
+                    _ => fetcher,
+                },
+            )
+    }
+
+    /// 差し替えるベース URL が1つもないかどうか
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.base_urls.is_empty()
+    }
+
+    /// 本番の URL を差し替え先の URL にする
+    fn rebase(&self, url: &str) -> String {
+        self.base_urls
+            .iter()
+            .find_map(|(default_base_url, base_url)| replace_base(url, default_base_url, base_url))
+            .unwrap_or_else(|| url.to_string())
+    }
+
+    /// 差し替え先の URL を本番の URL に戻す
+    fn restore(&self, url: &str) -> String {
+        self.base_urls
+            .iter()
+            .find_map(|(default_base_url, base_url)| replace_base(url, base_url, default_base_url))
+            .unwrap_or_else(|| url.to_string())
+    }
+
+    async fn send(&self, request: &FetchRequest) -> Result<FetchResponse> {
+        let rebased = FetchRequest {
+            url: self.rebase(&request.url),
+            ..request.clone()
+        };
+        let mut response = self.inner.fetch(&rebased).await?;
+        response.url = self.restore(&response.url);
+        Ok(response)
+    }
+}
+
+impl Fetcher for RebasedFetcher {
+    fn fetch<'a>(&'a self, request: &'a FetchRequest) -> BoxFuture<'a, Result<FetchResponse>> {
+        Box::pin(self.send(request))
+    }
+}
+
+/// `url` が `from` で始まる場合に `to` に置き換える (`https://bookmeter.community` などは置き換えない)
+fn replace_base(url: &str, from: &str, to: &str) -> Option<String> {
+    let rest = url.strip_prefix(from)?;
+    (rest.is_empty() || rest.starts_with(['/', '?', '#'])).then(|| format!("{to}{rest}"))
+}
+
+/// 環境変数から取得方法を組み立てる
+///
+/// - `HTTP_CASSETTE_DIR`: カセットの保存先 (未設定の場合は実際にリクエストを送る)
+/// - `HTTP_CASSETTE_MODE`: `replay` (既定値。記録済みのレスポンスだけを返す) または
+///   `record` (実際に取得して記録する)
+/// - `{SITE}_BASE_URL` (`BOOKMETER_BASE_URL` など、[`RebasedFetcher::from_env`]): サイトのベース URL を
+///   差し替える (スタブサーバーに向ける場合など)
+///
+/// # Errors
+///
+/// Returns an error if `HTTP_CASSETTE_MODE` is invalid.
+pub fn from_env() -> Result<Arc<dyn Fetcher>> {
+    let inner = storage_from_env()?;
+    let rebased = RebasedFetcher::from_env(Arc::clone(&inner));
+    Ok(if rebased.is_empty() {
+        inner
+    } else {
+        Arc::new(rebased)
+    })
+}
+
+/// 実際に送るか記録済みのレスポンスを使うかを環境変数から決める
+fn storage_from_env() -> Result<Arc<dyn Fetcher>> {
+    let Ok(dir) = std::env::var("HTTP_CASSETTE_DIR") else {
+        return Ok(Arc::new(LiveFetcher::new()));
+    };
+    match std::env::var("HTTP_CASSETTE_MODE").as_deref() {
+        Err(_) | Ok("replay") => Ok(Arc::new(CassetteFetcher::replay(dir))),
+        Ok("record") => Ok(Arc::new(CassetteFetcher::record(
+            dir,
+            Arc::new(LiveFetcher::new()),
+        ))),
+        Ok(mode) => Err(anyhow::anyhow!(
+            "HTTP_CASSETTE_MODE must be replay or record: {mode}"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::used_book::UsedBookStore;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// URL を本文にして返し、`/missing` には 404 を返す取得方法
+    #[derive(Default)]
+    struct StubFetcher {
+        requests: AtomicUsize,
+    }
+
+    impl Fetcher for StubFetcher {
+        fn fetch<'a>(&'a self, request: &'a FetchRequest) -> BoxFuture<'a, Result<FetchResponse>> {
+            Box::pin(async move {
+                self.requests.fetch_add(1, Ordering::SeqCst);
+                // 記録時は条件を付けずに取得する
+                assert_eq!(request.if_none_match, None);
+                let mut response =
+                    FetchResponse::ok(&request.url, format!("body of {}", request.url));
+                if request.url.ends_with("/missing") {
+                    response.status = 404;
+                }
+                Ok(response)
+            })
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "bookmeter_discounts_cassette_{name}_{}",
+            std::process::id()
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_cassette_record_and_replay() -> Result<()> {
+        let dir = temp_dir("record");
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+        let stub = Arc::new(StubFetcher::default());
+        let url = "https://example.com/page";
+
+        let recorder = CassetteFetcher::record(&dir, Arc::clone(&stub) as Arc<dyn Fetcher>);
+        let conditional = FetchRequest {
+            url: url.to_string(),
+            if_none_match: Some("\"v1\"".to_string()),
+            if_modified_since: None,
+        };
+        let response = recorder.fetch(&conditional).await?;
+        assert_eq!(response.body, "body of https://example.com/page");
+        let missing = (&recorder as &dyn Fetcher)
+            .get("https://example.com/missing")
+            .await;
+        assert_eq!(
+            missing
+                .err()
+                .and_then(|e| e.downcast::<HttpStatusError>().ok())
+                .map(|e| e.status),
+            Some(404)
+        );
+        assert_eq!(stub.requests.load(Ordering::SeqCst), 2);
+
+        // 再生時は取得せずに記録済みのレスポンス (404 も含む) を返す
+        let player: &dyn Fetcher = &CassetteFetcher::replay(&dir);
+        assert_eq!(player.get_text(url).await?, response.body);
+        assert!(player.get("https://example.com/missing").await.is_err());
+        assert!(player.get("https://example.com/unrecorded").await.is_err());
+        assert_eq!(stub.requests.load(Ordering::SeqCst), 2);
+
+        tokio::fs::remove_dir_all(&dir).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cassette_insert() -> Result<()> {
+        let dir = temp_dir("insert");
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+        let cassette = CassetteFetcher::replay(&dir);
+        let url = "https://bookmeter.com/users/1/books/wish?page=1";
+        let mut redirected = FetchResponse::ok("https://bookmeter.com/login", "<html></html>");
+        redirected.etag = Some("\"v1\"".to_string());
+        cassette.insert(url, &redirected).await?;
+        assert_eq!((&cassette as &dyn Fetcher).get(url).await?, redirected);
+        tokio::fs::remove_dir_all(&dir).await?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_http_status_error_is_permanent_failure() {
+        let not_found = FetchResponse {
+            status: 404,
+            ..FetchResponse::ok("https://bookmeter.com/books/1", "")
+        };
+        let error = not_found.error_for_status().err();
+        assert!(error
+            .as_ref()
+            .is_some_and(crate::bookmeter::is_permanent_failure));
+        let unavailable = FetchResponse {
+            status: 503,
+            ..FetchResponse::ok("https://bookmeter.com/books/1", "")
+        };
+        let error = unavailable.error_for_status().err();
+        assert!(error
+            .as_ref()
+            .is_some_and(|e| !crate::bookmeter::is_permanent_failure(e)));
+    }
+
+    #[tokio::test]
+    async fn test_rebased_fetcher() -> Result<()> {
+        let stub = Arc::new(StubFetcher::default());
+        let fetcher = RebasedFetcher::new(Arc::clone(&stub) as Arc<dyn Fetcher>)
+            .with_base_url(
+                Site::Bookmeter.default_base_url(),
+                "http://127.0.0.1:8080/bookmeter/",
+            )
+            .with_base_url(
+                crate::used_book::bookoff::Bookoff.default_base_url(),
+                "http://127.0.0.1:8080/bookoff",
+            );
+        let fetcher: &dyn Fetcher = &fetcher;
+
+        // リクエストは差し替え先に送り、レスポンスの URL は本番の URL に戻す
+        let response = fetcher
+            .get("https://bookmeter.com/users/1/books/wish?page=1")
+            .await?;
+        assert_eq!(
+            response.body,
+            "body of http://127.0.0.1:8080/bookmeter/users/1/books/wish?page=1"
+        );
+        assert_eq!(
+            response.url,
+            "https://bookmeter.com/users/1/books/wish?page=1"
+        );
+        let response = fetcher
+            .get("https://shopping.bookoff.co.jp/used/0019117467")
+            .await?;
+        assert_eq!(
+            response.body,
+            "body of http://127.0.0.1:8080/bookoff/used/0019117467"
+        );
+
+        // 差し替えていないサイトやホスト名が前方一致するだけの URL はそのまま
+        for url in [
+            "https://www.netoff.co.jp/detail/0012822282/",
+            "https://bookmeter.community/books/1",
+        ] {
+            assert_eq!(fetcher.get_text(url).await?, format!("body of {url}"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_amazon_url() {
+        assert!(is_amazon_url("https://www.amazon.co.jp/dp/4088843142"));
+        assert!(!is_amazon_url(
+            "https://www.listasin.net/kndlsl/asins/B0DJB4QN8R"
+        ));
+        assert!(!is_amazon_url("not a url"));
+    }
+}
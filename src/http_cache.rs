@@ -0,0 +1,397 @@
+//! 読書メーター・listasin・中古本サイトへの GET レスポンスのディスクキャッシュ
+//!
+//! 環境変数 `HTTP_CACHE_DIR` を設定した場合だけ有効になる。
+//! ホストごとの有効期間 (TTL) 内はリクエストを送らずに保存済みの本文を返し、
+//! 期限切れの場合は `ETag` / `Last-Modified` を付けた条件付きリクエストを送って、
+//! 304 Not Modified なら保存済みの本文を使う。
+//! [`always_revalidate`] の中では TTL に関わらず毎回サーバーに確認する (パーサーの確認用)。
+
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::fetcher::{FetchRequest, Fetcher};
+
+/// 304 Not Modified
+const NOT_MODIFIED: u16 = 304;
+
+tokio::task_local! {
+    /// TTL 内の保存済みの本文を使わず、毎回条件付きリクエストで確認するか
+    static ALWAYS_REVALIDATE: bool;
+}
+
+/// `future` の中のキャッシュ経由の取得で、TTL 内でも必ずサーバーに確認する
+///
+/// 304 Not Modified なら保存済みの本文を使うので、返る本文は常にサーバーの最新のもの。
+pub async fn always_revalidate<F: Future>(future: F) -> F::Output {
+    ALWAYS_REVALIDATE.scope(true, future).await
+}
+
+/// 保存済みのレスポンス1件分
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CacheEntry {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    /// 最後にサーバーに確認した時刻 (UNIX 秒)
+    checked_at: i64,
+    body: String,
+}
+
+/// GET レスポンスのディスクキャッシュ
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HttpCache {
+    dir: PathBuf,
+    /// ホストごとの TTL (ホスト名またはそのサブドメインに一致)
+    host_ttls: Vec<(String, Duration)>,
+    /// `host_ttls` に一致しないホストの TTL
+    default_ttl: Duration,
+}
+
+impl HttpCache {
+    /// `dir` に保存するキャッシュを作る (TTL は 0 = 毎回条件付きリクエストで確認)
+    #[must_use]
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            host_ttls: Vec::new(),
+            default_ttl: Duration::ZERO,
+        }
+    }
+
+    /// ホスト (とそのサブドメイン) の TTL を設定する
+    #[must_use]
+    pub fn with_host_ttl(mut self, host: &str, ttl: Duration) -> Self {
+        self.host_ttls.push((host.to_ascii_lowercase(), ttl));
+        self
+    }
+
+    /// 設定のないホストの TTL を設定する
+    #[must_use]
+    pub fn with_default_ttl(mut self, ttl: Duration) -> Self {
+        self.default_ttl = ttl;
+        self
+    }
+
+    /// 環境変数からキャッシュを組み立てる
+    ///
+    /// - `HTTP_CACHE_DIR`: 保存先ディレクトリ (未設定の場合はキャッシュしない)
+    /// - `HTTP_CACHE_TTLS`: ホストごとの TTL 秒 (例: `bookmeter.com=86400,www.listasin.net=3600`)
+    /// - `HTTP_CACHE_DEFAULT_TTL_SECS`: その他のホストの TTL 秒 (既定値 0)
+    ///
+    /// # Errors
+    ///
+    /// TTL の設定が数値でない、または `ホスト=秒` の形式でない場合にエラーを返す。
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(dir) = std::env::var("HTTP_CACHE_DIR") else {
+            return Ok(None);
+        };
+        let mut cache = Self::new(dir);
+        if let Ok(v) = std::env::var("HTTP_CACHE_DEFAULT_TTL_SECS") {
+            cache = cache.with_default_ttl(Duration::from_secs(v.parse()?));
+        }
+        if let Ok(v) = std::env::var("HTTP_CACHE_TTLS") {
+            for (host, ttl) in parse_host_ttls(&v)? {
+                cache = cache.with_host_ttl(&host, ttl);
+            }
+        }
+        Ok(Some(cache))
+    }
+
+    /// 環境変数から1度だけ組み立てた、プロセス全体で共有するキャッシュ
+    ///
+    /// 設定が不正な場合は警告を出してキャッシュしない。
+    pub fn global() -> Option<&'static Self> {
+        static GLOBAL: OnceLock<Option<HttpCache>> = OnceLock::new();
+        GLOBAL
+            .get_or_init(|| {
+                Self::from_env().unwrap_or_else(|e| {
+                    tracing::warn!("HTTP cache is disabled because of invalid settings: {e:?}");
+                    None
+                })
+            })
+            .as_ref()
+    }
+
+    /// URL のホストに適用する TTL
+    #[must_use]
+    pub fn ttl_for(&self, url: &str) -> Duration {
+        let host = url::Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_ascii_lowercase));
+        let Some(host) = host else {
+            return self.default_ttl;
+        };
+        self.host_ttls
+            .iter()
+            .find(|(configured, _)| {
+                host == *configured
+                    || host
+                        .strip_suffix(configured.as_str())
+                        .is_some_and(|prefix| prefix.ends_with('.'))
+            })
+            .map_or(self.default_ttl, |(_, ttl)| *ttl)
+    }
+
+    /// TTL 内の保存済みの本文 (期限切れまたは未保存の場合、[`always_revalidate`] の中では None)
+    pub async fn fresh(&self, url: &str) -> Option<String> {
+        if ALWAYS_REVALIDATE
+            .try_with(|always| *always)
+            .unwrap_or(false)
+        {
+            return None;
+        }
+        let entry = self.load(url).await?;
+        let age = chrono::Utc::now().timestamp() - entry.checked_at;
+        let ttl = i64::try_from(self.ttl_for(url).as_secs()).unwrap_or(i64::MAX);
+        (age >= 0 && age < ttl).then_some(entry.body)
+    }
+
+    /// TTL 内なら保存済みの本文を返し、期限切れなら条件付きリクエストで取得する
+    ///
+    /// # Errors
+    ///
+    /// HTTP リクエストが失敗した場合、またはステータスが 304 / 2xx 以外の場合にエラーを返す。
+    pub async fn get_text(&self, fetcher: &dyn Fetcher, url: &str) -> Result<String> {
+        match self.fresh(url).await {
+            Some(body) => Ok(body),
+            None => self.revalidate(fetcher, url).await,
+        }
+    }
+
+    /// 保存済みの `ETag` / `Last-Modified` を付けてリクエストし、結果を保存する
+    ///
+    /// TTL に関わらず必ずリクエストを1回送る。
+    ///
+    /// # Errors
+    ///
+    /// HTTP リクエストが失敗した場合、またはステータスが 304 / 2xx 以外の場合にエラーを返す。
+    pub async fn revalidate(&self, fetcher: &dyn Fetcher, url: &str) -> Result<String> {
+        let cached = self.load(url).await;
+        let request = FetchRequest {
+            url: url.to_string(),
+            if_none_match: cached.as_ref().and_then(|entry| entry.etag.clone()),
+            if_modified_since: cached
+                .as_ref()
+                .and_then(|entry| entry.last_modified.clone()),
+        };
+        let response = fetcher.fetch(&request).await?;
+        let now = chrono::Utc::now().timestamp();
+        if response.status == NOT_MODIFIED {
+            if let Some(mut entry) = cached {
+                entry.checked_at = now;
+                self.store(&entry).await;
+                return Ok(entry.body);
+            }
+            // 条件を付けていないのに 304 が返ることはないが、念のため条件なしで取り直す
+            return fetcher.get_text(url).await;
+        }
+        let response = response.error_for_status()?;
+        self.store(&CacheEntry {
+            url: url.to_string(),
+            etag: response.etag,
+            last_modified: response.last_modified,
+            checked_at: now,
+            body: response.body.clone(),
+        })
+        .await;
+        Ok(response.body)
+    }
+
+    fn path_for(&self, url: &str) -> PathBuf {
+        self.dir
+            .join(format!("{:016x}.json", fnv1a(url.as_bytes())))
+    }
+
+    /// 保存済みのレスポンス (読めない場合や別の URL の場合は None)
+    async fn load(&self, url: &str) -> Option<CacheEntry> {
+        let bytes = tokio::fs::read(self.path_for(url)).await.ok()?;
+        serde_json::from_slice::<CacheEntry>(&bytes)
+            .ok()
+            .filter(|entry| entry.url == url)
+    }
+
+    /// レスポンスを保存する (保存に失敗しても取得自体は成功させるため警告だけ出す)
+    async fn store(&self, entry: &CacheEntry) {
+        let result = async {
+            tokio::fs::create_dir_all(&self.dir).await?;
+            let path = self.path_for(&entry.url);
+            // 書き込み途中のファイルを読まないように、一時ファイルに書いてから置き換える
+            let tmp = path.with_extension("json.tmp");
+            tokio::fs::write(&tmp, serde_json::to_vec(entry)?).await?;
+            tokio::fs::rename(&tmp, &path).await?;
+            anyhow::Ok(())
+        }
+        .await;
+        if let Err(e) = result {
+            tracing::warn!("failed to store HTTP cache for {}: {e:?}", entry.url);
+        }
+    }
+}
+
+/// 共有キャッシュが有効ならキャッシュ経由で、無効なら直接 GET して本文を取得する
+///
+/// # Errors
+///
+/// HTTP リクエストが失敗した場合、またはステータスが成功でない場合にエラーを返す。
+pub async fn get_text(fetcher: &dyn Fetcher, url: &str) -> Result<String> {
+    match HttpCache::global() {
+        Some(cache) => cache.get_text(fetcher, url).await,
+        None => fetcher.get_text(url).await,
+    }
+}
+
+/// `ホスト=秒` のカンマ区切りを読む
+fn parse_host_ttls(value: &str) -> Result<Vec<(String, Duration)>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| {
+            let (host, secs) = item
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("HTTP_CACHE_TTLS item must be host=secs: {item}"))?;
+            Ok((
+                host.trim().to_string(),
+                Duration::from_secs(secs.trim().parse()?),
+            ))
+        })
+        .collect()
+}
+
+/// キャッシュのファイル名に使う URL のハッシュ (64bit FNV-1a。Rust のバージョンによらず同じ値になる)
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+    bytes.iter().fold(OFFSET, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(PRIME)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "bookmeter_discounts_http_cache_{name}_{}",
+            std::process::id()
+        ))
+    }
+
+    #[test]
+    fn test_parse_host_ttls() -> Result<()> {
+        assert_eq!(
+            parse_host_ttls("bookmeter.com=86400, www.listasin.net=3600,")?,
+            vec![
+                ("bookmeter.com".to_string(), Duration::from_hours(24)),
+                ("www.listasin.net".to_string(), Duration::from_hours(1)),
+            ]
+        );
+        assert!(parse_host_ttls("bookmeter.com").is_err());
+        assert!(parse_host_ttls("bookmeter.com=day").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_ttl_for() {
+        let cache = HttpCache::new(temp_dir("ttl"))
+            .with_host_ttl("bookmeter.com", Duration::from_mins(1))
+            .with_default_ttl(Duration::from_secs(5));
+        assert_eq!(
+            cache.ttl_for("https://bookmeter.com/books/1"),
+            Duration::from_mins(1)
+        );
+        assert_eq!(
+            cache.ttl_for("https://api.bookmeter.com/books/1"),
+            Duration::from_mins(1)
+        );
+        assert_eq!(
+            cache.ttl_for("https://notbookmeter.com/books/1"),
+            Duration::from_secs(5)
+        );
+        assert_eq!(cache.ttl_for("not a url"), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_fnv1a_is_stable() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    /// `ETag` を返し、一致する `If-None-Match` には 304 を返すモックサーバー
+    async fn spawn_mock_server() -> Result<(String, Arc<AtomicUsize>, Arc<AtomicUsize>)> {
+        use axum::{http::HeaderMap, http::StatusCode, routing::get, Router};
+
+        let requests = Arc::new(AtomicUsize::new(0));
+        let not_modified = Arc::new(AtomicUsize::new(0));
+        let (requests_counter, not_modified_counter) =
+            (Arc::clone(&requests), Arc::clone(&not_modified));
+        let app = Router::new().route(
+            "/page",
+            get(move |headers: HeaderMap| {
+                let (requests, not_modified) = (
+                    Arc::clone(&requests_counter),
+                    Arc::clone(&not_modified_counter),
+                );
+                async move {
+                    requests.fetch_add(1, Ordering::SeqCst);
+                    if headers.get("if-none-match").and_then(|v| v.to_str().ok()) == Some("\"v1\"")
+                    {
+                        not_modified.fetch_add(1, Ordering::SeqCst);
+                        return (
+                            StatusCode::NOT_MODIFIED,
+                            [("etag", "\"v1\"")],
+                            String::new(),
+                        );
+                    }
+                    (
+                        StatusCode::OK,
+                        [("etag", "\"v1\"")],
+                        "<p>body</p>".to_string(),
+                    )
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        Ok((format!("http://{addr}/page"), requests, not_modified))
+    }
+
+    #[tokio::test]
+    async fn test_get_text_uses_conditional_requests() -> Result<()> {
+        let (url, requests, not_modified) = spawn_mock_server().await?;
+        let dir = temp_dir("conditional");
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+        let fetcher = crate::fetcher::LiveFetcher::new();
+
+        // TTL 0: 毎回条件付きリクエストを送り、2回目は 304 で保存済みの本文を使う
+        let cache = HttpCache::new(&dir);
+        assert_eq!(cache.get_text(&fetcher, &url).await?, "<p>body</p>");
+        assert_eq!(cache.get_text(&fetcher, &url).await?, "<p>body</p>");
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert_eq!(not_modified.load(Ordering::SeqCst), 1);
+
+        // TTL 内はリクエストを送らない
+        let cache = cache.with_host_ttl("127.0.0.1", Duration::from_hours(1));
+        assert_eq!(cache.get_text(&fetcher, &url).await?, "<p>body</p>");
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        // always_revalidate の中では TTL 内でも確認する
+        let body = always_revalidate(cache.get_text(&fetcher, &url)).await?;
+        assert_eq!(body, "<p>body</p>");
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+        assert_eq!(not_modified.load(Ordering::SeqCst), 2);
+
+        tokio::fs::remove_dir_all(&dir).await?;
+        Ok(())
+    }
+}
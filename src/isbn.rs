@@ -2,6 +2,7 @@
 //!
 //! 中古本サイトの検索には ISBN-13 (JAN) を使うため、
 //! Amazon の紙書籍 ASIN (= ISBN-10) を変換する。
+//! 中古本サイトの商品ページに書かれた ISBN の照合にも使う。
 
 use anyhow::Result;
 
@@ -41,6 +42,22 @@ pub fn isbn10_to_isbn13(isbn10: &str) -> Result<String> {
     Ok(format!("{body12}{check}"))
 }
 
+/// 商品ページに書かれた ISBN (ハイフン付き・ISBN-10 を含む) を ISBN-13 に揃える
+///
+/// ISBN として読めない場合は `None` を返す。
+#[must_use]
+pub fn normalize_isbn13(raw: &str) -> Option<String> {
+    let compact: String = raw
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == 'X')
+        .collect();
+    match compact.len() {
+        13 if compact.chars().all(|c| c.is_ascii_digit()) => Some(compact),
+        10 => isbn10_to_isbn13(&compact).ok(),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -56,6 +73,20 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_normalize_isbn13() {
+        assert_eq!(
+            normalize_isbn13("978-4-8137-0518-5").as_deref(),
+            Some("9784813705185")
+        );
+        assert_eq!(
+            normalize_isbn13("4813705189").as_deref(),
+            Some("9784813705185")
+        );
+        assert_eq!(normalize_isbn13("B0DJB4QN8R"), None);
+        assert_eq!(normalize_isbn13(""), None);
+    }
+
     #[test]
     fn test_isbn10_to_isbn13_invalid() {
         // Kindle ASIN などは変換できない
@@ -5,9 +5,14 @@ use scraper::{Html, Selector};
 use tokio::{process::Command, time::sleep};
 use url::Url;
 
+use crate::fetcher::Fetcher;
+
+#[derive(Debug)]
 pub struct Kindle {
     pub basis_price: u32,
     pub price: u32,
+    /// 還元ポイント
+    pub point: u32,
     pub discount_rate: f32,
 }
 
@@ -17,6 +22,25 @@ pub struct KindleEdition {
     pub is_kindle_unlimited: bool,
 }
 
+/// 読書メーターの Amazon URL が指す商品ページ (通常は紙書籍)
+pub struct AmazonPage {
+    /// URL から取り出した ASIN
+    pub id: String,
+    pub url: String,
+    pub html: String,
+}
+
+impl AmazonPage {
+    /// `商品ページからKindle IDとKindle Unlimited対象かどうかを取得する`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the Kindle button is not found or the Kindle URL is invalid.
+    pub fn kindle_edition(&self) -> Result<KindleEdition> {
+        Kindle::parse_kindle_edition(&self.html, &self.id, &self.url)
+    }
+}
+
 impl Kindle {
     /// `AmazonのURLからIDを取得する`
     ///
@@ -39,15 +63,21 @@ impl Kindle {
         }
     }
 
-    /// `AmazonのURLからKindle IDとKindle Unlimited対象かどうかを取得する`
+    /// `AmazonのURLから商品ページのHTMLを取得する`
     ///
     /// # Errors
     ///
-    /// Returns an error if the URL is invalid or the Kindle button is not found.
-    pub async fn convert_amazon_url_to_kindle_id(url: &str) -> Result<KindleEdition> {
+    /// Returns an error if the URL is invalid or fetching HTML fails.
+    pub async fn fetch_amazon_page(fetcher: &dyn Fetcher, url: &str) -> Result<AmazonPage> {
         let id = Self::convert_amazon_url_to_id(url.trim_matches('\''))?;
-        let doc = Kindle::get_html_by_amazon_id(&id).await?;
-        Self::parse_kindle_edition(&doc, &id, url)
+        let html = fetcher
+            .get_text(&format!("https://www.amazon.co.jp/dp/{id}"))
+            .await?;
+        Ok(AmazonPage {
+            id,
+            url: url.to_string(),
+            html,
+        })
     }
 
     /// `Amazon商品ページのHTMLからKindle IDとKindle Unlimited対象かどうかを取得する`
@@ -55,7 +85,7 @@ impl Kindle {
     /// # Errors
     ///
     /// Returns an error if the Kindle button is not found or the Kindle URL is invalid.
-    fn parse_kindle_edition(doc: &str, id: &str, url: &str) -> Result<KindleEdition> {
+    pub(crate) fn parse_kindle_edition(doc: &str, id: &str, url: &str) -> Result<KindleEdition> {
         let html = Html::parse_document(doc);
         let swatch_selector = Selector::parse("#tmm-grid-swatch-KINDLE")
             .map_err(|e| anyhow::anyhow!("Failed to parse selector: {e:?}"))?;
@@ -118,20 +148,22 @@ impl Kindle {
     /// # Errors
     ///
     /// Returns an error if the price or Kindle data cannot be fetched.
-    pub async fn from_id(kindle_id: &str) -> Result<Self> {
-        let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(30))
-            .build()?;
-        let doc = client
-            .get(format!(
-                "https://www.listasin.net/kndlsl/asins/{}",
-                kindle_id.trim_matches('\'')
-            ))
-            .send()
-            .await?
-            .text()
-            .await?;
-        let html = Html::parse_document(&doc);
+    pub async fn from_id(fetcher: &dyn Fetcher, kindle_id: &str) -> Result<Self> {
+        let url = format!(
+            "https://www.listasin.net/kndlsl/asins/{}",
+            kindle_id.trim_matches('\'')
+        );
+        let doc = crate::http_cache::get_text(fetcher, &url).await?;
+        crate::snapshot::parse_or_record("listasin.price", &url, &doc, Self::parse_listasin).await
+    }
+
+    /// `listasinのページのHTMLから価格・基本価格・還元ポイントを取得する`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the price is not found or the basis price is not a number.
+    pub(crate) fn parse_listasin(doc: &str) -> Result<Self> {
+        let html = Html::parse_document(doc);
 
         // 値段の取得
         let price_selector = Selector::parse(".item-price > span")
@@ -174,6 +206,7 @@ impl Kindle {
         Ok(Kindle {
             basis_price,
             price,
+            point,
             discount_rate,
         })
     }
@@ -1,31 +1,66 @@
-use std::{env, sync::Arc, time::Duration};
+use std::{
+    collections::{BTreeSet, HashMap},
+    env,
+    sync::Arc,
+    time::Duration,
+};
 
 use anyhow::Result;
-use bookmeter::{BookMeterBook, BookMeterClient};
+use binding_filter::BindingNameFilter;
+pub use bookmeter::WishlistError;
+use bookmeter::{BookMeterBook, BookMeterClient, RetryPolicy};
+use fetcher::{Fetcher, LiveFetcher};
 use tracing::{error, info};
 
+pub mod basket;
+pub mod binding_filter;
+pub mod book_store_link;
 mod bookmeter;
+pub mod fetch_failure;
+pub mod fetcher;
+mod http_cache;
 mod isbn;
 mod kindle;
+pub mod library;
+pub mod library_availability;
 mod metrics;
 pub mod model;
+pub mod parser_check;
+pub mod recommendation;
+pub mod run_report;
+pub mod shipping_fee;
+pub mod snapshot;
 pub mod used_book;
 pub mod used_book_offer;
+pub mod used_book_offer_condition;
+pub mod used_book_override;
+use book_store_link::Entity as BookStoreLink;
+use fetch_failure::Entity as FetchFailure;
 use futures::{Stream, TryStreamExt};
 use kindle::Kindle;
+use library_availability::Entity as LibraryAvailability;
 use model::Entity as Book;
+use run_report::Entity as RunReport;
 use sea_orm::{
-    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, ExprTrait, IntoActiveModel,
-    QueryFilter, QueryOrder, QuerySelect, Set,
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, EntityTrait,
+    ExprTrait, IntoActiveModel, QueryFilter, QueryOrder, QuerySelect, QueryTrait, Set,
+    TransactionTrait,
 };
+use shipping_fee::Entity as ShippingFee;
 use tokio::time::sleep;
-use used_book::UsedBookSite;
+use used_book::{ConditionGrade, UsedBookStore, UsedBookStoreRegistry};
 use used_book_offer::Entity as UsedBookOffer;
+use used_book_offer_condition::Entity as UsedBookOfferCondition;
+use used_book_override::{Entity as UsedBookOverride, UsedBookLookup};
 
 pub struct BookMeterDiscounts {
     pub user_id: String,
     pub db: DatabaseConnection,
     pub get_amazon_page_interval: u64,
+    /// 中古本オファーを取得するサイト (`USED_BOOK_SITES` で有効なサイトを選ぶ)
+    pub used_book_stores: UsedBookStoreRegistry,
+    /// ページの取得方法 (`HTTP_CASSETTE_DIR` で記録済みのレスポンスを使う)
+    pub fetcher: Arc<dyn Fetcher>,
     metrics: Arc<metrics::MetricsCollector>,
 }
 
@@ -33,14 +68,27 @@ impl BookMeterDiscounts {
     #[must_use]
     pub fn new(user_id: &str, db: DatabaseConnection, get_amazon_page_interval: u64) -> Self {
         let metrics = metrics::MetricsCollector::new();
+        let fetcher = fetcher::from_env().unwrap_or_else(|e| {
+            error!("invalid HTTP cassette settings, sending live requests: {e:?}");
+            Arc::new(LiveFetcher::new())
+        });
         Self {
             user_id: user_id.to_string(),
             db,
             get_amazon_page_interval,
+            used_book_stores: UsedBookStoreRegistry::from_env(),
+            fetcher,
             metrics,
         }
     }
 
+    /// ページの取得方法を差し替える (テストで記録済みのレスポンスを使う場合など)
+    #[must_use]
+    pub fn with_fetcher(mut self, fetcher: Arc<dyn Fetcher>) -> Self {
+        self.fetcher = fetcher;
+        self
+    }
+
     /// # Errors
     ///
     /// Returns an error if updating or fetching discounts fails.
@@ -48,7 +96,49 @@ impl BookMeterDiscounts {
         &self,
     ) -> Result<impl Stream<Item = Result<model::Model>> + '_> {
         self.update_discounts().await?;
-        self.get_discounts(Some(10)).await
+        self.get_discounts(Some(10), None).await
+    }
+
+    /// 本情報を更新し、実行結果を `run_reports` に記録する
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any database or network operation fails.
+    /// Returns a [`WishlistError`] if the user does not exist, is private or requires login.
+    pub async fn update_discounts(&self) -> Result<()> {
+        let started_at = chrono::Utc::now().naive_utc();
+        let result = self.run_update_discounts().await;
+        let outcome = match &result {
+            Ok(()) => "ok",
+            Err(e) => e
+                .downcast_ref::<WishlistError>()
+                .map_or("error", |e| e.as_str()),
+        };
+        let report = run_report::ActiveModel {
+            user_id: Set(self.user_id.clone()),
+            outcome: Set(outcome.to_string()),
+            error: Set(result.as_ref().err().map(|e| format!("{e:?}"))),
+            started_at: Set(started_at),
+            finished_at: Set(chrono::Utc::now().naive_utc()),
+            ..Default::default()
+        };
+        if let Err(e) = RunReport::insert(report).exec(&self.db).await {
+            error!("failed to save run report: {:?}", e);
+        }
+        result
+    }
+
+    /// 最新の実行結果を取得する (まだ一度も実行していない場合は `None`)
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub async fn get_latest_run_report(&self) -> Result<Option<run_report::Model>> {
+        Ok(RunReport::find()
+            .filter(run_report::Column::UserId.eq(self.user_id.as_str()))
+            .order_by_desc(run_report::Column::FinishedAt)
+            .one(&self.db)
+            .await?)
     }
 
     /// # Errors
@@ -60,22 +150,45 @@ impl BookMeterDiscounts {
     /// This function does not panic.
     #[expect(
         clippy::too_many_lines,
-        reason = "sequential update steps (fetch, delete, kindle id, price, binding name, used book offers) read clearest inline"
+        reason = "sequential update steps (fetch, delete, kindle id, price, binding name, store links, used book offers) read clearest inline"
     )]
-    pub async fn update_discounts(&self) -> Result<()> {
+    async fn run_update_discounts(&self) -> Result<()> {
         // 読書メーターから本情報の取得
         let max_page = env::var("MAX_PAGE")
             .unwrap_or_else(|_| "1".to_string())
             .parse()?;
-        let bookmeter_client = BookMeterClient::new(self.user_id.parse()?);
+        let concurrency = env::var("BOOKMETER_CONCURRENCY")
+            .map_or(Ok(BookMeterClient::DEFAULT_CONCURRENCY), |v| v.parse())?;
+        let request_interval = env::var("BOOKMETER_REQUEST_INTERVAL_MILLIS")
+            .map_or(Ok(BookMeterClient::DEFAULT_REQUEST_INTERVAL), |v| {
+                v.parse().map(Duration::from_millis)
+            })?;
+        let bookmeter_client =
+            BookMeterClient::new(self.user_id.parse()?, RetryPolicy::from_env()?)
+                .with_concurrency(concurrency)
+                .with_request_interval(request_interval)
+                .with_fetcher(Arc::clone(&self.fetcher));
+        // 段階ごとに対象にする書籍の形式
+        let kindle_filter =
+            BindingNameFilter::from_env("KINDLE", BindingNameFilter::kindle_default())?;
+        let price_filter =
+            BindingNameFilter::from_env("PRICE", BindingNameFilter::price_default())?;
+        let used_book_filter =
+            BindingNameFilter::from_env("USED_BOOK", BindingNameFilter::used_book_default())?;
         let wishlist_ids = bookmeter_client.fetch_wishlist_ids(max_page).await?;
         let new_books = bookmeter_client
             .fetch_new_books(&wishlist_ids, &self.db)
             .await?;
         for bookmeter_book in new_books {
+            let bookmeter_id = i64::from(bookmeter_book.id);
+            let store_links = bookmeter_book.store_links.clone();
             let book = model::ActiveModel::from(bookmeter_book);
             if let Err(e) = Book::insert(book).exec(&self.db).await {
                 error!("{:?}", e);
+                continue;
+            }
+            if let Err(e) = self.save_store_links(bookmeter_id, &store_links).await {
+                error!("{:?}", e);
             }
         }
 
@@ -96,10 +209,16 @@ impl BookMeterDiscounts {
                     .exec(&self.db)
                     .await?;
             }
+            // ウィッシュリストから外れた本の取得失敗記録も不要になる
+            FetchFailure::delete_many()
+                .filter(fetch_failure::Column::BookmeterId.is_not_in(wishlist_ids.iter().copied()))
+                .exec(&self.db)
+                .await?;
         }
 
         // kindle idとKindle Unlimited判定の取得
         let mut stream = Book::find()
+            .filter(kindle_filter.condition())
             .filter(
                 model::Column::ActiveAt
                     .is_null()
@@ -118,24 +237,37 @@ impl BookMeterDiscounts {
                 continue;
             }
             sleep(Duration::from_secs(self.get_amazon_page_interval)).await;
-            let kindle_edition =
-                match Kindle::convert_amazon_url_to_kindle_id(&book.amazon_url).await {
-                    Ok(kindle_edition) => kindle_edition,
+            let page =
+                match Kindle::fetch_amazon_page(self.fetcher.as_ref(), &book.amazon_url).await {
+                    Ok(page) => page,
                     Err(e) => {
                         info!(
-                            "error while getting kindle edition from {}: {:?}",
+                            "error while getting amazon page from {}: {:?}",
                             book.amazon_url, e
                         );
-                        if e.to_string().contains("Kindle button not found") {
-                            active_book.active_at = Set(Some(
-                                chrono::Utc::now().naive_utc() + chrono::Duration::days(30),
-                            ));
-                            active_book.updated_at = Set(chrono::Utc::now().naive_utc());
-                            active_book.update(&self.db).await?;
-                        }
                         continue;
                     }
                 };
+            let kindle_edition = match page.kindle_edition() {
+                Ok(kindle_edition) => kindle_edition,
+                Err(e) => {
+                    info!(
+                        "error while getting kindle edition from {}: {:?}",
+                        book.amazon_url, e
+                    );
+                    if e.to_string().contains("Kindle button not found") {
+                        active_book.active_at = Set(Some(
+                            chrono::Utc::now().naive_utc() + chrono::Duration::days(30),
+                        ));
+                        active_book.updated_at = Set(chrono::Utc::now().naive_utc());
+                        active_book.update(&self.db).await?;
+                    } else {
+                        // Kindle 版がないだけの本は多いので、それ以外の失敗だけ保存する
+                        snapshot::record("amazon.kindle_edition", &page.url, &page.html, &e).await;
+                    }
+                    continue;
+                }
+            };
             active_book.kindle_id = Set(Some(kindle_edition.kindle_id));
             active_book.is_kindle_unlimited = Set(kindle_edition.is_kindle_unlimited);
             active_book.updated_at = Set(chrono::Utc::now().naive_utc());
@@ -146,6 +278,7 @@ impl BookMeterDiscounts {
         // kindle id取得済みの本の価格を取得
         let mut stream = Book::find()
             .filter(model::Column::KindleId.is_not_null())
+            .filter(price_filter.condition())
             .order_by_asc(model::Column::UpdatedAt)
             .stream(&self.db)
             .await?;
@@ -158,7 +291,7 @@ impl BookMeterDiscounts {
                 .into_value()
                 .ok_or_else(|| anyhow::anyhow!("kindle_id is None"))?
                 .to_string();
-            let kindle = match Kindle::from_id(&kindle_id).await {
+            let kindle = match Kindle::from_id(self.fetcher.as_ref(), &kindle_id).await {
                 Ok(kindle) => kindle,
                 Err(e) => {
                     info!("error while getting kindle price from {kindle_id}: {e:?}",);
@@ -167,15 +300,20 @@ impl BookMeterDiscounts {
             };
             book.basis_price = Set(Some(i32::try_from(kindle.basis_price)?));
             book.price = Set(Some(i32::try_from(kindle.price)?));
+            book.point = Set(Some(i32::try_from(kindle.point)?));
             book.discount_rate = Set(Some(kindle.discount_rate));
             book.updated_at = Set(chrono::Utc::now().naive_utc());
             book.update(&self.db).await?;
             self.metrics.record_price_fetched();
         }
 
-        // 書籍の形式 (binding_name) が未取得の本の形式を取得
+        // 書籍の形式 (binding_name) か著者名 (author) が未取得の本の本ページを取得し直して補完
         let mut stream = Book::find()
-            .filter(model::Column::BindingName.is_null())
+            .filter(
+                model::Column::BindingName
+                    .is_null()
+                    .or(model::Column::Author.is_null()),
+            )
             .stream(&self.db)
             .await?;
         while let Some(item) = stream.try_next().await? {
@@ -184,63 +322,333 @@ impl BookMeterDiscounts {
             let Ok(bookmeter_id) = u32::try_from(book.bookmeter_id) else {
                 continue;
             };
-            let binding_name = match BookMeterBook::fetch_binding_name(bookmeter_id).await {
-                Ok(binding_name) => binding_name,
+            let page = match BookMeterBook::fetch_page(&bookmeter_client, bookmeter_id).await {
+                Ok(page) => page,
                 Err(e) => {
-                    info!(
-                        "error while getting binding name for {}: {:?}",
-                        book.title, e
-                    );
+                    info!("error while getting book page for {}: {:?}", book.title, e);
                     continue;
                 }
             };
-            info!("binding name of {}: {:?}", book.title, binding_name);
-            // 形式が取得できなかった場合は NULL のままにし、次回実行時に再取得する
-            let Some(binding_name) = binding_name else {
+            info!(
+                "binding name and author of {}: {:?}, {:?}",
+                book.title, page.binding_name, page.author
+            );
+            // 取得できなかった項目は NULL のままにし、次回実行時に再取得する
+            let binding_name = book.binding_name.clone().or(page.binding_name);
+            let author = book.author.clone().or(page.author);
+            if binding_name == book.binding_name && author == book.author {
                 continue;
-            };
+            }
             let mut active_book = book.into_active_model();
-            active_book.binding_name = Set(Some(binding_name));
+            active_book.binding_name = Set(binding_name);
+            active_book.author = Set(author);
             active_book.updated_at = Set(chrono::Utc::now().naive_utc());
             active_book.update(&self.db).await?;
         }
 
-        // 漫画・ライトノベル以外の本の中古本オファーを取得
+        // 外部書店リンクが未取得の本 (リンク保存導入前に登録された本) のリンクを取得
+        // リンクが1つもない本も取得日時を記録するので、毎回取得し直すことはない
+        let mut stream = Book::find()
+            .filter(model::Column::StoreLinksCheckedAt.is_null())
+            .stream(&self.db)
+            .await?;
+        while let Some(item) = stream.try_next().await? {
+            let book: model::Model = item;
+            let Ok(bookmeter_id) = u32::try_from(book.bookmeter_id) else {
+                continue;
+            };
+            match BookMeterBook::fetch_store_links(&bookmeter_client, bookmeter_id).await {
+                Ok(store_links) => {
+                    self.save_store_links(book.bookmeter_id, &store_links)
+                        .await?;
+                }
+                Err(e) => {
+                    info!(
+                        "error while getting store links for {}: {:?}",
+                        book.title, e
+                    );
+                }
+            }
+        }
+
+        // 対象の形式 (既定では漫画・ライトノベル以外) の本と、本ごとに常に取得する指定の本の中古本オファーを取得
+        let lookup_ids = |lookup: UsedBookLookup| {
+            UsedBookOverride::find()
+                .select_only()
+                .column(used_book_override::Column::BookmeterId)
+                .filter(used_book_override::Column::Lookup.eq(lookup.as_str()))
+                .into_query()
+        };
         let mut stream = Book::find()
-            .filter(model::Column::BindingName.is_not_null())
-            .filter(model::Column::BindingName.is_not_in(["コミック", "ライトノベル"]))
+            .filter(
+                Condition::any()
+                    .add(
+                        used_book_filter.condition().add(
+                            model::Column::BookmeterId
+                                .not_in_subquery(lookup_ids(UsedBookLookup::Never)),
+                        ),
+                    )
+                    .add(
+                        model::Column::BookmeterId.in_subquery(lookup_ids(UsedBookLookup::Always)),
+                    ),
+            )
             .stream(&self.db)
             .await?;
         while let Some(item) = stream.try_next().await? {
             let book: model::Model = item;
+            // ISBN が分からない本 (Kindle 版しかない本など) はタイトル・著者名で検索する
             let isbn13 = match Kindle::convert_amazon_url_to_id(&book.amazon_url)
                 .and_then(|asin| isbn::isbn10_to_isbn13(&asin))
             {
-                Ok(isbn13) => isbn13,
+                Ok(isbn13) => Some(isbn13),
                 Err(e) => {
                     info!(
-                        "skip used book offers for {} (invalid ISBN from {}): {:?}",
+                        "search used book offers for {} by title (invalid ISBN from {}): {:?}",
                         book.title, book.amazon_url, e
                     );
-                    continue;
+                    None
                 }
             };
-            for site in UsedBookSite::ALL {
+            // Amazon マーケットプレイスの中古品は紙書籍ページから読む
+            // (Kindle 版しかない本のページには紙書籍の中古品の出品がないため、ISBN が分かる本のみ)
+            if isbn13.is_some() {
                 sleep(Duration::from_secs(self.get_amazon_page_interval)).await;
-                if let Err(e) = self.update_used_book_offer(&book, site, &isbn13).await {
+                match Kindle::fetch_amazon_page(self.fetcher.as_ref(), &book.amazon_url).await {
+                    Ok(page) => {
+                        if let Err(e) = self.update_amazon_used_offer(&book, &page).await {
+                            info!(
+                                "error while updating amazon used offer of {}: {:?}",
+                                book.title, e
+                            );
+                        }
+                    }
+                    Err(e) => {
+                        info!(
+                            "error while getting amazon page from {}: {:?}",
+                            book.amazon_url, e
+                        );
+                    }
+                }
+            }
+            for store in self.used_book_stores.iter() {
+                sleep(
+                    Duration::from_secs(self.get_amazon_page_interval)
+                        .max(store.min_request_interval()),
+                )
+                .await;
+                let result = match &isbn13 {
+                    Some(isbn13) => self.update_used_book_offer(&book, store, isbn13).await,
+                    None => self.update_used_book_offer_by_title(&book, store).await,
+                };
+                if let Err(e) = result {
                     info!(
                         "error while updating used book offer of {} on {}: {:?}",
                         book.title,
-                        site.as_str(),
+                        store.site_key(),
                         e
                     );
                 }
             }
         }
 
+        // 図書館の蔵書・貸出状況を取得 (CALIL_APP_KEY と LIBRARY_SYSTEM_IDS を設定した場合のみ)
+        if let Some(library_client) = library::LibraryClient::from_env()? {
+            // 図書館システムごとの更新日時のうち最も古いものが、取得し直す間隔を過ぎた本だけを取得する
+            let checked_at: HashMap<i64, chrono::NaiveDateTime> = LibraryAvailability::find()
+                .select_only()
+                .column(library_availability::Column::BookmeterId)
+                .column_as(library_availability::Column::UpdatedAt.min(), "updated_at")
+                .group_by(library_availability::Column::BookmeterId)
+                .into_tuple()
+                .all(&self.db)
+                .await?
+                .into_iter()
+                .collect();
+            let now = chrono::Utc::now().naive_utc();
+            let mut stream = Book::find().stream(&self.db).await?;
+            while let Some(item) = stream.try_next().await? {
+                let book: model::Model = item;
+                if !library_client.needs_check(checked_at.get(&book.bookmeter_id).copied(), now) {
+                    continue;
+                }
+                let Ok(isbn13) = Kindle::convert_amazon_url_to_id(&book.amazon_url)
+                    .and_then(|asin| isbn::isbn10_to_isbn13(&asin))
+                else {
+                    continue;
+                };
+                sleep(library_client.poll_interval()).await;
+                match library_client.check(&isbn13).await {
+                    Ok(availabilities) => {
+                        self.save_library_availabilities(book.bookmeter_id, &availabilities)
+                            .await?;
+                    }
+                    Err(e) => {
+                        info!(
+                            "error while checking library availability of {}: {:?}",
+                            book.title, e
+                        );
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 1冊分の図書館の蔵書・貸出状況を保存する
+    ///
+    /// 結果が返ってきた図書館システムの行だけを置き換え、問い合わせが終わらなかった図書館システムの行は残す。
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails.
+    async fn save_library_availabilities(
+        &self,
+        bookmeter_id: i64,
+        availabilities: &[library::LibraryAvailability],
+    ) -> Result<()> {
+        if availabilities.is_empty() {
+            return Ok(());
+        }
+        let system_ids: BTreeSet<&str> = availabilities
+            .iter()
+            .map(|availability| availability.system_id.as_str())
+            .collect();
+        LibraryAvailability::delete_many()
+            .filter(library_availability::Column::BookmeterId.eq(bookmeter_id))
+            .filter(library_availability::Column::SystemId.is_in(system_ids))
+            .exec(&self.db)
+            .await?;
+        LibraryAvailability::insert_many(availabilities.iter().map(|availability| {
+            library_availability::ActiveModel::from_availability(bookmeter_id, availability)
+        }))
+        .exec(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// 指定した本の図書館の蔵書・貸出状況を取得する
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub async fn get_library_availabilities(
+        &self,
+        bookmeter_ids: &[i64],
+    ) -> Result<Vec<library_availability::Model>> {
+        Ok(LibraryAvailability::find()
+            .filter(library_availability::Column::BookmeterId.is_in(bookmeter_ids.iter().copied()))
+            .order_by_asc(library_availability::Column::BookmeterId)
+            .order_by_asc(library_availability::Column::SystemId)
+            .order_by_asc(library_availability::Column::Library)
+            .all(&self.db)
+            .await?)
+    }
+
+    /// 1冊分の外部書店リンクを書店名ごとに保存し、取得日時を記録する (既存のリンクは置き換える)
+    ///
+    /// 途中で失敗した場合は既存のリンクを残す。
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails.
+    async fn save_store_links(
+        &self,
+        bookmeter_id: i64,
+        store_links: &[bookmeter::ExternalBookStoreLink],
+    ) -> Result<()> {
+        let txn = self.db.begin().await?;
+        BookStoreLink::delete_many()
+            .filter(book_store_link::Column::BookmeterId.eq(bookmeter_id))
+            .exec(&txn)
+            .await?;
+        if !store_links.is_empty() {
+            BookStoreLink::insert_many(
+                store_links
+                    .iter()
+                    .map(|link| book_store_link::ActiveModel::from_link(bookmeter_id, link)),
+            )
+            .exec(&txn)
+            .await?;
+        }
+        Book::update_many()
+            .col_expr(
+                model::Column::StoreLinksCheckedAt,
+                Expr::value(chrono::Utc::now().naive_utc()),
+            )
+            .filter(model::Column::BookmeterId.eq(bookmeter_id))
+            .exec(&txn)
+            .await?;
+        txn.commit().await?;
         Ok(())
     }
 
+    /// 本ごとの中古本オファー取得の指定を保存する (None の場合は指定を消して書籍の形式に従う)
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails.
+    pub async fn set_used_book_override(
+        &self,
+        bookmeter_id: i64,
+        lookup: Option<UsedBookLookup>,
+    ) -> Result<()> {
+        UsedBookOverride::delete_by_id(bookmeter_id)
+            .exec(&self.db)
+            .await?;
+        if let Some(lookup) = lookup {
+            UsedBookOverride::insert(used_book_override::ActiveModel {
+                bookmeter_id: Set(bookmeter_id),
+                lookup: Set(lookup.as_str().to_string()),
+                updated_at: Set(chrono::Utc::now().naive_utc()),
+            })
+            .exec(&self.db)
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// 1冊分の外部書店リンクを取得する
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub async fn get_store_links(&self, bookmeter_id: i64) -> Result<Vec<book_store_link::Model>> {
+        Ok(BookStoreLink::find()
+            .filter(book_store_link::Column::BookmeterId.eq(bookmeter_id))
+            .order_by_asc(book_store_link::Column::Store)
+            .all(&self.db)
+            .await?)
+    }
+
+    /// 指定した書店のリンクを取得する (書店ごとの価格取得用)
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub async fn get_store_link(
+        &self,
+        bookmeter_id: i64,
+        store: &str,
+    ) -> Result<Option<book_store_link::Model>> {
+        Ok(BookStoreLink::find_by_id((bookmeter_id, store.to_string()))
+            .one(&self.db)
+            .await?)
+    }
+
+    /// 読書メーターから詳細を取得できずに登録できていない本を、失敗回数の多い順に取得する
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub async fn get_fetch_failures(&self) -> Result<Vec<fetch_failure::Model>> {
+        Ok(FetchFailure::find()
+            .order_by_desc(fetch_failure::Column::Attempts)
+            .order_by_asc(fetch_failure::Column::BookmeterId)
+            .all(&self.db)
+            .await?)
+    }
+
     /// 1冊・1サイト分の中古本オファーを取得してDBに保存する
     ///
     /// # Errors
@@ -249,44 +657,336 @@ impl BookMeterDiscounts {
     pub async fn update_used_book_offer(
         &self,
         book: &model::Model,
-        site: UsedBookSite,
+        store: &dyn UsedBookStore,
         isbn13: &str,
     ) -> Result<()> {
-        let existing = UsedBookOffer::find_by_id((book.bookmeter_id, site.as_str().to_string()))
+        let existing = UsedBookOffer::find_by_id((book.bookmeter_id, store.site_key().to_string()))
             .one(&self.db)
             .await?;
         let known_product = existing
             .as_ref()
             .and_then(|m| m.product_id.as_deref().zip(m.product_url.as_deref()));
-        let update = site.refresh_offer(isbn13, known_product).await?;
-        if let Some(model) = existing {
-            model
-                .into_active_model()
-                .apply_update(&update)
-                .update(&self.db)
+        let update = store
+            .refresh_offer(self.fetcher.as_ref(), isbn13, known_product)
+            .await?;
+        self.save_used_book_offer(book, store.site_key(), existing, &update)
+            .await
+    }
+
+    /// ISBN が分からない本の1サイト分の中古本オファーをタイトル・著者名で検索してDBに保存する
+    ///
+    /// 一致度が `title_match::MIN_MATCH_SCORE` 未満の検索結果は保存しない。
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the network or database operation fails.
+    pub async fn update_used_book_offer_by_title(
+        &self,
+        book: &model::Model,
+        store: &dyn UsedBookStore,
+    ) -> Result<()> {
+        let existing = UsedBookOffer::find_by_id((book.bookmeter_id, store.site_key().to_string()))
+            .one(&self.db)
+            .await?;
+        let update = store
+            .search_offer_by_title(self.fetcher.as_ref(), &book.title, book.author.as_deref())
+            .await?;
+        self.save_used_book_offer(book, store.site_key(), existing, &update)
+            .await
+    }
+
+    /// Amazon の紙書籍ページから読んだ中古品の出品情報を `amazon_used` として保存する
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails or the used offer link cannot be parsed.
+    async fn update_amazon_used_offer(
+        &self,
+        book: &model::Model,
+        page: &kindle::AmazonPage,
+    ) -> Result<()> {
+        let existing =
+            UsedBookOffer::find_by_id((book.bookmeter_id, used_book::amazon::SITE_KEY.to_string()))
+                .one(&self.db)
                 .await?;
+        let used_offer = snapshot::parse_or_record(
+            "amazon.used_offer",
+            &page.url,
+            &page.html,
+            used_book::amazon::parse_used_offer,
+        )
+        .await?;
+        let update = match used_offer {
+            Some(offer) => offer.to_update(&page.id),
+            // 中古品の出品がなくなった場合は既存の行だけ在庫なしにする
+            None if existing.is_some() => used_book::OfferUpdate {
+                product_id: Some(page.id.clone()),
+                product_url: Some(used_book::amazon::offer_listing_url(&page.id)),
+                ..Default::default()
+            },
+            None => return Ok(()),
+        };
+        self.save_used_book_offer(book, used_book::amazon::SITE_KEY, existing, &update)
+            .await
+    }
+
+    /// 1冊・1サイト分のオファー情報を既存の行に反映するか、新しい行として保存する
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails.
+    async fn save_used_book_offer(
+        &self,
+        book: &model::Model,
+        site_key: &'static str,
+        existing: Option<used_book_offer::Model>,
+        update: &used_book::OfferUpdate,
+    ) -> Result<()> {
+        // 送料の設定がないサイトは送料込みの価格を計算しない
+        let shipping_fee = ShippingFee::find_by_id(site_key).one(&self.db).await?;
+        let landed_price = update
+            .price
+            .zip(shipping_fee)
+            .map(|(price, shipping_fee)| shipping_fee.landed_price(price));
+        if let Some(model) = existing {
+            let mut active = model.into_active_model().apply_update(update);
+            active.landed_price = Set(landed_price);
+            active.update(&self.db).await?;
         } else {
             // 検索で商品が見つからなかった場合は行を作らず、次回実行時に再検索する
             if update.product_id.is_none() {
                 return Ok(());
             }
-            let mut active = used_book_offer::ActiveModel::from(&update);
+            let mut active = used_book_offer::ActiveModel::from(update);
             active.bookmeter_id = Set(book.bookmeter_id);
-            active.site = Set(site.as_str().to_string());
+            active.site = Set(site_key.to_string());
+            active.landed_price = Set(landed_price);
             UsedBookOffer::insert(active).exec(&self.db).await?;
         }
-        self.metrics.record_used_book_offer_fetched(site.as_str());
+        if let Some(variants) = &update.variants {
+            self.save_used_book_offer_conditions(book.bookmeter_id, site_key, variants)
+                .await?;
+        }
+        self.metrics.record_used_book_offer_fetched(site_key);
+        Ok(())
+    }
+
+    /// 1冊・1サイト分の状態ごとの価格・在庫を保存する (既存の行は置き換える)
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails.
+    async fn save_used_book_offer_conditions(
+        &self,
+        bookmeter_id: i64,
+        site_key: &str,
+        variants: &[used_book::ConditionVariant],
+    ) -> Result<()> {
+        UsedBookOfferCondition::delete_many()
+            .filter(used_book_offer_condition::Column::BookmeterId.eq(bookmeter_id))
+            .filter(used_book_offer_condition::Column::Site.eq(site_key))
+            .exec(&self.db)
+            .await?;
+        if variants.is_empty() {
+            return Ok(());
+        }
+        UsedBookOfferCondition::insert_many(variants.iter().map(|variant| {
+            used_book_offer_condition::ActiveModel::from_variant(bookmeter_id, site_key, variant)
+        }))
+        .exec(&self.db)
+        .await?;
         Ok(())
     }
 
+    /// 1冊分の状態ごとの中古本オファーを、サイト・価格の安い順に取得する
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub async fn get_used_book_offer_conditions(
+        &self,
+        bookmeter_id: i64,
+    ) -> Result<Vec<used_book_offer_condition::Model>> {
+        Ok(UsedBookOfferCondition::find()
+            .filter(used_book_offer_condition::Column::BookmeterId.eq(bookmeter_id))
+            .order_by_asc(used_book_offer_condition::Column::Site)
+            .order_by_asc(used_book_offer_condition::Column::Price)
+            .all(&self.db)
+            .await?)
+    }
+
+    /// 1冊分の中古本オファーを送料込みの価格の安い順に取得する
+    ///
+    /// `min_grade` を指定した場合は、その状態ランク以上で在庫がある状態のうち最も安いものの
+    /// 価格でオファーを返す (該当する状態がないオファーは除く。[`used_book_offer::select_by_grade`])。
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub async fn get_used_book_offers(
+        &self,
+        bookmeter_id: i64,
+        min_grade: Option<ConditionGrade>,
+    ) -> Result<Vec<used_book_offer::Model>> {
+        let offers = UsedBookOffer::find()
+            .filter(used_book_offer::Column::BookmeterId.eq(bookmeter_id))
+            .order_by_asc(used_book_offer::Column::LandedPrice)
+            .order_by_asc(used_book_offer::Column::Price)
+            .all(&self.db)
+            .await?;
+        let Some(min_grade) = min_grade else {
+            return Ok(offers);
+        };
+        let conditions = self.get_used_book_offer_conditions(bookmeter_id).await?;
+        let shipping_fees = ShippingFee::find().all(&self.db).await?;
+        Ok(used_book_offer::select_by_grade(
+            offers,
+            &conditions,
+            &shipping_fees,
+            min_grade,
+        ))
+    }
+
+    /// 登録済みの本ごとに、Kindle 版・Kindle Unlimited・中古本サイトのうち最も安く買える先を取得する
+    ///
+    /// 買える先がない本は含めない。
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub async fn get_channel_recommendations(
+        &self,
+        options: recommendation::RecommendationOptions,
+    ) -> Result<Vec<recommendation::ChannelRecommendation>> {
+        let books = Book::find()
+            .order_by_asc(model::Column::Title)
+            .all(&self.db)
+            .await?;
+        let offers = UsedBookOffer::find()
+            .filter(used_book_offer::Column::InStock.eq(true))
+            .all(&self.db)
+            .await?;
+        Ok(books
+            .iter()
+            .filter_map(|book| recommendation::recommend(book, &offers, options))
+            .collect())
+    }
+
+    /// 指定した本 (空の場合は登録済みの全ての本) をまとめて買う最安の買い方を求める
+    ///
+    /// Kindle 版のポイント還元後の価格と在庫のある中古本オファーから、
+    /// `shipping_fees` の送料無料の金額を考慮して本ごとに買う先を選ぶ。
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub async fn plan_basket(&self, bookmeter_ids: &[i64]) -> Result<basket::BasketPlan> {
+        let books = Book::find()
+            .apply_if(
+                Some(bookmeter_ids).filter(|ids| !ids.is_empty()),
+                |query, ids| query.filter(model::Column::BookmeterId.is_in(ids.iter().copied())),
+            )
+            .order_by_asc(model::Column::Title)
+            .all(&self.db)
+            .await?;
+        let offers = UsedBookOffer::find()
+            .filter(
+                used_book_offer::Column::BookmeterId.is_in(books.iter().map(|b| b.bookmeter_id)),
+            )
+            .filter(used_book_offer::Column::InStock.eq(true))
+            .filter(used_book_offer::Column::Price.is_not_null())
+            .filter(used_book_offer::Column::ProductUrl.is_not_null())
+            .all(&self.db)
+            .await?;
+        let shipping_fees = ShippingFee::find().all(&self.db).await?;
+
+        let basket_books: Vec<basket::BasketBook> = books
+            .into_iter()
+            .map(|book| {
+                let kindle = book
+                    .kindle_id
+                    .as_ref()
+                    .zip(recommendation::kindle_net_price(&book))
+                    .map(|(kindle_id, price)| basket::PurchaseOption {
+                        channel: basket::KINDLE_CHANNEL.to_string(),
+                        price,
+                        url: format!("https://www.amazon.co.jp/dp/{kindle_id}"),
+                        listing_type: None,
+                    });
+                let used = offers
+                    .iter()
+                    .filter(|offer| offer.bookmeter_id == book.bookmeter_id)
+                    .filter_map(|offer| {
+                        Some(basket::PurchaseOption {
+                            channel: offer.site.clone(),
+                            price: offer.price?,
+                            url: offer.product_url.clone()?,
+                            listing_type: offer.listing_type.clone(),
+                        })
+                    });
+                basket::BasketBook {
+                    bookmeter_id: book.bookmeter_id,
+                    title: book.title,
+                    options: kindle.into_iter().chain(used).collect(),
+                }
+            })
+            .collect();
+        Ok(basket::optimise(&basket_books, &shipping_fees))
+    }
+
+    /// Kindle 版の割引率が高い順に本を取得する
+    ///
+    /// `min_grade` を指定した場合は、その状態ランク以上の中古本の在庫がある本だけを返す。
+    /// オファー自体の状態ランクは最も安い状態のものなので、状態ごとの在庫があるサイトは
+    /// 状態ごとの在庫だけで判定する。
+    ///
     /// # Errors
     ///
     /// Returns an error if the database query fails.
     pub async fn get_discounts(
         &self,
         limit: Option<u64>,
+        min_grade: Option<ConditionGrade>,
     ) -> Result<impl Stream<Item = Result<model::Model>> + '_> {
         Ok(Book::find()
+            .apply_if(min_grade, |query, grade| {
+                let variants = UsedBookOfferCondition::find()
+                    .select_only()
+                    .column(used_book_offer_condition::Column::BookmeterId)
+                    .filter(
+                        Expr::col((
+                            UsedBookOfferCondition,
+                            used_book_offer_condition::Column::BookmeterId,
+                        ))
+                        .equals((UsedBookOffer, used_book_offer::Column::BookmeterId)),
+                    )
+                    .filter(
+                        Expr::col((
+                            UsedBookOfferCondition,
+                            used_book_offer_condition::Column::Site,
+                        ))
+                        .equals((UsedBookOffer, used_book_offer::Column::Site)),
+                    )
+                    .into_query();
+                let offers = UsedBookOffer::find()
+                    .select_only()
+                    .column(used_book_offer::Column::BookmeterId)
+                    .filter(used_book_offer::Column::InStock.eq(true))
+                    .filter(used_book_offer::Column::ConditionGrade.is_in(grade.at_least()))
+                    .filter(Expr::exists(variants).not())
+                    .into_query();
+                let conditions = UsedBookOfferCondition::find()
+                    .select_only()
+                    .column(used_book_offer_condition::Column::BookmeterId)
+                    .filter(used_book_offer_condition::Column::InStock.eq(true))
+                    .filter(used_book_offer_condition::Column::Grade.is_in(grade.at_least()))
+                    .into_query();
+                query.filter(
+                    Condition::any()
+                        .add(model::Column::BookmeterId.in_subquery(offers))
+                        .add(model::Column::BookmeterId.in_subquery(conditions)),
+                )
+            })
             .filter(model::Column::Title.is_not_null())
             .filter(model::Column::BasisPrice.is_not_null())
             .filter(model::Column::Price.is_not_null())
@@ -0,0 +1,403 @@
+//! 公共図書館の蔵書・貸出状況 (カーリル互換の蔵書検索 API)
+//!
+//! - 検索開始: `{base_url}/check?appkey=…&isbn={isbn13}&systemid={system_id,…}&format=json&callback=no`
+//! - 続きの取得: `{base_url}/check?appkey=…&session={session}&format=json&callback=no`
+//!
+//! レスポンスの `continue` が 1 の間は図書館への問い合わせが終わっていないため、
+//! 間隔を空けて `session` で問い合わせ直す。
+
+use std::{collections::BTreeMap, time::Duration};
+
+use anyhow::Result;
+use serde::Deserialize;
+use tokio::time::sleep;
+
+/// カーリル API の URL
+pub const DEFAULT_BASE_URL: &str = "https://api.calil.jp";
+
+/// 図書館システムの蔵書がない場合に `library` に入れる値
+///
+/// カーリル API は蔵書がない図書館システムの `libkey` を空で返すため、図書館システム単位で1行にする。
+pub const NO_HOLDINGS_LIBRARY: &str = "";
+
+/// 蔵書がない場合の状態
+pub const NO_HOLDINGS_STATUS: &str = "蔵書なし";
+
+/// 1つの図書館の蔵書・貸出状況
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LibraryAvailability {
+    /// 図書館システム ID (`Tokyo_Setagaya` など)
+    pub system_id: String,
+    /// 図書館名。図書館システムに蔵書がない場合は [`NO_HOLDINGS_LIBRARY`]
+    pub library: String,
+    /// 貸出状況 (貸出可 / 貸出中 / 館内のみ / 蔵書あり / 予約中 / 準備中 / 休館中 / 蔵書なし)
+    pub status: String,
+    /// 予約ページの URL
+    pub reserve_url: Option<String>,
+}
+
+/// `check` API のレスポンス1回分
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CheckResponse {
+    pub session: String,
+    /// まだ問い合わせ中の図書館システムがあるか
+    pub continue_polling: bool,
+    /// 問い合わせが終わった図書館システムの状況
+    pub availabilities: Vec<LibraryAvailability>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawCheckResponse {
+    session: String,
+    #[serde(rename = "continue")]
+    continue_polling: u8,
+    #[serde(default)]
+    books: BTreeMap<String, BTreeMap<String, RawSystemStatus>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawSystemStatus {
+    status: String,
+    #[serde(default)]
+    reserveurl: Option<String>,
+    #[serde(default)]
+    libkey: Option<BTreeMap<String, String>>,
+}
+
+/// `check` API のレスポンス JSON を解析する
+///
+/// 問い合わせ中 (`Running`) や失敗 (`Error`) の図書館システムは結果に含めない。
+///
+/// # Errors
+///
+/// JSON の形式が想定と異なる場合にエラーを返す。
+pub fn parse_check_response(json: &str) -> Result<CheckResponse> {
+    let raw: RawCheckResponse = serde_json::from_str(json)?;
+    let availabilities = raw
+        .books
+        .into_values()
+        .flat_map(BTreeMap::into_iter)
+        .filter(|(_, system)| matches!(system.status.as_str(), "OK" | "Cache"))
+        .flat_map(|(system_id, system)| {
+            let reserve_url = system.reserveurl.filter(|url| !url.is_empty());
+            let libraries = system.libkey.unwrap_or_default();
+            if libraries.is_empty() {
+                return vec![LibraryAvailability {
+                    system_id,
+                    library: NO_HOLDINGS_LIBRARY.to_string(),
+                    status: NO_HOLDINGS_STATUS.to_string(),
+                    reserve_url: None,
+                }];
+            }
+            libraries
+                .into_iter()
+                .map(|(library, status)| LibraryAvailability {
+                    system_id: system_id.clone(),
+                    library,
+                    status,
+                    reserve_url: reserve_url.clone(),
+                })
+                .collect()
+        })
+        .collect();
+    Ok(CheckResponse {
+        session: raw.session,
+        continue_polling: raw.continue_polling != 0,
+        availabilities,
+    })
+}
+
+/// カーリル互換 API のクライアント
+#[derive(Clone, Debug)]
+pub struct LibraryClient {
+    base_url: String,
+    app_key: String,
+    system_ids: Vec<String>,
+    poll_interval: Duration,
+    max_polls: u32,
+    recheck_interval: Duration,
+    http: reqwest::Client,
+}
+
+impl LibraryClient {
+    /// カーリルの利用規約で求められている問い合わせ間隔 (2秒以上)
+    pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+    /// 問い合わせ直しの上限回数
+    pub const DEFAULT_MAX_POLLS: u32 = 30;
+    /// 同じ本の蔵書・貸出状況を取得し直すまでの間隔
+    pub const DEFAULT_RECHECK_INTERVAL: Duration = Duration::from_hours(24);
+
+    /// # Errors
+    ///
+    /// HTTP クライアントの構築に失敗した場合にエラーを返す。
+    pub fn new(base_url: &str, app_key: &str, system_ids: Vec<String>) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()?;
+        Ok(Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            app_key: app_key.to_string(),
+            system_ids,
+            poll_interval: Self::DEFAULT_POLL_INTERVAL,
+            max_polls: Self::DEFAULT_MAX_POLLS,
+            recheck_interval: Self::DEFAULT_RECHECK_INTERVAL,
+            http,
+        })
+    }
+
+    /// 問い合わせ直しの間隔を変える (モックサーバーでのテスト用)
+    #[must_use]
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// 同じ本の蔵書・貸出状況を取得し直すまでの間隔を変える
+    #[must_use]
+    pub fn with_recheck_interval(mut self, recheck_interval: Duration) -> Self {
+        self.recheck_interval = recheck_interval;
+        self
+    }
+
+    /// 問い合わせの間隔
+    #[must_use]
+    pub fn poll_interval(&self) -> Duration {
+        self.poll_interval
+    }
+
+    /// 前回 `checked_at` に取得した本の蔵書・貸出状況を `now` に取得し直すか
+    ///
+    /// 一度も取得していない本 (`None`) は取得する。
+    #[must_use]
+    pub fn needs_check(
+        &self,
+        checked_at: Option<chrono::NaiveDateTime>,
+        now: chrono::NaiveDateTime,
+    ) -> bool {
+        let Some(checked_at) = checked_at else {
+            return true;
+        };
+        chrono::Duration::from_std(self.recheck_interval)
+            .map_or(true, |interval| checked_at + interval <= now)
+    }
+
+    /// 環境変数から設定する
+    ///
+    /// - `CALIL_APP_KEY`: アプリケーションキー
+    /// - `LIBRARY_SYSTEM_IDS`: 図書館システム ID (カンマ区切り)
+    /// - `CALIL_API_BASE_URL`: API の URL (省略時は [`DEFAULT_BASE_URL`])
+    /// - `LIBRARY_RECHECK_HOURS`: 同じ本を取得し直すまでの時間
+    ///   (省略時は [`Self::DEFAULT_RECHECK_INTERVAL`])
+    ///
+    /// アプリケーションキーか図書館システム ID が設定されていない場合は `Ok(None)` を返す。
+    ///
+    /// # Errors
+    ///
+    /// HTTP クライアントの構築に失敗した場合や、`LIBRARY_RECHECK_HOURS` が数値でない場合にエラーを返す。
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(app_key) = std::env::var("CALIL_APP_KEY") else {
+            return Ok(None);
+        };
+        let system_ids: Vec<String> = std::env::var("LIBRARY_SYSTEM_IDS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .map(str::to_string)
+            .collect();
+        if system_ids.is_empty() {
+            return Ok(None);
+        }
+        let base_url =
+            std::env::var("CALIL_API_BASE_URL").unwrap_or_else(|_| DEFAULT_BASE_URL.to_string());
+        let client = Self::new(&base_url, &app_key, system_ids)?;
+        Ok(Some(match std::env::var("LIBRARY_RECHECK_HOURS") {
+            Ok(v) => client.with_recheck_interval(Duration::from_hours(v.parse()?)),
+            Err(_) => client,
+        }))
+    }
+
+    /// ISBN-13 の本の蔵書・貸出状況を、全ての図書館システムの問い合わせが終わるまで取得する
+    ///
+    /// 上限回数まで問い合わせ直しても終わらない図書館システムは結果に含めない。
+    ///
+    /// # Errors
+    ///
+    /// HTTP リクエストまたはレスポンスの解析に失敗した場合にエラーを返す。
+    pub async fn check(&self, isbn13: &str) -> Result<Vec<LibraryAvailability>> {
+        let system_ids = self.system_ids.join(",");
+        let mut response = self
+            .get(&[("isbn", isbn13), ("systemid", system_ids.as_str())])
+            .await?;
+        let mut polls = 0;
+        while response.continue_polling && polls < self.max_polls {
+            sleep(self.poll_interval).await;
+            polls += 1;
+            // 続きのレスポンスには前回までに終わった図書館システムも含まれる
+            let session = response.session.clone();
+            response = self.get(&[("session", session.as_str())]).await?;
+        }
+        if response.continue_polling {
+            tracing::warn!("library check for {isbn13} did not finish after {polls} polls");
+        }
+        Ok(response.availabilities)
+    }
+
+    async fn get(&self, params: &[(&str, &str)]) -> Result<CheckResponse> {
+        let url = url::Url::parse_with_params(
+            &format!("{}/check", self.base_url),
+            [("appkey", self.app_key.as_str())]
+                .into_iter()
+                .chain(params.iter().copied())
+                .chain([("format", "json"), ("callback", "no")]),
+        )?;
+        let body = self
+            .http
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        parse_check_response(&body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    const RUNNING: &str = r#"{
+        "session": "abc123",
+        "continue": 1,
+        "books": {
+            "9784813705185": {
+                "Tokyo_Setagaya": {"status": "Running", "reserveurl": ""},
+                "Tokyo_Meguro": {"status": "Cache", "reserveurl": "", "libkey": {}}
+            }
+        }
+    }"#;
+
+    const DONE: &str = r#"{
+        "session": "abc123",
+        "continue": 0,
+        "books": {
+            "9784813705185": {
+                "Tokyo_Setagaya": {
+                    "status": "OK",
+                    "reserveurl": "https://libweb.city.setagaya.tokyo.jp/reserve?isbn=9784813705185",
+                    "libkey": {"中央": "貸出中", "経堂": "貸出可"}
+                },
+                "Tokyo_Meguro": {"status": "Cache", "reserveurl": "", "libkey": {}}
+            }
+        }
+    }"#;
+
+    #[test]
+    fn test_parse_check_response_running() -> Result<()> {
+        let response = parse_check_response(RUNNING)?;
+        assert_eq!(response.session, "abc123");
+        assert!(response.continue_polling);
+        assert_eq!(
+            response.availabilities,
+            vec![LibraryAvailability {
+                system_id: "Tokyo_Meguro".to_string(),
+                library: NO_HOLDINGS_LIBRARY.to_string(),
+                status: NO_HOLDINGS_STATUS.to_string(),
+                reserve_url: None,
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_check_response_done() -> Result<()> {
+        let response = parse_check_response(DONE)?;
+        assert!(!response.continue_polling);
+        let statuses: Vec<(&str, &str, &str)> = response
+            .availabilities
+            .iter()
+            .map(|a| (a.system_id.as_str(), a.library.as_str(), a.status.as_str()))
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                ("Tokyo_Meguro", "", "蔵書なし"),
+                ("Tokyo_Setagaya", "中央", "貸出中"),
+                ("Tokyo_Setagaya", "経堂", "貸出可"),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_check_response_invalid() {
+        assert!(parse_check_response("callback({})").is_err());
+    }
+
+    /// 1回目は問い合わせ中、2回目以降は完了のレスポンスを返すモックサーバー
+    async fn spawn_mock_server() -> Result<(String, Arc<AtomicUsize>)> {
+        use axum::{extract::RawQuery, routing::get, Router};
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        let app = Router::new().route(
+            "/check",
+            get(move |RawQuery(query): RawQuery| {
+                let counter = Arc::clone(&counter);
+                async move {
+                    let query = query.unwrap_or_default();
+                    assert!(query.contains("appkey=test-key"));
+                    assert!(query.contains("callback=no"));
+                    if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                        assert!(query.contains("isbn=9784813705185"));
+                        assert!(query.contains("systemid=Tokyo_Setagaya%2CTokyo_Meguro"));
+                        RUNNING
+                    } else {
+                        assert!(query.contains("session=abc123"));
+                        DONE
+                    }
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        Ok((format!("http://{addr}"), calls))
+    }
+
+    #[test]
+    fn test_needs_check() -> Result<()> {
+        let client = LibraryClient::new(DEFAULT_BASE_URL, "test-key", Vec::new())?;
+        let now = chrono::NaiveDateTime::default() + chrono::Duration::days(10);
+        assert!(client.needs_check(None, now));
+        assert!(!client.needs_check(Some(now - chrono::Duration::hours(23)), now));
+        assert!(client.needs_check(Some(now - chrono::Duration::hours(24)), now));
+        let client = client.with_recheck_interval(Duration::ZERO);
+        assert!(client.needs_check(Some(now), now));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_check_polls_until_done() -> Result<()> {
+        let (base_url, calls) = spawn_mock_server().await?;
+        let client = LibraryClient::new(
+            &base_url,
+            "test-key",
+            vec!["Tokyo_Setagaya".to_string(), "Tokyo_Meguro".to_string()],
+        )?
+        .with_poll_interval(Duration::from_millis(10));
+        let availabilities = client.check("9784813705185").await?;
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(availabilities.len(), 3);
+        assert_eq!(
+            availabilities[2].reserve_url.as_deref(),
+            Some("https://libweb.city.setagaya.tokyo.jp/reserve?isbn=9784813705185")
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,43 @@
+use sea_orm::{entity::prelude::*, ActiveValue::Set};
+use serde::{Deserialize, Serialize};
+
+use crate::library::LibraryAvailability;
+
+/// 本ごと・図書館ごとの蔵書・貸出状況
+#[derive(Clone, Debug, DeriveEntityModel, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[sea_orm(table_name = "library_availabilities")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub bookmeter_id: i64,
+    /// 図書館システム ID (`Tokyo_Setagaya` など)
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub system_id: String,
+    /// 図書館名。図書館システムに蔵書がない場合は空文字列
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub library: String,
+    /// 貸出状況 (貸出可 / 貸出中 / 蔵書なし など)
+    pub status: String,
+    pub reserve_url: Option<String>,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+#[derive(Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModel {
+    /// API から取得した1図書館分の状況を `ActiveModel` に変換する
+    #[must_use]
+    pub fn from_availability(bookmeter_id: i64, availability: &LibraryAvailability) -> Self {
+        ActiveModel {
+            bookmeter_id: Set(bookmeter_id),
+            system_id: Set(availability.system_id.clone()),
+            library: Set(availability.library.clone()),
+            status: Set(availability.status.clone()),
+            reserve_url: Set(availability.reserve_url.clone()),
+            updated_at: Set(chrono::Utc::now().naive_utc()),
+        }
+    }
+}
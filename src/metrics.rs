@@ -1,6 +1,6 @@
 use opentelemetry::{
     global,
-    metrics::{Counter, Meter},
+    metrics::{Counter, Gauge, Meter},
     KeyValue,
 };
 use opentelemetry_otlp::WithExportConfig;
@@ -13,11 +13,14 @@ pub struct MetricsCollector {
     kindle_id_fetched: Counter<u64>,
     price_fetched: Counter<u64>,
     used_book_offer_fetched: Counter<u64>,
+    parser_check: Gauge<u64>,
+    /// OTLP に送る場合のプロバイダー (短時間で終わるコマンドの終了前に送信するため)
+    provider: Option<SdkMeterProvider>,
 }
 
 impl MetricsCollector {
     pub fn new() -> Arc<Self> {
-        let meter = Self::init_meter();
+        let (meter, provider) = Self::init_meter();
 
         let deleted_books = meter
             .u64_counter("bookmeter.deleted_books")
@@ -39,38 +42,47 @@ impl MetricsCollector {
             .with_description("Number of used book offers fetched")
             .build();
 
+        let parser_check = meter
+            .u64_gauge("bookmeter.parser_check")
+            .with_description(
+                "Result of the parser check per source (0: ok, 1: missing fields, 2: failed)",
+            )
+            .build();
+
         Arc::new(Self {
             deleted_books,
             kindle_id_fetched,
             price_fetched,
             used_book_offer_fetched,
+            parser_check,
+            provider,
         })
     }
 
-    fn init_meter() -> Meter {
+    fn init_meter() -> (Meter, Option<SdkMeterProvider>) {
         // OTLP エンドポイントが設定されていない場合は Noop メーターを返す
         if std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").is_err() {
             info!("OTEL_EXPORTER_OTLP_ENDPOINT not set, using noop metrics");
-            return global::meter("bookmeter-discounts");
+            return (global::meter("bookmeter-discounts"), None);
         }
 
         // OTLP 接続を試みる
         match Self::try_init_otlp_meter() {
-            Ok(meter) => {
+            Ok((meter, provider)) => {
                 info!("OpenTelemetry metrics initialized successfully");
-                meter
+                (meter, Some(provider))
             }
             Err(e) => {
                 warn!(
                     "Failed to initialize OpenTelemetry metrics: {:?}, using noop metrics",
                     e
                 );
-                global::meter("bookmeter-discounts")
+                (global::meter("bookmeter-discounts"), None)
             }
         }
     }
 
-    fn try_init_otlp_meter() -> Result<Meter, Box<dyn std::error::Error>> {
+    fn try_init_otlp_meter() -> Result<(Meter, SdkMeterProvider), Box<dyn std::error::Error>> {
         // Build metrics exporter using the public API
         let exporter = opentelemetry_otlp::MetricExporter::builder()
             .with_tonic()
@@ -83,9 +95,9 @@ impl MetricsCollector {
 
         let provider = SdkMeterProvider::builder().with_reader(reader).build();
 
-        global::set_meter_provider(provider);
+        global::set_meter_provider(provider.clone());
 
-        Ok(global::meter("bookmeter-discounts"))
+        Ok((global::meter("bookmeter-discounts"), provider))
     }
 
     pub fn record_deleted_book(&self) {
@@ -107,4 +119,19 @@ impl MetricsCollector {
         self.used_book_offer_fetched
             .add(1, &[KeyValue::new("site", site)]);
     }
+
+    /// パーサーの確認結果を記録する (0: 正常 / 1: 項目の欠落 / 2: 取得・解析の失敗)
+    pub fn record_parser_check(&self, source: &str, status: u64) {
+        self.parser_check
+            .record(status, &[KeyValue::new("source", source.to_string())]);
+    }
+
+    /// 溜まっているメトリクスをすぐに送信する (OTLP を使わない場合は何もしない)
+    pub fn flush(&self) {
+        if let Some(provider) = &self.provider {
+            if let Err(e) = provider.force_flush() {
+                warn!("Failed to flush metrics: {:?}", e);
+            }
+        }
+    }
 }
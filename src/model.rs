@@ -19,6 +19,12 @@ pub struct Model {
     pub active_at: Option<chrono::NaiveDateTime>,
     /// 書籍の形式 (コミック / ライトノベル / 文庫 / 新書 / 単行本 など)
     pub binding_name: Option<String>,
+    /// Kindle 版の還元ポイント
+    pub point: Option<i32>,
+    /// 著者名 (読書メーターの本ページの最初の著者。著者名の保存導入前に登録された本は None)
+    pub author: Option<String>,
+    /// 外部書店リンクを最後に取得した日時 (リンクが1つもない本も取得済みとして記録する)
+    pub store_links_checked_at: Option<chrono::NaiveDateTime>,
 }
 
 impl ActiveModelBehavior for ActiveModel {}
@@ -40,6 +46,9 @@ impl From<BookMeterBook> for ActiveModel {
             updated_at: Set(chrono::Utc::now().naive_utc()),
             active_at: Set(None),
             binding_name: Set(bookmeter_book.binding_name),
+            point: Set(None),
+            author: Set(bookmeter_book.author),
+            store_links_checked_at: Set(None),
         }
     }
 }
@@ -0,0 +1,368 @@
+//! サイトの HTML の変更 (パーサーの乖離) を検知するための確認
+//!
+//! 取得元ごとに変わりにくい参照ページを1つずつ取得し、本番と同じパーサーで解析して、
+//! 取れるはずの項目が欠けていないかを確認する。結果はメトリクス `bookmeter.parser_check` にも記録する。
+
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use crate::bookmeter::{BookMeterBook, BookMeterClient, BookPage, RetryPolicy};
+use crate::fetcher::Fetcher;
+use crate::http_cache;
+use crate::kindle::Kindle;
+use crate::metrics::MetricsCollector;
+use crate::used_book::{OfferDetails, UsedBookStore, UsedBookStoreRegistry};
+
+/// 確認に使う参照ページ
+///
+/// 既定値はテストでも使っている、在庫・Kindle 版が安定している本。
+/// 環境変数 `PARSER_CHECK_*` で差し替えられる。
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReferencePages {
+    /// 読みたい本リストを確認するユーザー
+    pub bookmeter_user_id: u32,
+    /// 本ページを確認する本 (None の場合は読みたい本リストの最初の本)
+    pub bookmeter_book_id: Option<u32>,
+    /// Kindle 版へのリンクがある紙書籍の ASIN
+    pub amazon_asin: String,
+    /// listasin で価格を確認する Kindle 本の ASIN
+    pub kindle_id: String,
+    /// 中古本サイトで検索する ISBN-13
+    pub isbn13: String,
+}
+
+impl ReferencePages {
+    /// ONE PIECE 110 (紙書籍と Kindle 版)
+    pub const DEFAULT_AMAZON_ASIN: &str = "4088843142";
+    pub const DEFAULT_KINDLE_ID: &str = "B0DJB4QN8R";
+    /// 海に願いを風に祈りをそして君に誓いを (スターツ出版文庫)
+    pub const DEFAULT_ISBN13: &str = "9784813705185";
+
+    #[must_use]
+    pub fn new(bookmeter_user_id: u32) -> Self {
+        Self {
+            bookmeter_user_id,
+            bookmeter_book_id: None,
+            amazon_asin: Self::DEFAULT_AMAZON_ASIN.to_string(),
+            kindle_id: Self::DEFAULT_KINDLE_ID.to_string(),
+            isbn13: Self::DEFAULT_ISBN13.to_string(),
+        }
+    }
+
+    /// `USER_ID` と `PARSER_CHECK_BOOKMETER_BOOK_ID` / `PARSER_CHECK_AMAZON_ASIN` /
+    /// `PARSER_CHECK_KINDLE_ID` / `PARSER_CHECK_ISBN13` から組み立てる
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `USER_ID` is not set or an ID is not a number.
+    pub fn from_env() -> Result<Self> {
+        let mut pages = Self::new(std::env::var("USER_ID")?.parse()?);
+        if let Ok(id) = std::env::var("PARSER_CHECK_BOOKMETER_BOOK_ID") {
+            pages.bookmeter_book_id = Some(id.parse()?);
+        }
+        if let Ok(asin) = std::env::var("PARSER_CHECK_AMAZON_ASIN") {
+            pages.amazon_asin = asin;
+        }
+        if let Ok(kindle_id) = std::env::var("PARSER_CHECK_KINDLE_ID") {
+            pages.kindle_id = kindle_id;
+        }
+        if let Ok(isbn13) = std::env::var("PARSER_CHECK_ISBN13") {
+            pages.isbn13 = isbn13;
+        }
+        Ok(pages)
+    }
+}
+
+/// 参照ページ1件分の確認結果
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CheckOutcome {
+    /// 取れるはずの項目が全て取れた
+    Ok,
+    /// 解析はできたが欠けている項目がある
+    Missing(Vec<&'static str>),
+    /// 取得または解析に失敗した
+    Failed(String),
+}
+
+impl CheckOutcome {
+    fn from_missing(missing: Vec<&'static str>) -> Self {
+        if missing.is_empty() {
+            CheckOutcome::Ok
+        } else {
+            CheckOutcome::Missing(missing)
+        }
+    }
+
+    /// 終了コード・メトリクスに使う値 (0: 正常 / 1: 項目の欠落 / 2: 失敗)
+    #[must_use]
+    pub fn status(&self) -> u8 {
+        match self {
+            CheckOutcome::Ok => 0,
+            CheckOutcome::Missing(_) => 1,
+            CheckOutcome::Failed(_) => 2,
+        }
+    }
+
+    /// 表示用の状態名
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CheckOutcome::Ok => "ok",
+            CheckOutcome::Missing(_) => "missing",
+            CheckOutcome::Failed(_) => "failed",
+        }
+    }
+}
+
+/// 取得元1つ分の確認結果
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParserCheck {
+    /// 取得元 (`bookmeter.book` / `bookmeter.wishlist` / `amazon.kindle_edition` / `listasin.price` / `used_book.{site}`)
+    pub source: String,
+    pub url: String,
+    pub outcome: CheckOutcome,
+}
+
+/// 全ての確認結果をまとめた終了コード (最も悪い結果の `CheckOutcome::status`)
+#[must_use]
+pub fn exit_code(checks: &[ParserCheck]) -> i32 {
+    checks
+        .iter()
+        .map(|check| i32::from(check.outcome.status()))
+        .max()
+        .unwrap_or(0)
+}
+
+/// 全ての取得元の参照ページを確認し、結果をメトリクスに記録する
+///
+/// 中古本サイトは `USED_BOOK_SITES` の設定に関わらず全てのサイトを確認する。
+/// HTTP キャッシュ (`HTTP_CACHE_DIR`) が有効でも TTL 内の保存済みの本文は使わず、
+/// 毎回サーバーに確認した最新のページを解析する。
+///
+/// # Errors
+///
+/// Returns an error if the retry settings of the Bookmeter client are invalid.
+pub async fn check_parsers(
+    pages: &ReferencePages,
+    fetcher: Arc<dyn Fetcher>,
+) -> Result<Vec<ParserCheck>> {
+    let checks = http_cache::always_revalidate(run_checks(pages, fetcher)).await?;
+    let metrics = MetricsCollector::new();
+    for check in &checks {
+        metrics.record_parser_check(&check.source, u64::from(check.outcome.status()));
+    }
+    metrics.flush();
+    Ok(checks)
+}
+
+/// 全ての取得元の参照ページを確認する
+async fn run_checks(pages: &ReferencePages, fetcher: Arc<dyn Fetcher>) -> Result<Vec<ParserCheck>> {
+    let client = BookMeterClient::new(pages.bookmeter_user_id, RetryPolicy::from_env()?)
+        .with_fetcher(Arc::clone(&fetcher));
+    let mut checks = Vec::new();
+
+    let (wishlist, first_book_id) = check_wishlist(&client).await;
+    checks.push(wishlist);
+    match pages.bookmeter_book_id.or(first_book_id) {
+        Some(id) => checks.push(check_bookmeter_book(&client, id).await),
+        None => checks.push(ParserCheck {
+            source: "bookmeter.book".to_string(),
+            url: String::new(),
+            outcome: CheckOutcome::Failed(
+                "no book to check: set PARSER_CHECK_BOOKMETER_BOOK_ID".to_string(),
+            ),
+        }),
+    }
+    checks.push(check_amazon(fetcher.as_ref(), &pages.amazon_asin).await);
+    checks.push(check_listasin(fetcher.as_ref(), &pages.kindle_id).await);
+    for store in UsedBookStoreRegistry::all().iter() {
+        checks.push(check_used_book_store(fetcher.as_ref(), store, &pages.isbn13).await);
+    }
+    Ok(checks)
+}
+
+/// 読みたい本リストの1ページ目を確認し、本ページの確認に使う最初の本の ID も返す
+async fn check_wishlist(client: &BookMeterClient) -> (ParserCheck, Option<u32>) {
+    let url = format!(
+        "https://bookmeter.com/users/{}/books/wish?page=1",
+        client.user_id
+    );
+    let (outcome, first_book_id) = match client.get_book_page_html(1).await {
+        Ok(html) => match BookMeterClient::get_book_ids_from_html(&html) {
+            Ok(ids) => {
+                let missing = if ids.is_empty() {
+                    vec!["book_ids"]
+                } else {
+                    vec![]
+                };
+                (CheckOutcome::from_missing(missing), ids.into_iter().next())
+            }
+            Err(e) => (CheckOutcome::Failed(format!("{e:?}")), None),
+        },
+        Err(e) => (CheckOutcome::Failed(format!("{e:?}")), None),
+    };
+    let check = ParserCheck {
+        source: "bookmeter.wishlist".to_string(),
+        url,
+        outcome,
+    };
+    (check, first_book_id)
+}
+
+/// 本ページと外部書店リンクの API を確認する
+async fn check_bookmeter_book(client: &BookMeterClient, id: u32) -> ParserCheck {
+    let url = format!("https://bookmeter.com/books/{id}");
+    let outcome = async {
+        let doc = BookMeterBook::get_book_page(client, id).await?;
+        let mut missing = missing_book_page_fields(&BookMeterBook::parse_page(&doc, id)?);
+        let links = BookMeterBook::fetch_store_links(client, id).await?;
+        if BookMeterBook::amazon_url_from_links(&links).is_none() {
+            missing.push("amazon_url");
+        }
+        Ok::<_, anyhow::Error>(missing)
+    }
+    .await;
+    ParserCheck {
+        source: "bookmeter.book".to_string(),
+        url,
+        outcome: to_outcome(outcome),
+    }
+}
+
+/// 紙書籍の商品ページから Kindle 版を取得できるかを確認する
+async fn check_amazon(fetcher: &dyn Fetcher, asin: &str) -> ParserCheck {
+    let url = format!("https://www.amazon.co.jp/dp/{asin}");
+    let outcome = async {
+        let page = Kindle::fetch_amazon_page(fetcher, &url).await?;
+        page.kindle_edition()?;
+        Ok(Vec::new())
+    }
+    .await;
+    ParserCheck {
+        source: "amazon.kindle_edition".to_string(),
+        url,
+        outcome: to_outcome(outcome),
+    }
+}
+
+/// listasin から Kindle 本の価格を取得できるかを確認する
+async fn check_listasin(fetcher: &dyn Fetcher, kindle_id: &str) -> ParserCheck {
+    let outcome = Kindle::from_id(fetcher, kindle_id)
+        .await
+        .map(|_| Vec::new());
+    ParserCheck {
+        source: "listasin.price".to_string(),
+        url: format!("https://www.listasin.net/kndlsl/asins/{kindle_id}"),
+        outcome: to_outcome(outcome),
+    }
+}
+
+/// 中古本サイトの検索と商品ページを確認する
+async fn check_used_book_store(
+    fetcher: &dyn Fetcher,
+    store: &dyn UsedBookStore,
+    isbn13: &str,
+) -> ParserCheck {
+    let mut url = String::new();
+    let outcome = async {
+        let Some(hit) = store.search(fetcher, isbn13).await? else {
+            return Ok(vec!["search_hit"]);
+        };
+        url.clone_from(&hit.product_url);
+        let details = match hit.details {
+            Some(details) => details,
+            None => store.fetch_details(fetcher, &hit.product_url).await?,
+        };
+        Ok(missing_offer_fields(&details))
+    }
+    .await;
+    ParserCheck {
+        source: format!("used_book.{}", store.site_key()),
+        url,
+        outcome: to_outcome(outcome),
+    }
+}
+
+fn to_outcome(result: Result<Vec<&'static str>>) -> CheckOutcome {
+    match result {
+        Ok(missing) => CheckOutcome::from_missing(missing),
+        Err(e) => CheckOutcome::Failed(format!("{e:?}")),
+    }
+}
+
+/// 本ページで欠けている項目 (タイトルがない場合は解析自体が失敗する)
+fn missing_book_page_fields(page: &BookPage) -> Vec<&'static str> {
+    let mut missing = Vec::new();
+    if page.binding_name.is_none() {
+        missing.push("binding_name");
+    }
+    if page.author.is_none() {
+        missing.push("author");
+    }
+    missing
+}
+
+/// 中古本の商品ページで欠けている項目
+///
+/// 参照する本は在庫が安定しているものを選ぶため、価格が取れない場合も欠落とみなす。
+fn missing_offer_fields(details: &OfferDetails) -> Vec<&'static str> {
+    let mut missing = Vec::new();
+    if details.price.is_none() {
+        missing.push("price");
+    }
+    missing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::used_book::{netoff, valuebooks};
+
+    fn check(source: &str, outcome: CheckOutcome) -> ParserCheck {
+        ParserCheck {
+            source: source.to_string(),
+            url: String::new(),
+            outcome,
+        }
+    }
+
+    #[test]
+    fn test_exit_code() {
+        assert_eq!(exit_code(&[]), 0);
+        let ok = check("used_book.netoff", CheckOutcome::Ok);
+        let missing = check("bookmeter.book", CheckOutcome::Missing(vec!["author"]));
+        let failed = check(
+            "listasin.price",
+            CheckOutcome::Failed("timeout".to_string()),
+        );
+        assert_eq!(exit_code(std::slice::from_ref(&ok)), 0);
+        assert_eq!(exit_code(&[ok.clone(), missing.clone()]), 1);
+        assert_eq!(exit_code(&[failed, ok, missing]), 2);
+    }
+
+    #[test]
+    fn test_missing_offer_fields() -> Result<()> {
+        let html = include_str!("../tests/fixtures/used_book/netoff_product.html");
+        assert!(missing_offer_fields(&netoff::parse_product(html)?).is_empty());
+        let html = include_str!("../tests/fixtures/used_book/valuebooks_product_oos.html");
+        assert_eq!(
+            missing_offer_fields(&valuebooks::parse_product(html)?),
+            vec!["price"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_book_page_fields() -> Result<()> {
+        let doc = r#"<html><body><h1 class="inner__title">タイトル</h1></body></html>"#;
+        let page = BookMeterBook::parse_page(doc, 1)?;
+        assert_eq!(page.title, "タイトル");
+        assert_eq!(
+            missing_book_page_fields(&page),
+            vec!["binding_name", "author"]
+        );
+        assert!(BookMeterBook::parse_page("<html></html>", 1).is_err());
+        Ok(())
+    }
+}
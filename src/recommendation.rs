@@ -0,0 +1,264 @@
+//! 本ごとに Kindle 版・Kindle Unlimited・中古本サイトのうち最も安く買える先を選ぶ
+
+use serde::{Deserialize, Serialize};
+
+use crate::basket::KINDLE_CHANNEL;
+use crate::{model, used_book_offer};
+
+/// Kindle Unlimited (会員は 0 円) を表すチャネル名
+pub const KINDLE_UNLIMITED_CHANNEL: &str = "kindle_unlimited";
+
+/// 比較の条件
+///
+/// サーバーではクエリ文字列 (`?kindle_unlimited=true&include_shipping=true`) から読む。
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct RecommendationOptions {
+    /// Kindle Unlimited の会員として、読み放題対象の本を 0 円とみなす
+    #[serde(default)]
+    pub kindle_unlimited: bool,
+    /// 中古本オファーを送料込みの価格 (`landed_price`) で比べる
+    #[serde(default)]
+    pub include_shipping: bool,
+}
+
+/// 買える先1つ分の価格
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelPrice {
+    /// `kindle` / `kindle_unlimited` または `used_book_offers.site` の値
+    pub channel: String,
+    /// 比較に使った価格 (円)
+    pub price: i32,
+    pub url: Option<String>,
+    /// 新品 (`new`) / 中古 (`used`) のどちらの出品の価格か (`used_book_offers.listing_type`。Kindle 版は None)
+    pub listing_type: Option<String>,
+}
+
+/// 1冊分の最安の買い先
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelRecommendation {
+    pub bookmeter_id: i64,
+    pub title: String,
+    pub cheapest: ChannelPrice,
+    /// 2番目に安い買い先 (買える先が1つしかない場合は None)
+    pub runner_up: Option<ChannelPrice>,
+    /// 2番目に安い買い先との差額 (円)
+    pub savings: Option<i32>,
+}
+
+/// Kindle 版のポイント還元を差し引いた実質価格 (価格が未取得の場合は None)
+#[must_use]
+pub fn kindle_net_price(book: &model::Model) -> Option<i32> {
+    book.price.map(|price| price - book.point.unwrap_or(0))
+}
+
+/// 1冊分の買える先を安い順に並べる
+///
+/// `offers` のうち対象の本の在庫のあるオファーだけを使う。
+#[must_use]
+pub fn channel_prices(
+    book: &model::Model,
+    offers: &[used_book_offer::Model],
+    options: RecommendationOptions,
+) -> Vec<ChannelPrice> {
+    let kindle_url = book
+        .kindle_id
+        .as_ref()
+        .map(|kindle_id| format!("https://www.amazon.co.jp/dp/{kindle_id}"));
+    let mut prices = Vec::new();
+    if let (Some(url), Some(price)) = (&kindle_url, kindle_net_price(book)) {
+        prices.push(ChannelPrice {
+            channel: KINDLE_CHANNEL.to_string(),
+            price,
+            url: Some(url.clone()),
+            listing_type: None,
+        });
+    }
+    if options.kindle_unlimited && book.is_kindle_unlimited {
+        prices.push(ChannelPrice {
+            channel: KINDLE_UNLIMITED_CHANNEL.to_string(),
+            price: 0,
+            url: kindle_url,
+            listing_type: None,
+        });
+    }
+    prices.extend(
+        offers
+            .iter()
+            .filter(|offer| offer.bookmeter_id == book.bookmeter_id && offer.in_stock)
+            .filter_map(|offer| {
+                // 送料の設定がないサイトは送料込みの価格が分からないため本体価格で比べる
+                let price = if options.include_shipping {
+                    offer.landed_price.or(offer.price)
+                } else {
+                    offer.price
+                }?;
+                Some(ChannelPrice {
+                    channel: offer.site.clone(),
+                    price,
+                    url: offer.product_url.clone(),
+                    listing_type: offer.listing_type.clone(),
+                })
+            }),
+    );
+    prices.sort_by(|a, b| {
+        a.price
+            .cmp(&b.price)
+            .then_with(|| a.channel.cmp(&b.channel))
+    });
+    prices
+}
+
+/// 1冊分の最安の買い先と、2番目に安い買い先との差額を求める
+///
+/// 買える先がない場合は `None` を返す。
+#[must_use]
+pub fn recommend(
+    book: &model::Model,
+    offers: &[used_book_offer::Model],
+    options: RecommendationOptions,
+) -> Option<ChannelRecommendation> {
+    let mut prices = channel_prices(book, offers, options).into_iter();
+    let cheapest = prices.next()?;
+    let runner_up = prices.next();
+    let savings = runner_up
+        .as_ref()
+        .map(|runner_up| runner_up.price - cheapest.price);
+    Some(ChannelRecommendation {
+        bookmeter_id: book.bookmeter_id,
+        title: book.title.clone(),
+        cheapest,
+        runner_up,
+        savings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book() -> model::Model {
+        model::Model {
+            bookmeter_id: 1,
+            amazon_url: "https://www.amazon.co.jp/dp/4813705189".to_string(),
+            kindle_id: Some("B07GSTLJ7H".to_string()),
+            title: "海に願いを風に祈りをそして君に誓いを".to_string(),
+            basis_price: Some(693),
+            price: Some(600),
+            discount_rate: Some(0.2),
+            is_kindle_unlimited: true,
+            updated_at: chrono::NaiveDateTime::default(),
+            active_at: None,
+            binding_name: Some("文庫".to_string()),
+            point: Some(50),
+            author: None,
+            store_links_checked_at: None,
+        }
+    }
+
+    fn offer(site: &str, price: i32, landed_price: Option<i32>) -> used_book_offer::Model {
+        used_book_offer::Model {
+            bookmeter_id: 1,
+            site: site.to_string(),
+            product_id: Some("1".to_string()),
+            product_url: Some(format!("https://example.com/{site}")),
+            price: Some(price),
+            condition: None,
+            condition_grade: "unknown".to_string(),
+            in_stock: true,
+            stock_count: None,
+            listing_type: None,
+            offer_count: None,
+            match_score: None,
+            landed_price,
+            updated_at: chrono::NaiveDateTime::default(),
+        }
+    }
+
+    #[test]
+    fn test_recommend_used_without_shipping() {
+        let offers = vec![offer("bookoff", 110, Some(550)), offer("netoff", 300, None)];
+        let recommendation = recommend(&book(), &offers, RecommendationOptions::default());
+        let recommendation = recommendation.as_ref();
+        assert_eq!(
+            recommendation.map(|r| r.cheapest.channel.as_str()),
+            Some("bookoff")
+        );
+        assert_eq!(
+            recommendation.and_then(|r| r.runner_up.as_ref().map(|r| r.channel.as_str())),
+            Some("netoff")
+        );
+        assert_eq!(recommendation.and_then(|r| r.savings), Some(190));
+    }
+
+    #[test]
+    fn test_recommend_with_shipping_prefers_kindle_net_of_points() {
+        let offers = vec![offer("bookoff", 110, Some(550))];
+        let options = RecommendationOptions {
+            include_shipping: true,
+            ..RecommendationOptions::default()
+        };
+        let prices = channel_prices(&book(), &offers, options);
+        assert_eq!(
+            prices
+                .iter()
+                .map(|p| (p.channel.as_str(), p.price))
+                .collect::<Vec<_>>(),
+            vec![("bookoff", 550), ("kindle", 550)]
+        );
+        let other_book = model::Model {
+            point: Some(60),
+            ..book()
+        };
+        let recommendation = recommend(&other_book, &offers, options);
+        assert_eq!(
+            recommendation.map(|r| (r.cheapest.channel, r.savings)),
+            Some(("kindle".to_string(), Some(10)))
+        );
+    }
+
+    #[test]
+    fn test_recommend_keeps_listing_type() {
+        let new = used_book_offer::Model {
+            listing_type: Some("new".to_string()),
+            ..offer("bookoff", 500, None)
+        };
+        let recommendation = recommend(&book(), &[new], RecommendationOptions::default());
+        assert_eq!(
+            recommendation.map(|r| (r.cheapest.channel, r.cheapest.listing_type)),
+            Some(("bookoff".to_string(), Some("new".to_string())))
+        );
+    }
+
+    #[test]
+    fn test_recommend_kindle_unlimited() {
+        let options = RecommendationOptions {
+            kindle_unlimited: true,
+            ..RecommendationOptions::default()
+        };
+        let recommendation = recommend(&book(), &[], options);
+        assert_eq!(
+            recommendation.map(|r| (r.cheapest.channel, r.cheapest.price, r.savings)),
+            Some(("kindle_unlimited".to_string(), 0, Some(550)))
+        );
+    }
+
+    #[test]
+    fn test_recommend_none() {
+        let book = model::Model {
+            kindle_id: None,
+            price: None,
+            is_kindle_unlimited: false,
+            ..book()
+        };
+        let out_of_stock = used_book_offer::Model {
+            in_stock: false,
+            ..offer("bookoff", 110, None)
+        };
+        assert_eq!(
+            recommend(&book, &[out_of_stock], RecommendationOptions::default()),
+            None
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// 中古本サイトごとの送料の設定 (`init.sql` の初期値を DB 上で変更して使う)
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[sea_orm(table_name = "shipping_fees")]
+pub struct Model {
+    /// `used_book_offers.site` の値
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub site: String,
+    /// 1回の注文あたりの送料 (円)
+    pub fee: i32,
+    /// この金額以上の注文で送料無料になる (円)。常に送料がかかる場合は None
+    pub free_threshold: Option<i32>,
+    /// 会員の送料 (円)。会員向けの送料がない場合は None
+    pub member_fee: Option<i32>,
+    /// 会員が送料無料になる金額 (円)
+    pub member_free_threshold: Option<i32>,
+    /// このサイトの会員として注文するか
+    pub is_member: bool,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+#[derive(Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl Model {
+    /// 商品代金の合計 `subtotal` (円) の注文にかかる送料
+    ///
+    /// 会員として注文する設定で会員向けの送料がある場合は、会員の送料・送料無料の金額を使う。
+    #[must_use]
+    pub fn fee_for(&self, subtotal: i32) -> i32 {
+        let (fee, free_threshold) = match self.member_fee {
+            Some(member_fee) if self.is_member => (member_fee, self.member_free_threshold),
+            _ => (self.fee, self.free_threshold),
+        };
+        if free_threshold.is_some_and(|threshold| subtotal >= threshold) {
+            0
+        } else {
+            fee
+        }
+    }
+
+    /// 1冊だけ注文した場合の送料込みの価格
+    #[must_use]
+    pub fn landed_price(&self, price: i32) -> i32 {
+        price + self.fee_for(price)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule() -> Model {
+        Model {
+            site: "bookoff".to_string(),
+            fee: 440,
+            free_threshold: Some(2000),
+            member_fee: Some(0),
+            member_free_threshold: None,
+            is_member: false,
+        }
+    }
+
+    #[test]
+    fn test_fee_for_threshold() {
+        let rule = rule();
+        assert_eq!(rule.fee_for(110), 440);
+        assert_eq!(rule.fee_for(2000), 0);
+        assert_eq!(rule.landed_price(110), 550);
+    }
+
+    #[test]
+    fn test_fee_for_member() {
+        let rule = Model {
+            is_member: true,
+            ..rule()
+        };
+        assert_eq!(rule.fee_for(110), 0);
+    }
+
+    #[test]
+    fn test_fee_for_member_without_member_fee() {
+        let rule = Model {
+            is_member: true,
+            member_fee: None,
+            ..rule()
+        };
+        assert_eq!(rule.fee_for(110), 440);
+    }
+
+    #[test]
+    fn test_fee_for_flat() {
+        let rule = Model {
+            free_threshold: None,
+            ..rule()
+        };
+        assert_eq!(rule.fee_for(10_000), 440);
+    }
+}
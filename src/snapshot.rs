@@ -0,0 +1,275 @@
+//! パーサーが失敗したページの HTML の保存と、保存した HTML での再解析
+//!
+//! 環境変数 `HTML_SNAPSHOT_DIR` を設定した場合だけ、解析に失敗したページを
+//! URL・取得時刻・パーサー名・エラーと一緒に gzip 圧縮した JSON (`*.json.gz`) で保存する。
+//! 保存した HTML は `bookmeter_discounts snapshots` コマンドで一覧・再解析でき、
+//! `snapshots show` の出力をそのままテスト用の fixture にできる。
+
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use anyhow::Result;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+
+use crate::bookmeter::{BookMeterBook, BookMeterClient};
+use crate::kindle::Kindle;
+use crate::used_book::{amazon, bookoff, mottainai, netoff, surugaya, valuebooks};
+
+/// 保存するファイルの拡張子
+const EXTENSION: &str = ".json.gz";
+
+/// 解析に失敗したページ1件分
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    /// 解析したパーサーの名前 (`bookoff.product` など。[`Snapshot::reparse`] が扱う名前)
+    pub parser: String,
+    pub url: String,
+    pub captured_at: chrono::NaiveDateTime,
+    /// 解析時のエラー
+    pub error: String,
+    pub html: String,
+}
+
+/// 保存先ディレクトリ
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SnapshotArchive {
+    dir: PathBuf,
+}
+
+impl SnapshotArchive {
+    #[must_use]
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// 環境変数 `HTML_SNAPSHOT_DIR` の保存先 (未設定の場合は None)
+    #[must_use]
+    pub fn from_env() -> Option<Self> {
+        std::env::var("HTML_SNAPSHOT_DIR").ok().map(Self::new)
+    }
+
+    /// 環境変数から1度だけ組み立てた、プロセス全体で共有する保存先
+    pub fn global() -> Option<&'static Self> {
+        static GLOBAL: OnceLock<Option<SnapshotArchive>> = OnceLock::new();
+        GLOBAL.get_or_init(Self::from_env).as_ref()
+    }
+
+    /// スナップショットを保存し、保存したファイルのパスを返す
+    ///
+    /// # Errors
+    ///
+    /// ディレクトリの作成・圧縮・書き込みに失敗した場合にエラーを返す。
+    pub async fn save(&self, snapshot: &Snapshot) -> Result<PathBuf> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&serde_json::to_vec(snapshot)?)?;
+        let bytes = encoder.finish()?;
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let path = self.dir.join(format!(
+            "{}_{}{EXTENSION}",
+            snapshot.captured_at.format("%Y%m%d%H%M%S%3f"),
+            snapshot.parser
+        ));
+        tokio::fs::write(&path, bytes).await?;
+        Ok(path)
+    }
+
+    /// 保存したスナップショットのファイルを古い順に返す
+    ///
+    /// # Errors
+    ///
+    /// ディレクトリを読めない場合にエラーを返す (ディレクトリがない場合は空)。
+    pub fn files(&self) -> Result<Vec<PathBuf>> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut files = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.ends_with(EXTENSION))
+            {
+                files.push(path);
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+}
+
+impl Snapshot {
+    /// 保存したスナップショットを読む
+    ///
+    /// # Errors
+    ///
+    /// ファイルの読み込み・展開・JSON の解析に失敗した場合にエラーを返す。
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut json = Vec::new();
+        GzDecoder::new(std::fs::File::open(path)?).read_to_end(&mut json)?;
+        Ok(serde_json::from_slice(&json)?)
+    }
+
+    /// 現在のパーサーで解析し直し、結果を1行の文字列で返す
+    ///
+    /// # Errors
+    ///
+    /// 解析に失敗した場合、または再解析できないパーサー名の場合にエラーを返す。
+    pub fn reparse(&self) -> Result<String> {
+        let html = self.html.as_str();
+        let summary = match self.parser.as_str() {
+            "bookmeter.wishlist" => {
+                let page = url::Url::parse(&self.url)
+                    .ok()
+                    .and_then(|url| {
+                        url.query_pairs()
+                            .find(|(key, _)| key == "page")
+                            .and_then(|(_, page)| page.parse().ok())
+                    })
+                    .unwrap_or(1);
+                format!("{:?}", BookMeterClient::parse_wishlist_page(html, page)?)
+            }
+            "bookmeter.book" => {
+                let id = self
+                    .url
+                    .trim_end_matches('/')
+                    .rsplit('/')
+                    .next()
+                    .and_then(|id| id.parse().ok())
+                    .ok_or_else(|| anyhow::anyhow!("Invalid Bookmeter URL: {}", self.url))?;
+                format!("{:?}", BookMeterBook::parse_page(html, id)?)
+            }
+            "amazon.kindle_edition" => {
+                let id = Kindle::convert_amazon_url_to_id(&self.url)?;
+                format!("{:?}", Kindle::parse_kindle_edition(html, &id, &self.url)?)
+            }
+            "amazon.used_offer" => format!("{:?}", amazon::parse_used_offer(html)?),
+            "listasin.price" => format!("{:?}", Kindle::parse_listasin(html)?),
+            "bookoff.search" => format!("{:?}", bookoff::parse_search(html)?),
+            "bookoff.title_search" => format!("{:?}", bookoff::parse_title_search(html)?),
+            "bookoff.product" => format!("{:?}", bookoff::parse_product(html)?),
+            "valuebooks.product" => format!("{:?}", valuebooks::parse_product(html)?),
+            "netoff.search" => format!("{:?}", netoff::parse_search(html)?),
+            "netoff.title_search" => format!("{:?}", netoff::parse_title_search(html)?),
+            "netoff.product" => format!("{:?}", netoff::parse_product(html)?),
+            "surugaya.search" => format!("{:?}", surugaya::parse_search(html)?),
+            "surugaya.title_search" => format!("{:?}", surugaya::parse_title_search(html)?),
+            "surugaya.product" => format!("{:?}", surugaya::parse_product(html)?),
+            "mottainai.search" => format!("{:?}", mottainai::parse_search(html)?),
+            "mottainai.title_search" => format!("{:?}", mottainai::parse_title_search(html)?),
+            "mottainai.product" => format!("{:?}", mottainai::parse_product(html)?),
+            parser => return Err(anyhow::anyhow!("Unknown parser: {parser}")),
+        };
+        Ok(summary)
+    }
+}
+
+/// 解析に失敗したページを共有の保存先 (設定されている場合) に保存する
+///
+/// 保存に失敗しても呼び出し元の処理は続けられるよう、警告を出すだけにする。
+pub async fn record(parser: &str, url: &str, html: &str, error: &anyhow::Error) {
+    let Some(archive) = SnapshotArchive::global() else {
+        return;
+    };
+    let snapshot = Snapshot {
+        parser: parser.to_string(),
+        url: url.to_string(),
+        captured_at: chrono::Utc::now().naive_utc(),
+        error: format!("{error:?}"),
+        html: html.to_string(),
+    };
+    match archive.save(&snapshot).await {
+        Ok(path) => tracing::info!("saved HTML snapshot of {url} to {}", path.display()),
+        Err(e) => tracing::warn!("failed to save HTML snapshot of {url}: {e:?}"),
+    }
+}
+
+/// `parse` で HTML を解析し、失敗した場合は HTML を保存してからエラーを返す
+///
+/// # Errors
+///
+/// `parse` が失敗した場合にそのエラーを返す。
+pub async fn parse_or_record<T>(
+    parser: &str,
+    url: &str,
+    html: &str,
+    parse: impl FnOnce(&str) -> Result<T>,
+) -> Result<T> {
+    let result = parse(html);
+    if let Err(e) = &result {
+        record(parser, url, html, e).await;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(parser: &str, url: &str, html: &str) -> Snapshot {
+        Snapshot {
+            parser: parser.to_string(),
+            url: url.to_string(),
+            captured_at: chrono::NaiveDateTime::default(),
+            error: "Failed to parse NetOff product page".to_string(),
+            html: html.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_save_and_load() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!(
+            "bookmeter_discounts_snapshot_{}",
+            std::process::id()
+        ));
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+        let archive = SnapshotArchive::new(&dir);
+        assert!(archive.files()?.is_empty());
+
+        let saved = snapshot(
+            "netoff.product",
+            "https://www.netoff.co.jp/detail/0012822282/",
+            "<html><body>メンテナンス中</body></html>",
+        );
+        let path = archive.save(&saved).await?;
+        assert_eq!(archive.files()?, vec![path.clone()]);
+        let loaded = Snapshot::load(&path)?;
+        assert_eq!(loaded, saved);
+        assert!(loaded.reparse().is_err());
+
+        tokio::fs::remove_dir_all(&dir).await?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_reparse_fixture() -> Result<()> {
+        let html = include_str!("../tests/fixtures/used_book/netoff_product.html");
+        let summary = snapshot(
+            "netoff.product",
+            "https://www.netoff.co.jp/detail/0012822282/",
+            html,
+        )
+        .reparse()?;
+        assert!(summary.contains("price: Some(220)"));
+        assert!(snapshot("unknown.parser", "", html).reparse().is_err());
+
+        // 読みたい本リストは URL のページ番号で空のページを失敗とするかを決める
+        let wishlist = snapshot(
+            "bookmeter.wishlist",
+            "https://bookmeter.com/users/1/books/wish?page=1",
+            "<html><body></body></html>",
+        );
+        assert!(wishlist.reparse().is_err());
+        let last_page = Snapshot {
+            url: "https://bookmeter.com/users/1/books/wish?page=3".to_string(),
+            ..wishlist
+        };
+        assert_eq!(last_page.reparse()?, "{}");
+        Ok(())
+    }
+}
@@ -0,0 +1,200 @@
+//! Amazon マーケットプレイスの中古品 (紙書籍) パーサー
+//!
+//! Kindle 版の取得で既にダウンロードしている紙書籍の商品ページ
+//! (`Kindle::get_html_by_amazon_id`) から「中古品」の最安値と出品数を読むため、
+//! 他サイトと違って検索・商品ページ取得のリクエストは行わない。
+
+use anyhow::Result;
+use scraper::{Html, Selector};
+
+use super::OfferUpdate;
+
+/// `used_book_offers.site` に保存するキー
+pub const SITE_KEY: &str = "amazon_used";
+
+/// 紙書籍の商品ページに表示される中古品の出品情報
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AmazonUsedOffer {
+    /// 中古品の最安値 (円)
+    pub price: Option<i32>,
+    /// 中古品の出品数
+    pub offer_count: Option<i32>,
+}
+
+impl AmazonUsedOffer {
+    /// DB 更新用の結果に変換する
+    #[must_use]
+    pub fn to_update(&self, asin: &str) -> OfferUpdate {
+        OfferUpdate {
+            product_id: Some(asin.to_string()),
+            product_url: Some(offer_listing_url(asin)),
+            price: self.price,
+            condition: Some("中古品".to_string()),
+            in_stock: self.price.is_some() || self.offer_count.is_some_and(|n| n > 0),
+            stock_count: None,
+            listing_type: None,
+            offer_count: self.offer_count,
+            variants: None,
+            match_score: None,
+        }
+    }
+}
+
+/// 中古品の出品一覧ページの URL
+#[must_use]
+pub fn offer_listing_url(asin: &str) -> String {
+    format!("https://www.amazon.co.jp/gp/offer-listing/{asin}/?condition=used")
+}
+
+/// 紙書籍の商品ページ HTML から中古品の最安値と出品数を取り出す
+///
+/// 形式の切り替えボタン (`#tmmSwatches`) のうち選択中の形式にある
+/// 「中古品 (12) ￥330 より」のようなリンクを読み、なければ出品一覧へのリンク
+/// (`#olpLinkWidget_feature_div`) を読む。
+/// 中古品の出品がない場合や Kindle 版のページの場合は `Ok(None)` を返す。
+///
+/// # Errors
+///
+/// 中古品のリンクはあるが価格も出品数も読めない場合 (ページ構造の変更) にエラーを返す。
+pub fn parse_used_offer(html: &str) -> Result<Option<AmazonUsedOffer>> {
+    let doc = Html::parse_document(html);
+    let selectors = [
+        ".swatchElement.selected .olp-used a",
+        "#olpLinkWidget_feature_div a",
+    ];
+    selectors
+        .iter()
+        .filter_map(|s| Selector::parse(s).ok())
+        .flat_map(|selector| {
+            doc.select(&selector)
+                .map(|e| e.text().collect::<String>())
+                .collect::<Vec<_>>()
+        })
+        .find(|text| text.contains("中古"))
+        .map(|text| {
+            let offer = parse_used_offer_text(&text);
+            if offer.price.is_none() && offer.offer_count.is_none() {
+                return Err(anyhow::anyhow!(
+                    "Failed to parse Amazon used offer link: {}",
+                    text.split_whitespace().collect::<Vec<_>>().join(" ")
+                ));
+            }
+            Ok(offer)
+        })
+        .transpose()
+}
+
+/// 「中古品 (12) ￥330 より」「中古品の出品：12￥330より」のような文言から価格と出品数を読む
+fn parse_used_offer_text(text: &str) -> AmazonUsedOffer {
+    let text: String = text.split_whitespace().collect();
+    let price = text
+        .split_once('￥')
+        .and_then(|(_, rest)| leading_number(rest));
+    let offer_count = text
+        .split_once('(')
+        .and_then(|(_, rest)| leading_number(rest))
+        .or_else(|| {
+            text.split_once('：')
+                .and_then(|(_, rest)| leading_number(rest))
+        });
+    AmazonUsedOffer { price, offer_count }
+}
+
+/// 先頭のカンマ区切りの数字を読む
+fn leading_number(text: &str) -> Option<i32> {
+    text.chars()
+        .take_while(|c| c.is_ascii_digit() || *c == ',')
+        .filter(char::is_ascii_digit)
+        .collect::<String>()
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 紙書籍ページの #tmmSwatches から抽出した、選択中の文庫の swatch の断片
+    const PAPER_SWATCH_FRAGMENT: &str = r#"
+        <div id="tmmSwatches">
+          <div id="tmm-grid-swatch-KINDLE" class="swatchElement unselected">
+            <span class="slot-price"><span aria-label="￥543">￥543</span></span>
+          </div>
+          <div id="tmm-grid-swatch-PAPERBACK_BUNKO" class="swatchElement selected">
+            <span class="slot-title">文庫</span>
+            <span class="slot-price"><span aria-label="￥693">￥693</span></span>
+            <span class="olp-used olp-link">
+              <a class="a-size-mini a-link-normal" href="/gp/offer-listing/4813705189/ref=tmm_pap_used_olp_0?condition=used">
+                中古品 (12) <span class="a-color-price">￥1,330</span> より
+              </a>
+            </span>
+            <span class="olp-new olp-link">
+              <a class="a-size-mini a-link-normal" href="/gp/offer-listing/4813705189/ref=tmm_pap_new_olp_0?condition=new">
+                新品 (3) <span class="a-color-price">￥693</span> より
+              </a>
+            </span>
+          </div>
+        </div>
+    "#;
+
+    // 出品一覧へのリンクだけがあるページの断片
+    const OLP_LINK_FRAGMENT: &str = r#"
+        <div id="olpLinkWidget_feature_div">
+          <a href="/gp/offer-listing/4167158054/ref=dp_olp_used?condition=used">
+            <span>中古品の出品：5</span><span class="a-color-price">￥300</span><span>より</span>
+          </a>
+        </div>
+    "#;
+
+    #[test]
+    fn test_parse_used_offer_from_swatch() -> Result<()> {
+        assert_eq!(
+            parse_used_offer(PAPER_SWATCH_FRAGMENT)?,
+            Some(AmazonUsedOffer {
+                price: Some(1330),
+                offer_count: Some(12),
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_used_offer_from_olp_link() -> Result<()> {
+        assert_eq!(
+            parse_used_offer(OLP_LINK_FRAGMENT)?,
+            Some(AmazonUsedOffer {
+                price: Some(300),
+                offer_count: Some(5),
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_used_offer_not_found() -> Result<()> {
+        // Kindle 版のページなど、中古品の出品がない場合
+        let html = r#"<div id="tmm-grid-swatch-KINDLE" class="swatchElement selected"></div>"#;
+        assert_eq!(parse_used_offer(html)?, None);
+        // 中古品のリンクはあるが価格も出品数も読めない場合は解析の失敗
+        let html = r#"<div id="olpLinkWidget_feature_div"><a>中古品の出品を見る</a></div>"#;
+        assert!(parse_used_offer(html).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_update() {
+        let update = AmazonUsedOffer {
+            price: Some(300),
+            offer_count: Some(5),
+        }
+        .to_update("4167158054");
+        assert_eq!(update.product_id.as_deref(), Some("4167158054"));
+        assert_eq!(
+            update.product_url.as_deref(),
+            Some("https://www.amazon.co.jp/gp/offer-listing/4167158054/?condition=used")
+        );
+        assert_eq!(update.price, Some(300));
+        assert_eq!(update.offer_count, Some(5));
+        assert!(update.in_stock);
+    }
+}
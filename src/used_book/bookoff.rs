@@ -2,33 +2,151 @@
 //!
 //! - 検索: `https://shopping.bookoff.co.jp/search/keyword/{isbn13}` (サーバサイドレンダリング)
 //! - 商品ページ: `https://shopping.bookoff.co.jp/used/{product_id}` (JSON-LD 埋め込みあり)
+//!   新品の出品は同じ商品 ID の `/new/{product_id}` で、ページ内の「中古 / 新品」ボタンで切り替わる
+
+use std::time::Duration;
 
 use anyhow::Result;
 use scraper::{Html, Selector};
+use tokio::time::sleep;
+
+use futures::future::BoxFuture;
 
-use super::{http_client, OfferDetails, SearchHit};
+use super::{
+    title_match, ConditionVariant, ListingLink, ListingType, OfferDetails, SearchHit,
+    TitleCandidate, UsedBookStore,
+};
+use crate::fetcher::Fetcher;
 
 const BASE_URL: &str = "https://shopping.bookoff.co.jp";
 
+/// リクエストの最小間隔 (検索と、商品ページの中古・新品の2つの出品の取得の間にも空ける)
+const MIN_REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// BOOKOFF
+pub struct Bookoff;
+
+impl UsedBookStore for Bookoff {
+    fn site_key(&self) -> &'static str {
+        "bookoff"
+    }
+
+    fn default_base_url(&self) -> &'static str {
+        BASE_URL
+    }
+
+    fn min_request_interval(&self) -> Duration {
+        MIN_REQUEST_INTERVAL
+    }
+
+    fn search<'a>(
+        &'a self,
+        fetcher: &'a dyn Fetcher,
+        isbn13: &'a str,
+    ) -> BoxFuture<'a, Result<Option<SearchHit>>> {
+        Box::pin(search(fetcher, isbn13))
+    }
+
+    fn search_by_title<'a>(
+        &'a self,
+        fetcher: &'a dyn Fetcher,
+        title: &'a str,
+        author: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<Vec<TitleCandidate>>> {
+        Box::pin(search_by_title(fetcher, title, author))
+    }
+
+    fn fetch_details<'a>(
+        &'a self,
+        fetcher: &'a dyn Fetcher,
+        product_url: &'a str,
+    ) -> BoxFuture<'a, Result<OfferDetails>> {
+        Box::pin(fetch_details(fetcher, product_url))
+    }
+}
+
 /// ISBN-13 で検索して最初の商品を取得する
 ///
 /// # Errors
 ///
 /// HTTP リクエストまたは HTML の解析に失敗した場合にエラーを返す。
-pub async fn search(isbn13: &str) -> Result<Option<SearchHit>> {
+pub async fn search(fetcher: &dyn Fetcher, isbn13: &str) -> Result<Option<SearchHit>> {
     let url = format!("{BASE_URL}/search/keyword/{isbn13}");
-    let html = http_client()?.get(&url).send().await?.text().await?;
-    parse_search(&html)
+    let html = super::get_text(fetcher, &url).await?;
+    crate::snapshot::parse_or_record("bookoff.search", url.as_str(), &html, parse_search).await
+}
+
+/// タイトルと著者名で検索して検索結果の商品を全て取得する
+///
+/// # Errors
+///
+/// HTTP リクエストまたは HTML の解析に失敗した場合にエラーを返す。
+pub async fn search_by_title(
+    fetcher: &dyn Fetcher,
+    title: &str,
+    author: Option<&str>,
+) -> Result<Vec<TitleCandidate>> {
+    let mut url = url::Url::parse(BASE_URL)?;
+    url.path_segments_mut()
+        .map_err(|()| anyhow::anyhow!("Invalid base URL: {BASE_URL}"))?
+        .extend([
+            "search",
+            "keyword",
+            &title_match::search_query(title, author),
+        ]);
+    let html = super::get_text(fetcher, url.as_str()).await?;
+    crate::snapshot::parse_or_record(
+        "bookoff.title_search",
+        url.as_str(),
+        &html,
+        parse_title_search,
+    )
+    .await
 }
 
 /// 商品ページから在庫・価格を取得する
 ///
+/// もう一方の出品 (中古のページなら新品、新品のページなら中古) があればそれも取得し、
+/// 両方を `variants` に入れる。もう一方の出品の取得に失敗した場合は警告を出して無視する。
+///
 /// # Errors
 ///
 /// HTTP リクエストまたは HTML の解析に失敗した場合にエラーを返す。
-pub async fn fetch_details(product_url: &str) -> Result<OfferDetails> {
-    let html = http_client()?.get(product_url).send().await?.text().await?;
-    parse_product(&html)
+pub async fn fetch_details(fetcher: &dyn Fetcher, product_url: &str) -> Result<OfferDetails> {
+    let html = super::get_text(fetcher, product_url).await?;
+    let details =
+        crate::snapshot::parse_or_record("bookoff.product", product_url, &html, parse_product)
+            .await?;
+    let mut other = None;
+    if let Some(url) = parse_other_listing_url(&html)? {
+        sleep(MIN_REQUEST_INTERVAL).await;
+        match fetch_listing(fetcher, &url).await {
+            Ok(details) => other = Some(details),
+            Err(e) => tracing::warn!("failed to fetch other BOOKOFF listing {url}: {e:?}"),
+        }
+    }
+    Ok(with_listing_variants(details, other.as_ref()))
+}
+
+/// 出品1つ分の商品ページを取得して解析する
+///
+/// オファー本体をこの出品に置き換えられるよう、`listing` に出品の商品 ID と URL を入れる。
+async fn fetch_listing(fetcher: &dyn Fetcher, url: &str) -> Result<OfferDetails> {
+    let html = super::get_text(fetcher, url).await?;
+    let details =
+        crate::snapshot::parse_or_record("bookoff.product", url, &html, parse_product).await?;
+    Ok(OfferDetails {
+        listing: Some(listing_link(url)),
+        ..details
+    })
+}
+
+/// 出品ページの URL (`.../used/{product_id}` または `.../new/{product_id}`) から商品 ID と URL を作る
+fn listing_link(url: &str) -> ListingLink {
+    ListingLink {
+        product_id: url.rsplit('/').next().unwrap_or(url).to_string(),
+        product_url: url.to_string(),
+    }
 }
 
 /// 検索結果 HTML から最初の商品の ID と URL を取り出す
@@ -70,20 +188,85 @@ pub fn parse_search(html: &str) -> Result<Option<SearchHit>> {
     }))
 }
 
+/// 検索結果 HTML から全ての商品の ID / URL とタイトル・著者名を取り出す
+///
+/// 同じ商品の中古と新品が並ぶ場合は中古の方だけを返す。
+///
+/// # Errors
+///
+/// セレクタの解析に失敗した場合にエラーを返す。
+pub fn parse_title_search(html: &str) -> Result<Vec<TitleCandidate>> {
+    let doc = Html::parse_document(html);
+    let item_selector = Selector::parse("a.productItem__link")
+        .map_err(|e| anyhow::anyhow!("Failed to parse selector: {e:?}"))?;
+    let title_selector = Selector::parse(".productItem__title")
+        .map_err(|e| anyhow::anyhow!("Failed to parse selector: {e:?}"))?;
+    let author_selector = Selector::parse(".productItem__author")
+        .map_err(|e| anyhow::anyhow!("Failed to parse selector: {e:?}"))?;
+    let mut candidates: Vec<TitleCandidate> = Vec::new();
+    for node in doc.select(&item_selector) {
+        let Some(href) = node
+            .value()
+            .attr("href")
+            .filter(|href| href.starts_with("/used/") || href.starts_with("/new/"))
+        else {
+            continue;
+        };
+        let Some(title) = super::select_text(node, &title_selector) else {
+            continue;
+        };
+        let Some(product_id) = href.rsplit('/').next().map(str::to_string) else {
+            continue;
+        };
+        let candidate = TitleCandidate {
+            hit: SearchHit {
+                product_url: format!("{BASE_URL}{href}"),
+                product_id,
+                details: None,
+            },
+            title,
+            author: super::select_text(node, &author_selector),
+        };
+        match candidates
+            .iter_mut()
+            .find(|c| c.hit.product_id == candidate.hit.product_id)
+        {
+            Some(existing) if href.starts_with("/used/") => *existing = candidate,
+            Some(_) => {}
+            None => candidates.push(candidate),
+        }
+    }
+    Ok(candidates)
+}
+
 /// 商品ページ HTML から価格・在庫を取り出す
 ///
 /// 埋め込み JSON-LD の `offers` を優先し、なければ HTML 要素から読む。
-/// BOOKOFF は商品ごとの状態ランクを持たないため `condition` は `None`。
+/// 出品の種類は「中古 / 新品」ボタンのうち選択中 (`-active`) のものから読む。
+/// BOOKOFF は中古品の状態ランクを持たないため、`condition` は新品の出品の場合だけ `"新品"`。
 ///
 /// # Errors
 ///
 /// 価格が取得できない場合にエラーを返す。
 pub fn parse_product(html: &str) -> Result<OfferDetails> {
+    let isbn13 = super::parse_isbn(html)?;
+    let listing_type = parse_listing_buttons(html)?
+        .into_iter()
+        .find(|button| button.active)
+        .map(|button| button.listing_type);
+    let condition = listing_type
+        .filter(|t| *t == ListingType::New)
+        .map(|t| t.label().to_string());
     if let Some((price, in_stock)) = super::parse_json_ld_offer(html)? {
         return Ok(OfferDetails {
             price,
-            condition: None,
+            condition,
             in_stock,
+            stock_count: None,
+            listing_type,
+            variants: Vec::new(),
+            isbn13,
+            listing: None,
         });
     }
     // JSON-LD が取れない場合のフォールバック
@@ -111,11 +294,99 @@ pub fn parse_product(html: &str) -> Result<OfferDetails> {
     }
     Ok(OfferDetails {
         price,
-        condition: None,
+        condition,
         in_stock,
+        stock_count: None,
+        listing_type,
+        variants: Vec::new(),
+        isbn13,
+        listing: None,
     })
 }
 
+/// 商品ページの「中古 / 新品」ボタン1つ分
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct ListingButton {
+    listing_type: ListingType,
+    href: String,
+    active: bool,
+}
+
+/// 商品ページの「中古 / 新品」ボタンを読む (片方の出品しかない場合はボタン自体がないことがある)
+fn parse_listing_buttons(html: &str) -> Result<Vec<ListingButton>> {
+    let doc = Html::parse_document(html);
+    let selector = Selector::parse(".productInformation__Btn a")
+        .map_err(|e| anyhow::anyhow!("Failed to parse selector: {e:?}"))?;
+    Ok(doc
+        .select(&selector)
+        .filter_map(|node| {
+            let href = node.value().attr("href")?;
+            let listing_type = if href.starts_with("/used/") {
+                ListingType::Used
+            } else if href.starts_with("/new/") {
+                ListingType::New
+            } else {
+                return None;
+            };
+            Some(ListingButton {
+                listing_type,
+                href: href.to_string(),
+                active: node.value().classes().any(|class| class == "-active"),
+            })
+        })
+        .collect())
+}
+
+/// 商品ページ HTML から、表示中でない方の出品 (中古なら新品、新品なら中古) の URL を取り出す
+///
+/// # Errors
+///
+/// セレクタの解析に失敗した場合にエラーを返す。
+pub fn parse_other_listing_url(html: &str) -> Result<Option<String>> {
+    let buttons = parse_listing_buttons(html)?;
+    if !buttons.iter().any(|button| button.active) {
+        return Ok(None);
+    }
+    Ok(buttons
+        .into_iter()
+        .find(|button| !button.active)
+        .map(|button| format!("{BASE_URL}{}", button.href)))
+}
+
+/// 表示中の出品ともう一方の出品を、出品の種類ごとの `variants` にまとめる
+///
+/// オファー本体 (価格・状態・在庫・出品の種類) は在庫のある出品のうち安い方にする
+/// (同じ価格なら表示中の出品)。もう一方の出品にした場合は、その商品 ID / URL を `listing` に入れる。
+fn with_listing_variants(mut details: OfferDetails, other: Option<&OfferDetails>) -> OfferDetails {
+    details.variants = std::iter::once(&details)
+        .chain(other)
+        .filter_map(|listing| {
+            Some(ConditionVariant {
+                condition: listing.listing_type?.label().to_string(),
+                price: listing.price,
+                stock_count: None,
+                in_stock: listing.in_stock,
+            })
+        })
+        .collect();
+    if let Some(other) = other.filter(|other| is_cheaper_in_stock(other, &details)) {
+        details.price = other.price;
+        details.condition.clone_from(&other.condition);
+        details.in_stock = other.in_stock;
+        details.listing_type = other.listing_type;
+        details.listing.clone_from(&other.listing);
+    }
+    details
+}
+
+/// `listing` が在庫があり、`current` より安く買えるか
+fn is_cheaper_in_stock(listing: &OfferDetails, current: &OfferDetails) -> bool {
+    let Some(price) = listing.price.filter(|_| listing.in_stock) else {
+        return false;
+    };
+    !current.in_stock || current.price.is_none_or(|current| price < current)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -125,6 +396,8 @@ mod tests {
     // - bookoff_search_empty.html: 該当なしの検索結果
     // - bookoff_product.html: /used/0019117467 (495円・在庫あり)
     // - bookoff_product_oos.html: /used/0016731582 吾輩は猫である (220円・在庫なし)
+    //
+    // どちらの商品ページにも「中古 / 新品」ボタンがあり、中古が選択中。
 
     #[test]
     fn test_parse_search() -> Result<()> {
@@ -142,6 +415,29 @@ mod tests {
     fn test_parse_search_empty() -> Result<()> {
         let html = include_str!("../../tests/fixtures/used_book/bookoff_search_empty.html");
         assert!(parse_search(html)?.is_none());
+        assert!(parse_title_search(html)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_title_search() -> Result<()> {
+        let html = include_str!("../../tests/fixtures/used_book/bookoff_search.html");
+        let candidates = parse_title_search(html)?;
+        assert_eq!(
+            candidates
+                .iter()
+                .map(|c| (
+                    c.hit.product_url.as_str(),
+                    c.title.as_str(),
+                    c.author.as_deref()
+                ))
+                .collect::<Vec<_>>(),
+            vec![(
+                "https://shopping.bookoff.co.jp/used/0019117467",
+                "海に願いを風に祈りをそして君に誓いを スターツ出版文庫",
+                Some("汐見夏衛")
+            )]
+        );
         Ok(())
     }
 
@@ -151,7 +447,111 @@ mod tests {
         let details = parse_product(html)?;
         assert_eq!(details.price, Some(495));
         assert!(details.in_stock);
+        assert_eq!(details.isbn13.as_deref(), Some("9784813705185"));
         assert_eq!(details.condition, None);
+        assert_eq!(details.listing_type, Some(ListingType::Used));
+        assert_eq!(
+            parse_other_listing_url(html)?.as_deref(),
+            Some("https://shopping.bookoff.co.jp/new/0019117467")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_listing_variants() -> Result<()> {
+        let used = parse_product(include_str!(
+            "../../tests/fixtures/used_book/bookoff_product.html"
+        ))?;
+        let new = OfferDetails {
+            price: Some(803),
+            condition: Some("新品".to_string()),
+            in_stock: true,
+            stock_count: None,
+            listing_type: Some(ListingType::New),
+            variants: Vec::new(),
+            isbn13: Some("9784813705185".to_string()),
+            listing: None,
+        };
+        let details = with_listing_variants(used, Some(&new));
+        assert_eq!(details.price, Some(495));
+        assert_eq!(details.listing_type, Some(ListingType::Used));
+        assert_eq!(
+            details
+                .variants
+                .iter()
+                .map(|v| (v.condition.as_str(), v.price, v.in_stock, v.grade()))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    "中古",
+                    Some(495),
+                    true,
+                    crate::used_book::ConditionGrade::Unknown
+                ),
+                (
+                    "新品",
+                    Some(803),
+                    true,
+                    crate::used_book::ConditionGrade::New
+                ),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_listing_variants_without_other() -> Result<()> {
+        let used = parse_product(include_str!(
+            "../../tests/fixtures/used_book/bookoff_product_oos.html"
+        ))?;
+        let details = with_listing_variants(used, None);
+        assert_eq!(details.variants.len(), 1);
+        assert!(!details.variants[0].in_stock);
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_listing_variants_uses_cheaper_listing_in_stock() -> Result<()> {
+        let used = parse_product(include_str!(
+            "../../tests/fixtures/used_book/bookoff_product_oos.html"
+        ))?;
+        let new = OfferDetails {
+            price: Some(803),
+            condition: Some("新品".to_string()),
+            in_stock: true,
+            stock_count: None,
+            listing_type: Some(ListingType::New),
+            variants: Vec::new(),
+            isbn13: Some("9784167158057".to_string()),
+            listing: Some(listing_link(
+                "https://shopping.bookoff.co.jp/new/0016731582",
+            )),
+        };
+        // 中古が在庫切れなら、高くても在庫のある新品の出品をオファーにする
+        let details = with_listing_variants(used.clone(), Some(&new));
+        assert_eq!(details.price, Some(803));
+        assert!(details.in_stock);
+        assert_eq!(details.condition.as_deref(), Some("新品"));
+        assert_eq!(details.listing_type, Some(ListingType::New));
+        assert_eq!(details.variants.len(), 2);
+        // 商品 ID / URL も新品の出品のものにする
+        assert_eq!(
+            details.listing,
+            Some(ListingLink {
+                product_id: "0016731582".to_string(),
+                product_url: "https://shopping.bookoff.co.jp/new/0016731582".to_string(),
+            })
+        );
+
+        // どちらも在庫があれば安い方
+        let used = OfferDetails {
+            in_stock: true,
+            ..used
+        };
+        let details = with_listing_variants(used.clone(), Some(&new));
+        assert_eq!(details.price, Some(220));
+        assert_eq!(details.listing_type, used.listing_type);
+        assert_eq!(details.listing, None);
         Ok(())
     }
 
@@ -161,6 +561,7 @@ mod tests {
         let details = parse_product(html)?;
         assert_eq!(details.price, Some(220));
         assert!(!details.in_stock);
+        assert_eq!(details.isbn13.as_deref(), Some("9784167158057"));
         Ok(())
     }
 }
@@ -0,0 +1,144 @@
+//! 中古本サイトごとの商品状態の表記を共通の状態ランクに揃える
+
+use serde::{Deserialize, Serialize};
+
+/// サイト間で比較できる商品状態のランク (良い順)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConditionGrade {
+    /// 状態が分からない (サイトが状態を持たない、未知の表記など)
+    Unknown,
+    /// 可
+    Acceptable,
+    /// 良い
+    Good,
+    /// 非常に良い
+    VeryGood,
+    /// ほぼ新品
+    LikeNew,
+    /// 新品
+    New,
+}
+
+impl ConditionGrade {
+    /// DB に保存する値
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ConditionGrade::Unknown => "unknown",
+            ConditionGrade::Acceptable => "acceptable",
+            ConditionGrade::Good => "good",
+            ConditionGrade::VeryGood => "very-good",
+            ConditionGrade::LikeNew => "like-new",
+            ConditionGrade::New => "new",
+        }
+    }
+
+    /// 全てのランク (悪い順)
+    pub const ALL: [ConditionGrade; 6] = [
+        ConditionGrade::Unknown,
+        ConditionGrade::Acceptable,
+        ConditionGrade::Good,
+        ConditionGrade::VeryGood,
+        ConditionGrade::LikeNew,
+        ConditionGrade::New,
+    ];
+
+    /// DB に保存した値から戻す (未知の値は `None`)
+    #[must_use]
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|grade| grade.as_str() == value)
+    }
+
+    /// このランク以上の DB 上の値の一覧 (最低ランクでの絞り込み用)
+    #[must_use]
+    pub fn at_least(self) -> Vec<&'static str> {
+        Self::ALL
+            .into_iter()
+            .filter(|grade| *grade >= self)
+            .map(ConditionGrade::as_str)
+            .collect()
+    }
+
+    /// オファーの状態 (`OfferDetails.condition`) を状態ランクに変換する
+    ///
+    /// BOOKOFF のように状態を持たないサイトの `None` は `Unknown` とする。
+    #[must_use]
+    pub fn from_condition(condition: Option<&str>) -> Self {
+        condition.map_or(ConditionGrade::Unknown, Self::from_raw)
+    }
+
+    /// サイトの状態表記を状態ランクに変換する
+    ///
+    /// バリューブックスの英語表記 (NEW / `VERY_GOOD` / GOOD / ACCEPTABLE) と
+    /// もったいない本舗などの日本語表記 (新品 / ほぼ新品 / 非常に良い / 良い / 可) に対応する。
+    /// 状態ランクを持たないサイト (ネットオフ・駿河屋・Amazon) の「中古」「中古品」は `Unknown` とする。
+    /// Amazon の出品一覧の「中古品 - 非常に良い」のような表記は後半の状態で判定する。
+    #[must_use]
+    pub fn from_raw(raw: &str) -> Self {
+        let raw = raw.trim();
+        let raw = raw
+            .split_once(" - ")
+            .map_or(raw, |(_, condition)| condition.trim());
+        match raw {
+            "NEW" | "新品" => ConditionGrade::New,
+            "LIKE_NEW" | "ほぼ新品" | "新品同様" => ConditionGrade::LikeNew,
+            "VERY_GOOD" | "非常に良い" => ConditionGrade::VeryGood,
+            "GOOD" | "良い" => ConditionGrade::Good,
+            "ACCEPTABLE" | "可" => ConditionGrade::Acceptable,
+            _ => ConditionGrade::Unknown,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_raw() {
+        assert_eq!(ConditionGrade::from_raw("GOOD"), ConditionGrade::Good);
+        assert_eq!(
+            ConditionGrade::from_raw("VERY_GOOD"),
+            ConditionGrade::VeryGood
+        );
+        assert_eq!(
+            ConditionGrade::from_raw("非常に良い"),
+            ConditionGrade::VeryGood
+        );
+        assert_eq!(ConditionGrade::from_raw("可"), ConditionGrade::Acceptable);
+        assert_eq!(ConditionGrade::from_raw("新品"), ConditionGrade::New);
+        assert_eq!(ConditionGrade::from_raw("中古品"), ConditionGrade::Unknown);
+        assert_eq!(
+            ConditionGrade::from_raw("中古品 - 非常に良い"),
+            ConditionGrade::VeryGood
+        );
+        assert_eq!(
+            ConditionGrade::from_condition(None),
+            ConditionGrade::Unknown
+        );
+    }
+
+    #[test]
+    fn test_at_least() {
+        assert_eq!(
+            ConditionGrade::VeryGood.at_least(),
+            vec!["very-good", "like-new", "new"]
+        );
+        assert_eq!(ConditionGrade::Unknown.at_least().len(), 6);
+    }
+
+    #[test]
+    fn test_ordering() {
+        assert!(ConditionGrade::VeryGood > ConditionGrade::Acceptable);
+        assert!(ConditionGrade::New > ConditionGrade::LikeNew);
+        assert!(ConditionGrade::Unknown < ConditionGrade::Acceptable);
+    }
+
+    #[test]
+    fn test_as_str_roundtrip() {
+        for grade in ConditionGrade::ALL {
+            assert_eq!(ConditionGrade::parse(grade.as_str()), Some(grade));
+        }
+    }
+}
@@ -0,0 +1,385 @@
+//! もったいない本舗の検索・商品ページパーサー
+//!
+//! - 検索: `https://www.mottainaihonpo.com/search/?q={isbn13}`
+//! - 商品ページ: `https://www.mottainaihonpo.com/item/{product_id}/`
+//!   状態 (非常に良い / 良い / 可) ごとの価格・在庫が表で並ぶ。
+//!
+//! セレクタは実際のページで確認できていないため、既定では巡回しない
+//! (`USED_BOOK_SITES` に `mottainai` を含めた場合だけ巡回する)。
+//! `scripts/save-used-book-fixtures.sh mottainai` で実際のページを保存して確認する。
+
+use std::time::Duration;
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use scraper::{Html, Selector};
+
+use super::{
+    title_match, ConditionVariant, OfferDetails, SearchHit, TitleCandidate, UsedBookStore,
+};
+use crate::fetcher::Fetcher;
+
+const BASE_URL: &str = "https://www.mottainaihonpo.com";
+
+/// もったいない本舗
+pub struct Mottainai;
+
+impl UsedBookStore for Mottainai {
+    fn site_key(&self) -> &'static str {
+        "mottainai"
+    }
+
+    fn default_base_url(&self) -> &'static str {
+        BASE_URL
+    }
+
+    fn min_request_interval(&self) -> Duration {
+        // 小規模なサイトのため大手のサイトより長めに空ける
+        Duration::from_secs(2)
+    }
+
+    fn enabled_by_default(&self) -> bool {
+        // フィクスチャが手書きで、セレクタを実際のページで確認できていないため
+        false
+    }
+
+    fn search<'a>(
+        &'a self,
+        fetcher: &'a dyn Fetcher,
+        isbn13: &'a str,
+    ) -> BoxFuture<'a, Result<Option<SearchHit>>> {
+        Box::pin(search(fetcher, isbn13))
+    }
+
+    fn search_by_title<'a>(
+        &'a self,
+        fetcher: &'a dyn Fetcher,
+        title: &'a str,
+        author: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<Vec<TitleCandidate>>> {
+        Box::pin(search_by_title(fetcher, title, author))
+    }
+
+    fn fetch_details<'a>(
+        &'a self,
+        fetcher: &'a dyn Fetcher,
+        product_url: &'a str,
+    ) -> BoxFuture<'a, Result<OfferDetails>> {
+        Box::pin(fetch_details(fetcher, product_url))
+    }
+}
+
+/// タイトルと著者名で検索して検索結果の商品を全て取得する
+///
+/// # Errors
+///
+/// HTTP リクエストまたは HTML の解析に失敗した場合にエラーを返す。
+pub async fn search_by_title(
+    fetcher: &dyn Fetcher,
+    title: &str,
+    author: Option<&str>,
+) -> Result<Vec<TitleCandidate>> {
+    let url = url::Url::parse_with_params(
+        &format!("{BASE_URL}/search/"),
+        [("q", &title_match::search_query(title, author))],
+    )?;
+    let html = super::get_text(fetcher, url.as_str()).await?;
+    crate::snapshot::parse_or_record(
+        "mottainai.title_search",
+        url.as_str(),
+        &html,
+        parse_title_search,
+    )
+    .await
+}
+
+/// ISBN-13 で検索して最初の商品を取得する
+///
+/// # Errors
+///
+/// HTTP リクエストまたは HTML の解析に失敗した場合にエラーを返す。
+pub async fn search(fetcher: &dyn Fetcher, isbn13: &str) -> Result<Option<SearchHit>> {
+    let url = format!("{BASE_URL}/search/?q={isbn13}");
+    let html = super::get_text(fetcher, &url).await?;
+    crate::snapshot::parse_or_record("mottainai.search", url.as_str(), &html, parse_search).await
+}
+
+/// 商品ページから在庫・価格・状態を取得する
+///
+/// # Errors
+///
+/// HTTP リクエストまたは HTML の解析に失敗した場合にエラーを返す。
+pub async fn fetch_details(fetcher: &dyn Fetcher, product_url: &str) -> Result<OfferDetails> {
+    let html = super::get_text(fetcher, product_url).await?;
+    crate::snapshot::parse_or_record("mottainai.product", product_url, &html, parse_product).await
+}
+
+/// 検索結果 HTML から最初の商品の ID と URL を取り出す
+///
+/// ヒットなしの場合は `Ok(None)` を返す。
+///
+/// # Errors
+///
+/// セレクタの解析に失敗した場合にエラーを返す。
+pub fn parse_search(html: &str) -> Result<Option<SearchHit>> {
+    let doc = Html::parse_document(html);
+    let selector = Selector::parse("a.item-list__link")
+        .map_err(|e| anyhow::anyhow!("Failed to parse selector: {e:?}"))?;
+    let Some(href) = doc
+        .select(&selector)
+        .find_map(|e| e.value().attr("href").map(str::to_string))
+    else {
+        return Ok(None);
+    };
+    // href は "/item/1012345678/" 形式
+    let product_id = href
+        .trim_matches('/')
+        .rsplit('/')
+        .next()
+        .filter(|id| !id.is_empty())
+        .ok_or_else(|| anyhow::anyhow!("Invalid product href: {href}"))?
+        .to_string();
+    Ok(Some(SearchHit {
+        product_id,
+        product_url: format!("{BASE_URL}{href}"),
+        details: None,
+    }))
+}
+
+/// 検索結果 HTML から全ての商品の ID / URL とタイトル・著者名を取り出す
+///
+/// # Errors
+///
+/// セレクタの解析に失敗した場合にエラーを返す。
+pub fn parse_title_search(html: &str) -> Result<Vec<TitleCandidate>> {
+    let doc = Html::parse_document(html);
+    let item_selector = Selector::parse("a.item-list__link")
+        .map_err(|e| anyhow::anyhow!("Failed to parse selector: {e:?}"))?;
+    let title_selector = Selector::parse(".item-list__title")
+        .map_err(|e| anyhow::anyhow!("Failed to parse selector: {e:?}"))?;
+    let author_selector = Selector::parse(".item-list__author")
+        .map_err(|e| anyhow::anyhow!("Failed to parse selector: {e:?}"))?;
+    Ok(doc
+        .select(&item_selector)
+        .filter_map(|link| {
+            let href = link.value().attr("href")?;
+            let product_id = href.trim_matches('/').rsplit('/').next()?.to_string();
+            Some(TitleCandidate {
+                hit: SearchHit {
+                    product_id,
+                    product_url: format!("{BASE_URL}{href}"),
+                    details: None,
+                },
+                title: super::select_text(link, &title_selector)?,
+                author: super::select_text(link, &author_selector),
+            })
+        })
+        .collect())
+}
+
+/// 商品ページ HTML から状態ごとの価格・在庫を全て取り出す
+///
+/// # Errors
+///
+/// セレクタの解析に失敗した場合にエラーを返す。
+pub fn parse_condition_prices(html: &str) -> Result<Vec<ConditionVariant>> {
+    let doc = Html::parse_document(html);
+    let row_selector = Selector::parse(".condition-list .condition-list__row")
+        .map_err(|e| anyhow::anyhow!("Failed to parse selector: {e:?}"))?;
+    let name_selector = Selector::parse(".condition-list__name")
+        .map_err(|e| anyhow::anyhow!("Failed to parse selector: {e:?}"))?;
+    let price_selector = Selector::parse(".condition-list__price")
+        .map_err(|e| anyhow::anyhow!("Failed to parse selector: {e:?}"))?;
+    let stock_selector = Selector::parse(".condition-list__stock")
+        .map_err(|e| anyhow::anyhow!("Failed to parse selector: {e:?}"))?;
+    let text_of = |row: scraper::ElementRef<'_>, selector: &Selector| {
+        row.select(selector)
+            .next()
+            .map(|e| e.text().collect::<String>().trim().to_string())
+    };
+    let mut conditions = Vec::new();
+    for row in doc.select(&row_selector) {
+        let Some(condition) = text_of(row, &name_selector).filter(|c| !c.is_empty()) else {
+            continue;
+        };
+        let price = text_of(row, &price_selector).and_then(|text| {
+            text.chars()
+                .filter(char::is_ascii_digit)
+                .collect::<String>()
+                .parse::<i32>()
+                .ok()
+        });
+        let in_stock = price.is_some()
+            && text_of(row, &stock_selector).is_some_and(|text| text.contains("在庫あり"));
+        // 在庫数は表示されない
+        conditions.push(ConditionVariant {
+            condition,
+            price,
+            stock_count: None,
+            in_stock,
+        });
+    }
+    Ok(conditions)
+}
+
+/// 商品ページ HTML から価格・在庫・状態を取り出す
+///
+/// 在庫のある状態のうち最安のものを `price` / `condition` とし、
+/// 全ての状態を `variants` に残す。
+///
+/// # Errors
+///
+/// 状態ごとの価格表が見つからない場合にエラーを返す。
+pub fn parse_product(html: &str) -> Result<OfferDetails> {
+    let isbn13 = super::parse_isbn(html)?;
+    let variants = parse_condition_prices(html)?;
+    if variants.is_empty() {
+        return Err(anyhow::anyhow!("Failed to parse Mottainai product page"));
+    }
+    let cheapest = variants
+        .iter()
+        .filter(|c| c.in_stock)
+        .min_by_key(|c| c.price)
+        .cloned();
+    Ok(match cheapest {
+        Some(cheapest) => OfferDetails {
+            price: cheapest.price,
+            condition: Some(cheapest.condition),
+            in_stock: true,
+            stock_count: None,
+            listing_type: None,
+            variants,
+            isbn13,
+            listing: None,
+        },
+        None => OfferDetails {
+            price: None,
+            condition: None,
+            in_stock: false,
+            stock_count: None,
+            listing_type: None,
+            variants,
+            isbn13,
+            listing: None,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // fixtures (tests/fixtures/used_book/):
+    // もったいない本舗のページ構造 (検索結果の .item-list / 商品ページの .condition-list) に
+    // 合わせて手で書いた HTML で、商品 ID も仮のもの。実際のページではないため、
+    // `scripts/save-used-book-fixtures.sh mottainai` で保存したページに置き換え、
+    // 商品 ID・価格とセレクタをそれに合わせること
+    // - mottainai_search.html: ISBN 9784813705185 の検索結果 (2件。先頭が対象の文庫)
+    // - mottainai_search_empty.html: 該当なしの検索結果
+    // - mottainai_product.html: /item/1012345678/ (非常に良い 1,100円・在庫なし / 良い 385円 / 可 275円)
+    // - mottainai_product_oos.html: /item/1022222222/ 吾輩は猫である (全状態在庫なし)
+
+    #[test]
+    fn test_parse_search() -> Result<()> {
+        let html = include_str!("../../tests/fixtures/used_book/mottainai_search.html");
+        let hit = parse_search(html)?.ok_or_else(|| anyhow::anyhow!("hit expected"))?;
+        assert_eq!(hit.product_id, "1012345678");
+        assert_eq!(
+            hit.product_url,
+            "https://www.mottainaihonpo.com/item/1012345678/"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_search_empty() -> Result<()> {
+        let html = include_str!("../../tests/fixtures/used_book/mottainai_search_empty.html");
+        assert!(parse_search(html)?.is_none());
+        assert!(parse_title_search(html)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_title_search() -> Result<()> {
+        let html = include_str!("../../tests/fixtures/used_book/mottainai_search.html");
+        let candidates = parse_title_search(html)?;
+        assert_eq!(
+            candidates
+                .iter()
+                .map(|c| (c.hit.product_id.as_str(), c.title.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("1012345678", "海に願いを風に祈りをそして君に誓いを"),
+                ("1087654321", "海に願いを風に祈りをそして君に誓いを 特装版"),
+            ]
+        );
+        let best = title_match::best_match(
+            "海に願いを風に祈りをそして君に誓いを",
+            Some("汐見 夏衛"),
+            candidates,
+        );
+        assert_eq!(
+            best.map(|(hit, _)| hit.product_id),
+            Some("1012345678".to_string())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_condition_prices() -> Result<()> {
+        let html = include_str!("../../tests/fixtures/used_book/mottainai_product.html");
+        let conditions = parse_condition_prices(html)?;
+        assert_eq!(
+            conditions,
+            vec![
+                ConditionVariant {
+                    condition: "非常に良い".to_string(),
+                    price: Some(1100),
+                    stock_count: None,
+                    in_stock: false,
+                },
+                ConditionVariant {
+                    condition: "良い".to_string(),
+                    price: Some(385),
+                    stock_count: None,
+                    in_stock: true,
+                },
+                ConditionVariant {
+                    condition: "可".to_string(),
+                    price: Some(275),
+                    stock_count: None,
+                    in_stock: true,
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_product_in_stock() -> Result<()> {
+        let html = include_str!("../../tests/fixtures/used_book/mottainai_product.html");
+        let details = parse_product(html)?;
+        assert_eq!(details.price, Some(275));
+        assert_eq!(details.condition, Some("可".to_string()));
+        assert!(details.in_stock);
+        assert_eq!(details.isbn13.as_deref(), Some("9784813705185"));
+        assert_eq!(details.variants.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_product_out_of_stock() -> Result<()> {
+        let html = include_str!("../../tests/fixtures/used_book/mottainai_product_oos.html");
+        let details = parse_product(html)?;
+        assert_eq!(details.price, None);
+        assert_eq!(details.condition, None);
+        assert!(!details.in_stock);
+        assert_eq!(details.isbn13.as_deref(), Some("9784167158057"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_product_unknown_page() {
+        assert!(parse_product("<html><body></body></html>").is_err());
+    }
+}
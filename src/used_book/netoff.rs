@@ -4,22 +4,96 @@
 //!   (`cat=1002` は「古本・中古本」カテゴリ)
 //! - 商品ページ: `https://www.netoff.co.jp/detail/{product_id}/`
 
+use std::time::Duration;
+
 use anyhow::Result;
 use scraper::{Html, Selector};
 
-use super::{http_client, OfferDetails, SearchHit};
+use futures::future::BoxFuture;
+
+use super::{title_match, OfferDetails, SearchHit, TitleCandidate, UsedBookStore};
+use crate::fetcher::Fetcher;
 
 const BASE_URL: &str = "https://www.netoff.co.jp";
 
+/// ネットオフ
+pub struct NetOff;
+
+impl UsedBookStore for NetOff {
+    fn site_key(&self) -> &'static str {
+        "netoff"
+    }
+
+    fn default_base_url(&self) -> &'static str {
+        BASE_URL
+    }
+
+    fn min_request_interval(&self) -> Duration {
+        Duration::from_secs(1)
+    }
+
+    fn search<'a>(
+        &'a self,
+        fetcher: &'a dyn Fetcher,
+        isbn13: &'a str,
+    ) -> BoxFuture<'a, Result<Option<SearchHit>>> {
+        Box::pin(search(fetcher, isbn13))
+    }
+
+    fn search_by_title<'a>(
+        &'a self,
+        fetcher: &'a dyn Fetcher,
+        title: &'a str,
+        author: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<Vec<TitleCandidate>>> {
+        Box::pin(search_by_title(fetcher, title, author))
+    }
+
+    fn fetch_details<'a>(
+        &'a self,
+        fetcher: &'a dyn Fetcher,
+        product_url: &'a str,
+    ) -> BoxFuture<'a, Result<OfferDetails>> {
+        Box::pin(fetch_details(fetcher, product_url))
+    }
+}
+
+/// タイトルと著者名で検索して検索結果の商品を全て取得する
+///
+/// # Errors
+///
+/// HTTP リクエストまたは HTML の解析に失敗した場合にエラーを返す。
+pub async fn search_by_title(
+    fetcher: &dyn Fetcher,
+    title: &str,
+    author: Option<&str>,
+) -> Result<Vec<TitleCandidate>> {
+    let url = url::Url::parse_with_params(
+        &format!("{BASE_URL}/cmdtyallsearch/"),
+        [
+            ("cat", "1002"),
+            ("word", &title_match::search_query(title, author)),
+        ],
+    )?;
+    let html = super::get_text(fetcher, url.as_str()).await?;
+    crate::snapshot::parse_or_record(
+        "netoff.title_search",
+        url.as_str(),
+        &html,
+        parse_title_search,
+    )
+    .await
+}
+
 /// ISBN-13 で検索して最初の商品を取得する
 ///
 /// # Errors
 ///
 /// HTTP リクエストまたは HTML の解析に失敗した場合にエラーを返す。
-pub async fn search(isbn13: &str) -> Result<Option<SearchHit>> {
+pub async fn search(fetcher: &dyn Fetcher, isbn13: &str) -> Result<Option<SearchHit>> {
     let url = format!("{BASE_URL}/cmdtyallsearch/?cat=1002&word={isbn13}");
-    let html = http_client()?.get(&url).send().await?.text().await?;
-    parse_search(&html)
+    let html = super::get_text(fetcher, &url).await?;
+    crate::snapshot::parse_or_record("netoff.search", url.as_str(), &html, parse_search).await
 }
 
 /// 商品ページから在庫・価格・状態を取得する
@@ -27,9 +101,9 @@ pub async fn search(isbn13: &str) -> Result<Option<SearchHit>> {
 /// # Errors
 ///
 /// HTTP リクエストまたは HTML の解析に失敗した場合にエラーを返す。
-pub async fn fetch_details(product_url: &str) -> Result<OfferDetails> {
-    let html = http_client()?.get(product_url).send().await?.text().await?;
-    parse_product(&html)
+pub async fn fetch_details(fetcher: &dyn Fetcher, product_url: &str) -> Result<OfferDetails> {
+    let html = super::get_text(fetcher, product_url).await?;
+    crate::snapshot::parse_or_record("netoff.product", product_url, &html, parse_product).await
 }
 
 /// 検索結果 HTML から最初の商品の ID と URL を取り出す
@@ -63,12 +137,46 @@ pub fn parse_search(html: &str) -> Result<Option<SearchHit>> {
     }))
 }
 
+/// 検索結果 HTML から全ての商品の ID / URL とタイトル・著者名を取り出す
+///
+/// # Errors
+///
+/// セレクタの解析に失敗した場合にエラーを返す。
+pub fn parse_title_search(html: &str) -> Result<Vec<TitleCandidate>> {
+    let doc = Html::parse_document(html);
+    let item_selector = Selector::parse(".c-cassette")
+        .map_err(|e| anyhow::anyhow!("Failed to parse selector: {e:?}"))?;
+    let title_selector = Selector::parse("a.c-cassette__title")
+        .map_err(|e| anyhow::anyhow!("Failed to parse selector: {e:?}"))?;
+    // 著者名の後ろに「 / 文庫」「 / 2018年08月」などが続く
+    let author_selector = Selector::parse(".c-cassette__author span")
+        .map_err(|e| anyhow::anyhow!("Failed to parse selector: {e:?}"))?;
+    Ok(doc
+        .select(&item_selector)
+        .filter_map(|item| {
+            let link = item.select(&title_selector).next()?;
+            let href = link.value().attr("href")?;
+            let product_id = href.trim_matches('/').rsplit('/').next()?.to_string();
+            Some(TitleCandidate {
+                hit: SearchHit {
+                    product_id,
+                    product_url: format!("{BASE_URL}{href}"),
+                    details: None,
+                },
+                title: link.text().collect::<String>().trim().to_string(),
+                author: super::select_text(item, &author_selector),
+            })
+        })
+        .collect())
+}
+
 /// 商品ページ HTML から価格・在庫・状態を取り出す
 ///
 /// # Errors
 ///
 /// 価格が取得できない場合にエラーを返す。
 pub fn parse_product(html: &str) -> Result<OfferDetails> {
+    let isbn13 = super::parse_isbn(html)?;
     let doc = Html::parse_document(html);
 
     let price_selector = Selector::parse(".product-price__normal-num")
@@ -85,7 +193,12 @@ pub fn parse_product(html: &str) -> Result<OfferDetails> {
     // 在庫がある場合は「在庫あとN点！」などの文言が入る (ない場合は要素自体が空)
     let stock_selector = Selector::parse(".l-product__stock-text")
         .map_err(|e| anyhow::anyhow!("Failed to parse selector: {e:?}"))?;
-    let in_stock = doc.select(&stock_selector).next().is_some();
+    let stock_text = doc
+        .select(&stock_selector)
+        .next()
+        .map(|e| e.text().collect::<String>());
+    let in_stock = stock_text.is_some();
+    let stock_count = stock_text.as_deref().and_then(parse_stock_count);
 
     // 「状態：中古品」のような文言から状態を取り出す (在庫がない場合は要素自体がない)
     let condition_selector = Selector::parse(".l-product__condition-text")
@@ -105,9 +218,25 @@ pub fn parse_product(html: &str) -> Result<OfferDetails> {
         price,
         condition,
         in_stock,
+        stock_count,
+        listing_type: None,
+        variants: Vec::new(),
+        isbn13,
+        listing: None,
     })
 }
 
+/// 「在庫あとN点！」から残りの在庫数 N を取り出す (数字がない場合は None)
+fn parse_stock_count(text: &str) -> Option<i32> {
+    let (_, rest) = text.split_once("あと")?;
+    let digits: String = rest
+        .trim_start()
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .collect();
+    digits.parse().ok()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -134,6 +263,29 @@ mod tests {
     fn test_parse_search_empty() -> Result<()> {
         let html = include_str!("../../tests/fixtures/used_book/netoff_search_empty.html");
         assert!(parse_search(html)?.is_none());
+        assert!(parse_title_search(html)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_title_search() -> Result<()> {
+        let html = include_str!("../../tests/fixtures/used_book/netoff_search.html");
+        let candidates = parse_title_search(html)?;
+        assert_eq!(
+            candidates
+                .iter()
+                .map(|c| (
+                    c.hit.product_id.as_str(),
+                    c.title.as_str(),
+                    c.author.as_deref()
+                ))
+                .collect::<Vec<_>>(),
+            vec![(
+                "0012822282",
+                "海に願いを風に祈りをそして君に誓いを",
+                Some("汐見夏衛")
+            )]
+        );
         Ok(())
     }
 
@@ -144,6 +296,8 @@ mod tests {
         assert_eq!(details.price, Some(220));
         assert_eq!(details.condition, Some("中古品".to_string()));
         assert!(details.in_stock);
+        assert_eq!(details.stock_count, Some(1));
+        assert_eq!(details.isbn13.as_deref(), Some("9784813705185"));
         Ok(())
     }
 
@@ -154,6 +308,15 @@ mod tests {
         assert_eq!(details.price, Some(110));
         assert_eq!(details.condition, None);
         assert!(!details.in_stock);
+        assert_eq!(details.stock_count, None);
+        assert_eq!(details.isbn13.as_deref(), Some("9784041004098"));
         Ok(())
     }
+
+    #[test]
+    fn test_parse_stock_count() {
+        assert_eq!(parse_stock_count("在庫あと3点！"), Some(3));
+        assert_eq!(parse_stock_count("在庫あと 12点"), Some(12));
+        assert_eq!(parse_stock_count("在庫あり"), None);
+    }
 }
@@ -0,0 +1,360 @@
+//! 駿河屋の検索・商品ページパーサー
+//!
+//! - 検索: `https://www.suruga-ya.jp/search?category=&search_word={isbn13}`
+//! - 商品ページ: `https://www.suruga-ya.jp/product/detail/{product_id}`
+//!
+//! セレクタは実際のページで確認できていないため、既定では巡回しない
+//! (`USED_BOOK_SITES` に `surugaya` を含めた場合だけ巡回する)。
+//! `scripts/save-used-book-fixtures.sh surugaya` で実際のページを保存して確認する。
+
+use std::time::Duration;
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use scraper::{Html, Selector};
+
+use super::{title_match, OfferDetails, SearchHit, TitleCandidate, UsedBookStore};
+use crate::fetcher::Fetcher;
+
+const BASE_URL: &str = "https://www.suruga-ya.jp";
+
+/// 駿河屋
+pub struct Surugaya;
+
+impl UsedBookStore for Surugaya {
+    fn site_key(&self) -> &'static str {
+        "surugaya"
+    }
+
+    fn default_base_url(&self) -> &'static str {
+        BASE_URL
+    }
+
+    fn min_request_interval(&self) -> Duration {
+        // 検索ページが重く、連続したアクセスを制限されやすいため長めに空ける
+        Duration::from_secs(3)
+    }
+
+    fn enabled_by_default(&self) -> bool {
+        // フィクスチャが手書きで、セレクタを実際のページで確認できていないため
+        false
+    }
+
+    fn search<'a>(
+        &'a self,
+        fetcher: &'a dyn Fetcher,
+        isbn13: &'a str,
+    ) -> BoxFuture<'a, Result<Option<SearchHit>>> {
+        Box::pin(search(fetcher, isbn13))
+    }
+
+    fn search_by_title<'a>(
+        &'a self,
+        fetcher: &'a dyn Fetcher,
+        title: &'a str,
+        author: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<Vec<TitleCandidate>>> {
+        Box::pin(search_by_title(fetcher, title, author))
+    }
+
+    fn fetch_details<'a>(
+        &'a self,
+        fetcher: &'a dyn Fetcher,
+        product_url: &'a str,
+    ) -> BoxFuture<'a, Result<OfferDetails>> {
+        Box::pin(fetch_details(fetcher, product_url))
+    }
+}
+
+/// タイトルと著者名で検索して検索結果の商品を全て取得する
+///
+/// # Errors
+///
+/// HTTP リクエストまたは HTML の解析に失敗した場合にエラーを返す。
+pub async fn search_by_title(
+    fetcher: &dyn Fetcher,
+    title: &str,
+    author: Option<&str>,
+) -> Result<Vec<TitleCandidate>> {
+    let url = url::Url::parse_with_params(
+        &format!("{BASE_URL}/search"),
+        [
+            ("category", ""),
+            ("search_word", &title_match::search_query(title, author)),
+        ],
+    )?;
+    let html = super::get_text(fetcher, url.as_str()).await?;
+    crate::snapshot::parse_or_record(
+        "surugaya.title_search",
+        url.as_str(),
+        &html,
+        parse_title_search,
+    )
+    .await
+}
+
+/// ISBN-13 で検索して最初の商品を取得する
+///
+/// # Errors
+///
+/// HTTP リクエストまたは HTML の解析に失敗した場合にエラーを返す。
+pub async fn search(fetcher: &dyn Fetcher, isbn13: &str) -> Result<Option<SearchHit>> {
+    let url = format!("{BASE_URL}/search?category=&search_word={isbn13}");
+    let html = super::get_text(fetcher, &url).await?;
+    crate::snapshot::parse_or_record("surugaya.search", url.as_str(), &html, parse_search).await
+}
+
+/// 商品ページから在庫・価格・状態を取得する
+///
+/// # Errors
+///
+/// HTTP リクエストまたは HTML の解析に失敗した場合にエラーを返す。
+pub async fn fetch_details(fetcher: &dyn Fetcher, product_url: &str) -> Result<OfferDetails> {
+    let html = super::get_text(fetcher, product_url).await?;
+    crate::snapshot::parse_or_record("surugaya.product", product_url, &html, parse_product).await
+}
+
+/// 検索結果 HTML から最初の商品の ID と URL を取り出す
+///
+/// ヒットなしの場合は `Ok(None)` を返す。
+///
+/// # Errors
+///
+/// セレクタの解析に失敗した場合にエラーを返す。
+pub fn parse_search(html: &str) -> Result<Option<SearchHit>> {
+    let doc = Html::parse_document(html);
+    let selector = Selector::parse(".item_box .item .title a")
+        .map_err(|e| anyhow::anyhow!("Failed to parse selector: {e:?}"))?;
+    let Some(href) = doc.select(&selector).find_map(|e| {
+        e.value()
+            .attr("href")
+            .filter(|href| href.contains("/product/detail/"))
+            .map(str::to_string)
+    }) else {
+        return Ok(None);
+    };
+    // href は絶対 URL ("https://www.suruga-ya.jp/product/detail/ZHOR1234567") のことも
+    // 相対パスのこともある
+    let product_url = if href.starts_with('/') {
+        format!("{BASE_URL}{href}")
+    } else {
+        href
+    };
+    let product_id = product_url
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .filter(|id| !id.is_empty())
+        .ok_or_else(|| anyhow::anyhow!("Invalid product href: {product_url}"))?
+        .to_string();
+    Ok(Some(SearchHit {
+        product_id,
+        product_url,
+        details: None,
+    }))
+}
+
+/// 検索結果 HTML から全ての商品の ID / URL とタイトル・著者名を取り出す
+///
+/// 駿河屋のタイトルは「<<ジャンル>> タイトル / 著者名」形式なので、最後の " / " で著者名を分ける。
+///
+/// # Errors
+///
+/// セレクタの解析に失敗した場合にエラーを返す。
+pub fn parse_title_search(html: &str) -> Result<Vec<TitleCandidate>> {
+    let doc = Html::parse_document(html);
+    let selector = Selector::parse(".item_box .item .title a")
+        .map_err(|e| anyhow::anyhow!("Failed to parse selector: {e:?}"))?;
+    Ok(doc
+        .select(&selector)
+        .filter_map(|link| {
+            let href = link
+                .value()
+                .attr("href")
+                .filter(|href| href.contains("/product/detail/"))?;
+            let product_url = if href.starts_with('/') {
+                format!("{BASE_URL}{href}")
+            } else {
+                href.to_string()
+            };
+            let product_id = product_url
+                .trim_end_matches('/')
+                .rsplit('/')
+                .next()
+                .filter(|id| !id.is_empty())?
+                .to_string();
+            let text = link.text().collect::<String>();
+            let (title, author) = match text.trim().rsplit_once(" / ") {
+                Some((title, author)) => (title.to_string(), Some(author.trim().to_string())),
+                None => (text.trim().to_string(), None),
+            };
+            Some(TitleCandidate {
+                hit: SearchHit {
+                    product_id,
+                    product_url,
+                    details: None,
+                },
+                title,
+                author,
+            })
+        })
+        .collect())
+}
+
+/// 商品ページ HTML から価格・在庫・状態を取り出す
+///
+/// 品切れの場合は価格欄に「品切れ」と表示されるため、`price` は `None` になる。
+///
+/// # Errors
+///
+/// 価格欄が見つからない場合にエラーを返す。
+pub fn parse_product(html: &str) -> Result<OfferDetails> {
+    let isbn13 = super::parse_isbn(html)?;
+    let doc = Html::parse_document(html);
+
+    let price_selector = Selector::parse(".price_group .price-buy")
+        .map_err(|e| anyhow::anyhow!("Failed to parse selector: {e:?}"))?;
+    let Some(price_text) = doc
+        .select(&price_selector)
+        .next()
+        .map(|e| e.text().collect::<String>())
+    else {
+        return Err(anyhow::anyhow!("Failed to parse Surugaya product page"));
+    };
+    let price = Some(
+        price_text
+            .chars()
+            .filter(char::is_ascii_digit)
+            .collect::<String>(),
+    )
+    .filter(|digits| !digits.is_empty())
+    .and_then(|digits| digits.parse::<i32>().ok());
+
+    // 在庫がある場合だけカートボタンが表示される
+    let cart_selector = Selector::parse(".price_group .btn_buy")
+        .map_err(|e| anyhow::anyhow!("Failed to parse selector: {e:?}"))?;
+    let in_stock = price.is_some() && doc.select(&cart_selector).next().is_some();
+
+    // 「中古」「新品」などの区分
+    let condition_selector = Selector::parse(".price_group .tag_product")
+        .map_err(|e| anyhow::anyhow!("Failed to parse selector: {e:?}"))?;
+    let condition = doc
+        .select(&condition_selector)
+        .next()
+        .map(|e| e.text().collect::<String>().trim().to_string())
+        .filter(|c| !c.is_empty() && in_stock);
+
+    Ok(OfferDetails {
+        price,
+        condition,
+        in_stock,
+        stock_count: None,
+        listing_type: None,
+        variants: Vec::new(),
+        isbn13,
+        listing: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // fixtures (tests/fixtures/used_book/):
+    // 駿河屋のページ構造 (検索結果の .item_box / 商品ページの .price_group) に合わせて手で書いた
+    // HTML で、商品 ID も仮のもの。実際のページではないため、
+    // `scripts/save-used-book-fixtures.sh surugaya` で保存したページに置き換え、
+    // 商品 ID・価格とセレクタをそれに合わせること
+    // - surugaya_search.html: ISBN 9784813705185 の検索結果 (2件。先頭が対象の文庫)
+    // - surugaya_search_empty.html: 該当なしの検索結果
+    // - surugaya_product.html: /product/detail/ZHOR1234567 (中古 330円・在庫あり)
+    // - surugaya_product_oos.html: /product/detail/ZHOR2222222 吾輩は猫である (品切れ)
+
+    #[test]
+    fn test_parse_search() -> Result<()> {
+        let html = include_str!("../../tests/fixtures/used_book/surugaya_search.html");
+        let hit = parse_search(html)?.ok_or_else(|| anyhow::anyhow!("hit expected"))?;
+        assert_eq!(hit.product_id, "ZHOR1234567");
+        assert_eq!(
+            hit.product_url,
+            "https://www.suruga-ya.jp/product/detail/ZHOR1234567"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_search_relative_href() -> Result<()> {
+        let html = r#"<div class="item_box"><div class="item"><p class="title"><a href="/product/detail/ZHOR1234567">本</a></p></div></div>"#;
+        let hit = parse_search(html)?.ok_or_else(|| anyhow::anyhow!("hit expected"))?;
+        assert_eq!(hit.product_id, "ZHOR1234567");
+        assert_eq!(
+            hit.product_url,
+            "https://www.suruga-ya.jp/product/detail/ZHOR1234567"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_search_empty() -> Result<()> {
+        let html = include_str!("../../tests/fixtures/used_book/surugaya_search_empty.html");
+        assert!(parse_search(html)?.is_none());
+        assert!(parse_title_search(html)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_title_search() -> Result<()> {
+        let html = include_str!("../../tests/fixtures/used_book/surugaya_search.html");
+        let candidates = parse_title_search(html)?;
+        assert_eq!(
+            candidates
+                .iter()
+                .map(|c| (
+                    c.hit.product_id.as_str(),
+                    c.title.as_str(),
+                    c.author.as_deref()
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    "ZHOR1234567",
+                    "<<日本文学>> 海に願いを風に祈りをそして君に誓いを",
+                    Some("汐見夏衛")
+                ),
+                (
+                    "ZHOR7654321",
+                    "<<日本文学>> 海に願いを風に祈りをそして君に誓いを 特装版",
+                    Some("汐見夏衛")
+                ),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_product_in_stock() -> Result<()> {
+        let html = include_str!("../../tests/fixtures/used_book/surugaya_product.html");
+        let details = parse_product(html)?;
+        assert_eq!(details.price, Some(330));
+        assert_eq!(details.condition, Some("中古".to_string()));
+        assert!(details.in_stock);
+        assert_eq!(details.isbn13.as_deref(), Some("9784813705185"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_product_out_of_stock() -> Result<()> {
+        let html = include_str!("../../tests/fixtures/used_book/surugaya_product_oos.html");
+        let details = parse_product(html)?;
+        assert_eq!(details.price, None);
+        assert_eq!(details.condition, None);
+        assert!(!details.in_stock);
+        assert_eq!(details.isbn13.as_deref(), Some("9784167158057"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_product_unknown_page() {
+        assert!(parse_product("<html><body></body></html>").is_err());
+    }
+}
@@ -0,0 +1,487 @@
+//! ISBN が分からない本を、中古本サイトのタイトル検索結果と照合する
+//!
+//! タイトル・著者名を NFKC 正規化し、括弧書きのレーベル名や「文庫」などの表記、
+//! 巻数の表記を取り除いてから、文字 bigram の一致度 (Dice 係数) で比べる。
+//! 巻数 (数字・漢数字・上中下・前編後編) が両方に書かれていて異なる場合は別の本とみなす。
+
+use unicode_normalization::UnicodeNormalization;
+
+use super::SearchHit;
+
+/// この一致度以上の検索結果だけをオファーとして保存する
+pub const MIN_MATCH_SCORE: f32 = 0.85;
+
+/// 著者名が両方分かる場合の、一致度に占める著者名の重み
+const AUTHOR_WEIGHT: f32 = 0.2;
+
+/// 片方にだけ巻数が書かれている場合の減点
+const VOLUME_MISSING_PENALTY: f32 = 0.1;
+
+/// タイトルの末尾に付くレーベル・版の表記 (空白区切りの語がこれで終わる場合は取り除く)
+const LABEL_SUFFIXES: [&str; 8] = [
+    "文庫",
+    "新書",
+    "ノベルス",
+    "コミックス",
+    "特装版",
+    "限定版",
+    "新装版",
+    "完全版",
+];
+
+/// タイトル検索の結果1件分
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TitleCandidate {
+    pub hit: SearchHit,
+    /// 検索結果に表示されたタイトル
+    pub title: String,
+    /// 検索結果に表示された著者名 (表示しないサイトは None)
+    pub author: Option<String>,
+}
+
+/// 照合用に正規化したタイトル
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NormalizedTitle {
+    /// 空白・記号・レーベル・巻数を取り除いた文字列
+    pub text: String,
+    /// タイトルに書かれた巻数
+    pub volume: Option<Volume>,
+}
+
+/// タイトルに書かれた巻数
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Volume {
+    /// 数字・漢数字の巻数 (「3」「第三巻」など)
+    Number(u32),
+    /// 上巻・前編
+    Upper,
+    /// 中巻
+    Middle,
+    /// 下巻・後編
+    Lower,
+}
+
+impl Volume {
+    /// 巻数だけが書かれた語 (「3」「三」「上」「前編」など) を読む
+    fn parse(word: &str) -> Option<Self> {
+        match word {
+            "上" | "前編" => Some(Self::Upper),
+            "中" => Some(Self::Middle),
+            "下" | "後編" => Some(Self::Lower),
+            _ if !word.is_empty() && word.chars().all(|c| c.is_ascii_digit()) => {
+                word.parse().ok().map(Self::Number)
+            }
+            _ => kanji_number(word).map(Self::Number),
+        }
+    }
+}
+
+/// サイトのキーワード検索に使う文字列
+///
+/// NFKC 正規化して括弧書きとレーベルの表記を除いたタイトルに、著者名があれば
+/// [`normalize_author`] で正規化した著者名をキーワードとして加える。
+#[must_use]
+pub fn search_query(title: &str, author: Option<&str>) -> String {
+    let nfkc: String = title.nfkc().collect();
+    let (without_brackets, _) = strip_brackets(&nfkc);
+    let author = author.map(normalize_author).filter(|a| !a.is_empty());
+    without_brackets
+        .split_whitespace()
+        .filter(|word| !LABEL_SUFFIXES.iter().any(|suffix| word.ends_with(suffix)))
+        .chain(author.as_deref())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// タイトルを NFKC 正規化し、巻数を取り出してレーベル・記号などの表記を取り除く
+#[must_use]
+pub fn normalize_title(title: &str) -> NormalizedTitle {
+    let nfkc: String = title.nfkc().collect::<String>().to_lowercase();
+    let (without_brackets, bracket_volume) = strip_brackets(&nfkc);
+    let words: Vec<&str> = without_brackets
+        .split_whitespace()
+        .filter(|word| !LABEL_SUFFIXES.iter().any(|suffix| word.ends_with(suffix)))
+        .collect();
+    let (joined, volume) = strip_volume(&words.join(" "));
+    NormalizedTitle {
+        text: joined.chars().filter(|c| c.is_alphanumeric()).collect(),
+        volume: volume.or(bracket_volume),
+    }
+}
+
+/// 著者名を NFKC 正規化し、空白・記号と「著」「作」などの役割表記を取り除く
+#[must_use]
+pub fn normalize_author(author: &str) -> String {
+    let nfkc: String = author.nfkc().collect::<String>().to_lowercase();
+    nfkc.split(['/', ','])
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .trim_end_matches(['著', '作', '訳', '編'])
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect()
+}
+
+/// 本のタイトル・著者名と検索結果の一致度 (0.0〜1.0)
+#[must_use]
+pub fn score(title: &str, author: Option<&str>, candidate: &TitleCandidate) -> f32 {
+    let expected = normalize_title(title);
+    let found = normalize_title(&candidate.title);
+    let mut title_score = dice(&expected.text, &found.text);
+    match (expected.volume, found.volume) {
+        (Some(a), Some(b)) if a != b => return 0.0,
+        (Some(_), None) | (None, Some(_)) => title_score -= VOLUME_MISSING_PENALTY,
+        _ => {}
+    }
+    let title_score = title_score.max(0.0);
+    let author_score = author
+        .map(normalize_author)
+        .zip(candidate.author.as_deref().map(normalize_author))
+        .filter(|(a, b)| !a.is_empty() && !b.is_empty())
+        .map(|(a, b)| {
+            if a.contains(&b) || b.contains(&a) {
+                1.0
+            } else {
+                0.0
+            }
+        });
+    match author_score {
+        Some(author_score) => title_score * (1.0 - AUTHOR_WEIGHT) + author_score * AUTHOR_WEIGHT,
+        None => title_score,
+    }
+}
+
+/// 一致度が `MIN_MATCH_SCORE` 以上の検索結果のうち最も一致度の高いものを選ぶ
+///
+/// 同じ一致度の場合は検索結果の先頭に近いものを選ぶ。
+#[must_use]
+pub fn best_match(
+    title: &str,
+    author: Option<&str>,
+    candidates: Vec<TitleCandidate>,
+) -> Option<(SearchHit, f32)> {
+    candidates
+        .into_iter()
+        .map(|candidate| {
+            let score = score(title, author, &candidate);
+            (candidate.hit, score)
+        })
+        .filter(|(_, score)| *score >= MIN_MATCH_SCORE)
+        .fold(
+            None,
+            |best: Option<(SearchHit, f32)>, (hit, score)| match best {
+                Some((_, best_score)) if best_score >= score => best,
+                _ => Some((hit, score)),
+            },
+        )
+}
+
+/// 括弧書き (レーベル名・ジャンルなど) を取り除く。括弧の中が巻数だけの場合は巻数として返す
+fn strip_brackets(text: &str) -> (String, Option<Volume>) {
+    const PAIRS: [(char, char); 6] = [
+        ('(', ')'),
+        ('[', ']'),
+        ('<', '>'),
+        ('【', '】'),
+        ('〈', '〉'),
+        ('《', '》'),
+    ];
+    let mut result = String::new();
+    let mut volume = None;
+    let mut stack: Vec<char> = Vec::new();
+    let mut inner = String::new();
+    for c in text.chars() {
+        if let Some((_, close)) = PAIRS.iter().find(|(open, _)| *open == c) {
+            stack.push(*close);
+            inner.clear();
+        } else if stack.last() == Some(&c) {
+            stack.pop();
+            if stack.is_empty() {
+                volume = volume.or_else(|| Volume::parse(inner.trim()));
+                // 括弧の前後の語がつながらないように区切る
+                result.push(' ');
+            }
+        } else if stack.is_empty() {
+            result.push(c);
+        } else {
+            inner.push(c);
+        }
+    }
+    (result, volume)
+}
+
+/// 「第3巻」「三巻」「上巻」「vol.3」や末尾の巻数 (「3」「上」「前編」など) を取り出して取り除く
+fn strip_volume(text: &str) -> (String, Option<Volume>) {
+    if let Some(pos) = text.find('巻') {
+        let before = &text[..pos];
+        let numeral_start = before
+            .char_indices()
+            .rev()
+            .take_while(|(_, c)| c.is_ascii_digit() || KANJI_DIGITS.contains(c) || *c == '十')
+            .last()
+            .map(|(i, _)| i);
+        let start = numeral_start.or_else(|| {
+            before
+                .char_indices()
+                .next_back()
+                .filter(|(_, c)| matches!(c, '上' | '中' | '下'))
+                .map(|(i, _)| i)
+        });
+        if let Some((start, volume)) =
+            start.and_then(|start| Some((start, Volume::parse(&before[start..])?)))
+        {
+            let start = before[..start].strip_suffix('第').map_or(start, str::len);
+            return (
+                format!("{} {}", &text[..start], &text[pos + '巻'.len_utf8()..]),
+                Some(volume),
+            );
+        }
+    }
+    if let Some(pos) = text.find("vol") {
+        let rest = text[pos + 3..].trim_start_matches(['.', ' ']);
+        let digits: String = rest.chars().take_while(char::is_ascii_digit).collect();
+        if let Ok(volume) = digits.parse() {
+            let end = text.len() - rest.len() + digits.len();
+            return (
+                format!("{} {}", &text[..pos], &text[end..]),
+                Some(Volume::Number(volume)),
+            );
+        }
+    }
+    // 末尾の空白区切りの巻数 (「ワンピース 3」「ノルウェイの森 上」など。巻数だけのタイトルは除く)
+    let trimmed = text.trim_end();
+    if let Some((head, last)) = trimmed.rsplit_once(' ') {
+        if let Some(volume) = Volume::parse(last).filter(|_| !head.trim().is_empty()) {
+            return (head.to_string(), Some(volume));
+        }
+    }
+    (text.to_string(), None)
+}
+
+/// 一〜九の漢数字 (位置 + 1 が値)
+const KANJI_DIGITS: [char; 9] = ['一', '二', '三', '四', '五', '六', '七', '八', '九'];
+
+/// 一〜九十九の漢数字 (「三」「十」「十二」「二十」など) を読む
+fn kanji_number(text: &str) -> Option<u32> {
+    let digit = |c: char| {
+        KANJI_DIGITS
+            .iter()
+            .position(|d| *d == c)
+            .and_then(|i| u32::try_from(i + 1).ok())
+    };
+    let chars: Vec<char> = text.chars().collect();
+    match chars.as_slice() {
+        [c] => digit(*c).or((*c == '十').then_some(10)),
+        ['十', ones] => Some(10 + digit(*ones)?),
+        [tens, '十'] => Some(digit(*tens)? * 10),
+        [tens, '十', ones] => Some(digit(*tens)? * 10 + digit(*ones)?),
+        _ => None,
+    }
+}
+
+/// 文字 bigram の Dice 係数 (どちらかが1文字以下の場合は完全一致かどうか)
+#[expect(
+    clippy::cast_precision_loss,
+    reason = "タイトルの bigram 数は f32 で正確に表せる範囲に収まる"
+)]
+fn dice(a: &str, b: &str) -> f32 {
+    if a == b {
+        return 1.0;
+    }
+    let bigrams = |s: &str| -> Vec<(char, char)> {
+        let chars: Vec<char> = s.chars().collect();
+        chars.windows(2).map(|w| (w[0], w[1])).collect()
+    };
+    let a = bigrams(a);
+    let mut b = bigrams(b);
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let total = a.len() + b.len();
+    let mut common = 0;
+    for bigram in &a {
+        if let Some(pos) = b.iter().position(|other| other == bigram) {
+            b.swap_remove(pos);
+            common += 1;
+        }
+    }
+    (2 * common) as f32 / total as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(title: &str, author: Option<&str>) -> TitleCandidate {
+        TitleCandidate {
+            hit: SearchHit {
+                product_id: title.to_string(),
+                product_url: format!("https://example.com/{title}"),
+                details: None,
+            },
+            title: title.to_string(),
+            author: author.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_normalize_title() {
+        assert_eq!(
+            normalize_title("海に願いを風に祈りをそして君に誓いを スターツ出版文庫"),
+            NormalizedTitle {
+                text: "海に願いを風に祈りをそして君に誓いを".to_string(),
+                volume: None,
+            }
+        );
+        assert_eq!(
+            normalize_title("<<日本文学>> ＳＰＹ×ＦＡＭＩＬＹ　第３巻 (ジャンプコミックス)"),
+            NormalizedTitle {
+                text: "spyfamily".to_string(),
+                volume: Some(Volume::Number(3)),
+            }
+        );
+        assert_eq!(
+            normalize_title("ワンピース 105").volume,
+            Some(Volume::Number(105))
+        );
+        assert_eq!(
+            normalize_title("魔法科高校の劣等生 Vol.2").volume,
+            Some(Volume::Number(2))
+        );
+        assert_eq!(
+            normalize_title("葬送のフリーレン (12)").volume,
+            Some(Volume::Number(12))
+        );
+        assert_eq!(
+            normalize_title("進撃の巨人 第十二巻"),
+            NormalizedTitle {
+                text: "進撃の巨人".to_string(),
+                volume: Some(Volume::Number(12)),
+            }
+        );
+        assert_eq!(
+            normalize_title("ノルウェイの森 上"),
+            NormalizedTitle {
+                text: "ノルウェイの森".to_string(),
+                volume: Some(Volume::Upper),
+            }
+        );
+        assert_eq!(
+            normalize_title("ノルウェイの森 (下) (講談社文庫)").volume,
+            Some(Volume::Lower)
+        );
+        assert_eq!(normalize_title("罪と罰 中巻").volume, Some(Volume::Middle));
+        assert_eq!(normalize_title("君の名は 前編").volume, Some(Volume::Upper));
+        assert_eq!(normalize_title("天気の子 後編").volume, Some(Volume::Lower));
+        // 巻数ではない「上」「十」は取り除かない
+        assert_eq!(normalize_title("風の上").volume, None);
+        assert_eq!(normalize_title("下町ロケット").volume, None);
+        assert_eq!(
+            normalize_title("1984"),
+            NormalizedTitle {
+                text: "1984".to_string(),
+                volume: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_search_query() {
+        assert_eq!(
+            search_query("ＳＰＹ×ＦＡＭＩＬＹ　３ (ジャンプコミックス)", None),
+            "SPY×FAMILY 3"
+        );
+        assert_eq!(
+            search_query(
+                "海に願いを風に祈りをそして君に誓いを (スターツ出版文庫)",
+                Some("汐見 夏衛 著")
+            ),
+            "海に願いを風に祈りをそして君に誓いを 汐見夏衛"
+        );
+    }
+
+    #[test]
+    fn test_normalize_author() {
+        assert_eq!(normalize_author("汐見 夏衛 著"), "汐見夏衛");
+        assert_eq!(normalize_author("遠藤達哉 / 集英社"), "遠藤達哉");
+    }
+
+    #[test]
+    fn test_score() {
+        let title = "海に願いを風に祈りをそして君に誓いを";
+        let exact = candidate(
+            "海に願いを風に祈りをそして君に誓いを (スターツ出版文庫)",
+            Some("汐見夏衛"),
+        );
+        assert!((score(title, Some("汐見 夏衛"), &exact) - 1.0).abs() < f32::EPSILON);
+        let other_author = candidate(title, Some("別の著者"));
+        assert!(score(title, Some("汐見夏衛"), &other_author) < MIN_MATCH_SCORE);
+        let unrelated = candidate("君の膵臓をたべたい", None);
+        assert!(score(title, None, &unrelated) < MIN_MATCH_SCORE);
+    }
+
+    #[test]
+    fn test_score_volume_mismatch() {
+        let wrong_volume = candidate("SPY×FAMILY 2", Some("遠藤達哉"));
+        assert!(score("SPY×FAMILY 3", Some("遠藤達哉"), &wrong_volume).abs() < f32::EPSILON);
+        let right_volume = candidate("ＳＰＹ×ＦＡＭＩＬＹ ３巻", Some("遠藤達哉"));
+        assert!(score("SPY×FAMILY 3", Some("遠藤達哉"), &right_volume) >= MIN_MATCH_SCORE);
+
+        // 上下巻・前後編・漢数字の巻数
+        for (title, other) in [
+            ("ノルウェイの森 上", "ノルウェイの森 下"),
+            ("ノルウェイの森 (上)", "ノルウェイの森 下巻"),
+            ("罪と罰 中巻", "罪と罰 上巻"),
+            ("君の名は 前編", "君の名は 後編"),
+            ("進撃の巨人 第三巻", "進撃の巨人 第四巻"),
+            ("進撃の巨人 十", "進撃の巨人 第一巻"),
+        ] {
+            assert!(
+                score(title, None, &candidate(other, None)).abs() < f32::EPSILON,
+                "{title} / {other}"
+            );
+        }
+        for (title, other) in [
+            ("ノルウェイの森 上", "ノルウェイの森 (上) (講談社文庫)"),
+            ("君の名は 前編", "君の名は 上巻"),
+            ("進撃の巨人 第三巻", "進撃の巨人 3"),
+        ] {
+            assert!(
+                score(title, None, &candidate(other, None)) >= MIN_MATCH_SCORE,
+                "{title} / {other}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_best_match() {
+        let candidates = vec![
+            candidate("君の膵臓をたべたい", Some("住野よる")),
+            candidate("海に願いを風に祈りをそして君に誓いを", Some("汐見夏衛")),
+            candidate(
+                "海に願いを風に祈りをそして君に誓いを 特装版",
+                Some("汐見夏衛"),
+            ),
+        ];
+        let (hit, score) = best_match(
+            "海に願いを風に祈りをそして君に誓いを",
+            Some("汐見夏衛"),
+            candidates,
+        )
+        .unzip();
+        assert_eq!(
+            hit.map(|hit| hit.product_id),
+            Some("海に願いを風に祈りをそして君に誓いを".to_string())
+        );
+        assert!(score.is_some_and(|score| score >= MIN_MATCH_SCORE));
+        assert_eq!(
+            best_match(
+                "まったく別の本",
+                None,
+                vec![candidate("君の膵臓をたべたい", None)]
+            ),
+            None
+        );
+    }
+}
@@ -5,15 +5,71 @@
 //!   ヒットしない場合は `/search` に留まる。
 //! - 商品ページ: Vue の `<router-view :item-info="{...}">` に
 //!   状態 (condition) ごとの価格・在庫を持つ JSON が埋め込まれている。
+//! - タイトル検索: 検索結果は `<vb-search>` がブラウザで描画するため HTML に含まれず、
+//!   対応していない (ISBN が分からない本は照合しない)。
+
+use std::sync::Once;
+use std::time::Duration;
 
 use anyhow::Result;
 use scraper::{Html, Selector};
 use serde::Deserialize;
 
-use super::{http_client, OfferDetails, SearchHit};
+use futures::future::BoxFuture;
+
+use super::{ConditionVariant, OfferDetails, SearchHit, TitleCandidate, UsedBookStore};
+use crate::fetcher::{FetchRequest, Fetcher};
 
 const BASE_URL: &str = "https://www.valuebooks.jp";
 
+/// バリューブックス
+pub struct ValueBooks;
+
+impl UsedBookStore for ValueBooks {
+    fn site_key(&self) -> &'static str {
+        "valuebooks"
+    }
+
+    fn default_base_url(&self) -> &'static str {
+        BASE_URL
+    }
+
+    fn min_request_interval(&self) -> Duration {
+        Duration::from_secs(1)
+    }
+
+    fn search<'a>(
+        &'a self,
+        fetcher: &'a dyn Fetcher,
+        isbn13: &'a str,
+    ) -> BoxFuture<'a, Result<Option<SearchHit>>> {
+        Box::pin(search(fetcher, isbn13))
+    }
+
+    fn search_by_title<'a>(
+        &'a self,
+        _fetcher: &'a dyn Fetcher,
+        _title: &'a str,
+        _author: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<Vec<TitleCandidate>>> {
+        static WARNED: Once = Once::new();
+        WARNED.call_once(|| {
+            tracing::warn!(
+                "valuebooks renders title search results in the browser; books without an ISBN are not matched on valuebooks"
+            );
+        });
+        Box::pin(async { Ok(Vec::new()) })
+    }
+
+    fn fetch_details<'a>(
+        &'a self,
+        fetcher: &'a dyn Fetcher,
+        product_url: &'a str,
+    ) -> BoxFuture<'a, Result<OfferDetails>> {
+        Box::pin(fetch_details(fetcher, product_url))
+    }
+}
+
 /// ISBN-13 で検索して商品を取得する
 ///
 /// 検索は商品ページへリダイレクトされるため、レスポンス HTML をそのまま解析し
@@ -22,11 +78,12 @@ const BASE_URL: &str = "https://www.valuebooks.jp";
 /// # Errors
 ///
 /// HTTP リクエストまたは HTML の解析に失敗した場合にエラーを返す。
-pub async fn search(isbn13: &str) -> Result<Option<SearchHit>> {
+pub async fn search(fetcher: &dyn Fetcher, isbn13: &str) -> Result<Option<SearchHit>> {
     let url = format!("{BASE_URL}/search?keyword={isbn13}");
-    let response = http_client()?.get(&url).send().await?;
-    let final_url = response.url().to_string();
-    let html = response.text().await?;
+    // ヒットしない場合も検索ページが返るだけなので、ステータスは確認しない
+    let response = fetcher.fetch(&FetchRequest::get(&url)).await?;
+    let final_url = response.url;
+    let html = response.body;
     let Some(product_id) = product_id_from_url(&final_url) else {
         return Ok(None);
     };
@@ -42,9 +99,9 @@ pub async fn search(isbn13: &str) -> Result<Option<SearchHit>> {
 /// # Errors
 ///
 /// HTTP リクエストまたは HTML の解析に失敗した場合にエラーを返す。
-pub async fn fetch_details(product_url: &str) -> Result<OfferDetails> {
-    let html = http_client()?.get(product_url).send().await?.text().await?;
-    parse_product(&html)
+pub async fn fetch_details(fetcher: &dyn Fetcher, product_url: &str) -> Result<OfferDetails> {
+    let html = super::get_text(fetcher, product_url).await?;
+    crate::snapshot::parse_or_record("valuebooks.product", product_url, &html, parse_product).await
 }
 
 /// 商品ページ URL (`.../bp/VS0051080734`) から商品 ID を取り出す
@@ -81,12 +138,14 @@ struct Genpin {
 
 /// 商品ページ HTML から価格・在庫・状態を取り出す
 ///
-/// 在庫のある状態のうち最安のものを `price` / `condition` とする。
+/// 在庫のある状態のうち最安のものを `price` / `condition` とし、
+/// 全ての状態 (NEW / `VERY_GOOD` / GOOD / ACCEPTABLE) を `variants` に残す。
 ///
 /// # Errors
 ///
 /// ページ構造の解析に失敗した場合にエラーを返す。
 pub fn parse_product(html: &str) -> Result<OfferDetails> {
+    let isbn13 = super::parse_isbn(html)?;
     let doc = Html::parse_document(html);
     let selector = Selector::parse("router-view")
         .map_err(|e| anyhow::anyhow!("Failed to parse selector: {e:?}"))?;
@@ -97,22 +156,42 @@ pub fn parse_product(html: &str) -> Result<OfferDetails> {
         .and_then(|json| serde_json::from_str::<ItemInfo>(json).ok());
 
     if let Some(item_info) = item_info {
-        // 在庫のある状態のうち最安のものを選ぶ
-        let cheapest = item_info
+        let variants: Vec<ConditionVariant> = item_info
             .genpin_list
             .iter()
-            .filter(|g| g.stock > 0 && g.price.is_some())
-            .min_by_key(|g| g.price);
+            .map(|g| ConditionVariant {
+                condition: g.condition_name.clone(),
+                price: g.price,
+                stock_count: i32::try_from(g.stock).ok(),
+                in_stock: g.stock > 0 && g.price.is_some(),
+            })
+            .collect();
+        // 在庫のある状態のうち最安のものを選ぶ
+        let cheapest = variants
+            .iter()
+            .filter(|v| v.in_stock)
+            .min_by_key(|v| v.price)
+            .cloned();
         return Ok(match cheapest {
-            Some(genpin) => OfferDetails {
-                price: genpin.price,
-                condition: Some(genpin.condition_name.clone()),
+            Some(variant) => OfferDetails {
+                price: variant.price,
+                condition: Some(variant.condition),
                 in_stock: true,
+                stock_count: variant.stock_count,
+                listing_type: None,
+                variants,
+                isbn13,
+                listing: None,
             },
             None => OfferDetails {
                 price: None,
                 condition: None,
                 in_stock: false,
+                stock_count: Some(0),
+                listing_type: None,
+                variants,
+                isbn13,
+                listing: None,
             },
         });
     }
@@ -123,6 +202,11 @@ pub fn parse_product(html: &str) -> Result<OfferDetails> {
             price,
             condition: None,
             in_stock,
+            stock_count: None,
+            listing_type: None,
+            variants: Vec::new(),
+            isbn13,
+            listing: None,
         });
     }
     Err(anyhow::anyhow!("Failed to parse ValueBooks product page"))
@@ -159,6 +243,33 @@ mod tests {
         assert_eq!(details.price, Some(287));
         assert_eq!(details.condition, Some("GOOD".to_string()));
         assert!(details.in_stock);
+        assert_eq!(details.stock_count, Some(1));
+        assert_eq!(details.isbn13.as_deref(), Some("9784813705185"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_product_variants() -> Result<()> {
+        let html = include_str!("../../tests/fixtures/used_book/valuebooks_product.html");
+        let details = parse_product(html)?;
+        let variants: Vec<(&str, Option<i32>, Option<i32>, bool)> = details
+            .variants
+            .iter()
+            .map(|v| (v.condition.as_str(), v.price, v.stock_count, v.in_stock))
+            .collect();
+        assert_eq!(
+            variants,
+            vec![
+                ("NEW", None, Some(0), false),
+                ("VERY_GOOD", None, Some(0), false),
+                ("GOOD", Some(287), Some(1), true),
+                ("ACCEPTABLE", None, Some(0), false),
+            ]
+        );
+        assert_eq!(
+            details.variants[2].grade(),
+            crate::used_book::ConditionGrade::Good
+        );
         Ok(())
     }
 
@@ -169,6 +280,8 @@ mod tests {
         assert_eq!(details.price, None);
         assert_eq!(details.condition, None);
         assert!(!details.in_stock);
+        assert_eq!(details.stock_count, Some(0));
+        assert_eq!(details.isbn13.as_deref(), Some("9784041004098"));
         Ok(())
     }
 
@@ -1,16 +1,21 @@
 use sea_orm::{entity::prelude::*, ActiveValue::Set};
 use serde::{Deserialize, Serialize};
 
-use crate::used_book::OfferUpdate;
+use crate::shipping_fee;
+use crate::used_book::{ConditionGrade, OfferUpdate};
+use crate::used_book_offer_condition;
 
-/// 中古本サイト (bookoff / valuebooks / netoff) の商品オファー
+/// この在庫数以下のオファーを残りわずか (`is_low_stock`) とみなす
+pub const LOW_STOCK_THRESHOLD: i32 = 1;
+
+/// 中古本サイト (bookoff / valuebooks / netoff / surugaya / mottainai / `amazon_used`) の商品オファー
 #[derive(Clone, Debug, DeriveEntityModel, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 #[sea_orm(table_name = "used_book_offers")]
 pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
     pub bookmeter_id: i64,
-    /// `UsedBookSite::as_str()` の値 (bookoff / valuebooks / netoff)
+    /// `UsedBookStore::site_key()` の値 (bookoff / valuebooks / netoff / surugaya / mottainai) または `amazon_used`
     #[sea_orm(primary_key, auto_increment = false)]
     pub site: String,
     pub product_id: Option<String>,
@@ -19,10 +24,87 @@ pub struct Model {
     pub price: Option<i32>,
     /// 商品状態 (バリューブックスの GOOD など)。サイトが状態を持たない場合は None
     pub condition: Option<String>,
+    /// `condition` をサイト間で比較できるようにした状態ランク (`ConditionGrade::as_str()` の値)
+    pub condition_grade: String,
     pub in_stock: bool,
+    /// 残りの在庫数 (netoff / valuebooks のみ)
+    pub stock_count: Option<i32>,
+    /// 新品 (`new`) / 中古 (`used`) のどちらの出品か (`ListingType::as_str()` の値。bookoff のみ)
+    pub listing_type: Option<String>,
+    /// 出品数 (`amazon_used` のみ)
+    pub offer_count: Option<i32>,
+    /// タイトル検索で商品を特定した場合の一致度 (0.0〜1.0)。ISBN で特定した場合は None
+    pub match_score: Option<f32>,
+    /// 1冊だけ注文した場合の送料込みの価格 (円)。`shipping_fees` にサイトの設定がない場合は None
+    pub landed_price: Option<i32>,
     pub updated_at: chrono::NaiveDateTime,
 }
 
+impl Model {
+    /// 在庫があり、残りが `LOW_STOCK_THRESHOLD` 冊以下か (在庫数が分からない場合は false)
+    #[must_use]
+    pub fn is_low_stock(&self) -> bool {
+        self.in_stock
+            && self
+                .stock_count
+                .is_some_and(|count| (1..=LOW_STOCK_THRESHOLD).contains(&count))
+    }
+}
+
+/// `min_grade` 以上の状態で在庫があるオファーだけを、その状態の価格で送料込みの価格の安い順に返す
+///
+/// 状態ごとの価格・在庫 (`conditions`) があるサイトは、`min_grade` 以上で在庫がある状態のうち
+/// 最も安いものの価格・状態・在庫数に置き換え (送料込みの価格も計算し直す)、該当する状態が
+/// なければ除く。オファー自体の状態ランクは最も安い状態のものなので、状態ごとの価格がない
+/// サイトだけオファー自体の状態ランクで判定する。
+#[must_use]
+pub fn select_by_grade(
+    offers: Vec<Model>,
+    conditions: &[used_book_offer_condition::Model],
+    shipping_fees: &[shipping_fee::Model],
+    min_grade: ConditionGrade,
+) -> Vec<Model> {
+    let grades = min_grade.at_least();
+    let mut selected: Vec<Model> = offers
+        .into_iter()
+        .filter_map(|mut offer| {
+            let variants: Vec<_> = conditions
+                .iter()
+                .filter(|c| c.bookmeter_id == offer.bookmeter_id && c.site == offer.site)
+                .collect();
+            if variants.is_empty() {
+                return grades
+                    .contains(&offer.condition_grade.as_str())
+                    .then_some(offer);
+            }
+            let cheapest = variants
+                .into_iter()
+                .filter(|c| c.in_stock && grades.contains(&c.grade.as_str()))
+                .min_by_key(|c| c.price.unwrap_or(i32::MAX))?;
+            offer.price = cheapest.price;
+            offer.condition = Some(cheapest.condition.clone());
+            offer.condition_grade.clone_from(&cheapest.grade);
+            offer.stock_count = cheapest.stock_count;
+            offer.in_stock = true;
+            offer.landed_price = cheapest
+                .price
+                .zip(shipping_fees.iter().find(|fee| fee.site == offer.site))
+                .map(|(price, fee)| fee.landed_price(price));
+            Some(offer)
+        })
+        .collect();
+    // DB の並び順 (NULL は最後) に合わせる
+    selected.sort_by_key(|offer| {
+        (
+            offer.landed_price.is_none(),
+            offer.landed_price,
+            offer.price.is_none(),
+            offer.price,
+        )
+    });
+    selected
+}
+
 impl ActiveModelBehavior for ActiveModel {}
 
 #[derive(Debug, EnumIter, DeriveRelation)]
@@ -36,7 +118,12 @@ impl ActiveModel {
         self.product_url = Set(update.product_url.clone());
         self.price = Set(update.price);
         self.condition = Set(update.condition.clone());
+        self.condition_grade = Set(update.condition_grade().as_str().to_string());
         self.in_stock = Set(update.in_stock);
+        self.stock_count = Set(update.stock_count);
+        self.listing_type = Set(update.listing_type.map(|t| t.as_str().to_string()));
+        self.match_score = Set(update.match_score);
+        self.offer_count = Set(update.offer_count);
         self.updated_at = Set(chrono::Utc::now().naive_utc());
         self
     }
@@ -44,7 +131,128 @@ impl ActiveModel {
 
 impl From<&OfferUpdate> for ActiveModel {
     fn from(update: &OfferUpdate) -> Self {
-        // bookmeter_id / site は NotSet のまま返すので、呼び出し側でセットする
+        // bookmeter_id / site / landed_price は NotSet のまま返すので、呼び出し側でセットする
         <ActiveModel as Default>::default().apply_update(update)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offer(in_stock: bool, stock_count: Option<i32>) -> Model {
+        Model {
+            bookmeter_id: 1,
+            site: "netoff".to_string(),
+            product_id: None,
+            product_url: None,
+            price: Some(220),
+            condition: None,
+            condition_grade: "unknown".to_string(),
+            in_stock,
+            stock_count,
+            listing_type: None,
+            offer_count: None,
+            match_score: None,
+            landed_price: None,
+            updated_at: chrono::NaiveDateTime::default(),
+        }
+    }
+
+    fn variant(
+        site: &str,
+        condition: &str,
+        price: i32,
+        in_stock: bool,
+    ) -> used_book_offer_condition::Model {
+        used_book_offer_condition::Model {
+            bookmeter_id: 1,
+            site: site.to_string(),
+            condition: condition.to_string(),
+            grade: ConditionGrade::from_raw(condition).as_str().to_string(),
+            price: Some(price),
+            stock_count: Some(1),
+            in_stock,
+            updated_at: chrono::NaiveDateTime::default(),
+        }
+    }
+
+    #[test]
+    fn test_select_by_grade() {
+        // 最も安い在庫は ACCEPTABLE だが VERY_GOOD の在庫もあるバリューブックスのオファー
+        let valuebooks = Model {
+            site: "valuebooks".to_string(),
+            price: Some(120),
+            condition: Some("ACCEPTABLE".to_string()),
+            condition_grade: "acceptable".to_string(),
+            landed_price: Some(470),
+            ..offer(true, Some(1))
+        };
+        let netoff = Model {
+            landed_price: Some(440),
+            ..offer(true, None)
+        };
+        let conditions = [
+            variant("valuebooks", "ACCEPTABLE", 120, true),
+            variant("valuebooks", "LIKE_NEW", 900, false),
+            variant("valuebooks", "VERY_GOOD", 380, true),
+            variant("valuebooks", "GOOD", 250, true),
+        ];
+        let shipping_fees = [shipping_fee::Model {
+            site: "valuebooks".to_string(),
+            fee: 350,
+            free_threshold: Some(1500),
+            member_fee: None,
+            member_free_threshold: None,
+            is_member: false,
+        }];
+
+        let selected = select_by_grade(
+            vec![netoff.clone(), valuebooks.clone()],
+            &conditions,
+            &shipping_fees,
+            ConditionGrade::VeryGood,
+        );
+        // 状態ごとの価格がないネットオフは状態が分からないので除き、
+        // バリューブックスは在庫がある VERY_GOOD の価格にする
+        assert_eq!(selected.len(), 1);
+        assert_eq!(selected[0].site, "valuebooks");
+        assert_eq!(selected[0].price, Some(380));
+        assert_eq!(selected[0].condition.as_deref(), Some("VERY_GOOD"));
+        assert_eq!(selected[0].condition_grade, "very-good");
+        assert_eq!(selected[0].landed_price, Some(730));
+
+        // ランクの指定が最も安い状態以下なら価格はそのままで、送料込みの価格の安い順
+        let selected = select_by_grade(
+            vec![valuebooks, netoff],
+            &conditions,
+            &shipping_fees,
+            ConditionGrade::Unknown,
+        );
+        let prices: Vec<_> = selected
+            .iter()
+            .map(|offer| (offer.site.as_str(), offer.price))
+            .collect();
+        assert_eq!(
+            prices,
+            vec![("netoff", Some(220)), ("valuebooks", Some(120))]
+        );
+
+        // 指定したランク以上の在庫がなければ除く
+        assert!(select_by_grade(
+            vec![offer(true, None)],
+            &[variant("netoff", "GOOD", 220, false)],
+            &shipping_fees,
+            ConditionGrade::Good,
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_is_low_stock() {
+        assert!(offer(true, Some(1)).is_low_stock());
+        assert!(!offer(true, Some(2)).is_low_stock());
+        assert!(!offer(true, None).is_low_stock());
+        assert!(!offer(false, Some(0)).is_low_stock());
+    }
+}
@@ -0,0 +1,48 @@
+use sea_orm::{entity::prelude::*, ActiveValue::Set};
+use serde::{Deserialize, Serialize};
+
+use crate::used_book::ConditionVariant;
+
+/// 中古本オファーの状態ごとの価格・在庫 (`used_book_offers` の子テーブル)
+#[derive(Clone, Debug, DeriveEntityModel, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[sea_orm(table_name = "used_book_offer_conditions")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub bookmeter_id: i64,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub site: String,
+    /// サイト上の状態の表記 (`VERY_GOOD` / 非常に良い など)
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub condition: String,
+    /// サイト間で比較できる状態ランク (`ConditionGrade::as_str()` の値)
+    pub grade: String,
+    /// 税込価格 (円)
+    pub price: Option<i32>,
+    /// 在庫数 (サイトが在庫数を表示する場合のみ)
+    pub stock_count: Option<i32>,
+    pub in_stock: bool,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+#[derive(Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModel {
+    /// 商品ページから読んだ状態1つ分を `ActiveModel` に変換する
+    #[must_use]
+    pub fn from_variant(bookmeter_id: i64, site: &str, variant: &ConditionVariant) -> Self {
+        ActiveModel {
+            bookmeter_id: Set(bookmeter_id),
+            site: Set(site.to_string()),
+            condition: Set(variant.condition.clone()),
+            grade: Set(variant.grade().as_str().to_string()),
+            price: Set(variant.price),
+            stock_count: Set(variant.stock_count),
+            in_stock: Set(variant.in_stock),
+            updated_at: Set(chrono::Utc::now().naive_utc()),
+        }
+    }
+}
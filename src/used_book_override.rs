@@ -0,0 +1,49 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// 本ごとの中古本オファー取得の指定 (書籍の形式による絞り込みより優先する)
+///
+/// 読みたい物リストから外れて `books` から消えても指定を残すため、`books` への外部キーは張らない。
+#[derive(Clone, Debug, DeriveEntityModel, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[sea_orm(table_name = "used_book_overrides")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub bookmeter_id: i64,
+    /// `UsedBookLookup::as_str()` の値 (always / never)
+    pub lookup: String,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+#[derive(Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+/// 中古本オファーを取得するかどうかの指定
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UsedBookLookup {
+    /// 書籍の形式に関わらず常に取得する
+    Always,
+    /// 書籍の形式に関わらず取得しない
+    Never,
+}
+
+impl UsedBookLookup {
+    /// DB に保存する値
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            UsedBookLookup::Always => "always",
+            UsedBookLookup::Never => "never",
+        }
+    }
+
+    /// DB に保存した値・CLI の引数から戻す (未知の値は `None`)
+    #[must_use]
+    pub fn parse(value: &str) -> Option<Self> {
+        [UsedBookLookup::Always, UsedBookLookup::Never]
+            .into_iter()
+            .find(|lookup| lookup.as_str() == value)
+    }
+}
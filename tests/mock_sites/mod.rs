@@ -0,0 +1,253 @@
+//! 読書メーター・Amazon・listasin・中古本サイトの代わりにフィクスチャを返すローカルのスタブサーバー
+//!
+//! サイトごとに `/bookmeter` や `/bookoff` のようなパスの下で応答する。
+//! [`MockServer::fetcher`] は各スクレイパーのベース URL をこのサーバーに差し替えた取得方法を返すので、
+//! ネットワークなしでスクレイパーやパイプライン全体を動かせる。
+//!
+//! 返すページ (`tests/fixtures/mock_sites` / `tests/fixtures/used_book`):
+//!
+//! - 読書メーター: ユーザー 9999998 の読みたい本 (999999801 の文庫と 999999802 のコミック)、
+//!   それぞれの本ページと `external_book_stores.json`
+//! - Amazon: 4813705189 (文庫) と 4088843142 (コミック) の商品ページ
+//! - listasin: それぞれの Kindle 版 (B07L3K7V5N / B0DJB4QN8R) の価格
+//! - 中古本サイト: ISBN 9784813705185 の検索結果と商品ページ (BOOKOFF は新品の出品も)
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use axum::extract::State;
+use axum::http::{header, StatusCode, Uri};
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::Router;
+use bookmeter_discounts::fetcher::{Fetcher, LiveFetcher, RebasedFetcher, Site};
+use bookmeter_discounts::used_book::UsedBookStoreRegistry;
+
+/// スタブサーバーが返すレスポンス
+#[derive(Clone)]
+enum Page {
+    Html(String),
+    Json(String),
+    /// 同じサーバー上のパスへのリダイレクト
+    Redirect(String),
+}
+
+impl IntoResponse for Page {
+    fn into_response(self) -> Response {
+        match self {
+            Self::Html(body) => {
+                ([(header::CONTENT_TYPE, "text/html; charset=utf-8")], body).into_response()
+            }
+            Self::Json(body) => {
+                ([(header::CONTENT_TYPE, "application/json")], body).into_response()
+            }
+            Self::Redirect(location) => Redirect::to(&location).into_response(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Routes {
+    /// パス (クエリ付きまたはクエリなし) ごとのレスポンス
+    pages: HashMap<String, Page>,
+    /// 該当するページがなく 404 を返したリクエスト
+    unmatched: Mutex<Vec<String>>,
+}
+
+impl Routes {
+    /// `/{prefix}{path}` (`prefix` はサイトのパス) で `page` を返す
+    fn page(&mut self, prefix: &str, path: &str, page: Page) {
+        self.pages.insert(format!("/{prefix}{path}"), page);
+    }
+
+    /// フィクスチャを全て読み込む
+    fn from_fixtures() -> Result<Self> {
+        let mut routes = Self::default();
+        let html = |name: &str| fixture(name).map(Page::Html);
+
+        routes.page(
+            "bookmeter",
+            "/users/9999998/books/wish?page=1",
+            html("mock_sites/bookmeter_wishlist.html")?,
+        );
+        for id in ["999999801", "999999802"] {
+            routes.page(
+                "bookmeter",
+                &format!("/books/{id}"),
+                html(&format!("mock_sites/bookmeter_book_{id}.html"))?,
+            );
+            routes.page(
+                "bookmeter",
+                &format!("/api/v1/books/{id}/external_book_stores.json"),
+                Page::Json(fixture(&format!(
+                    "mock_sites/external_book_stores_{id}.json"
+                ))?),
+            );
+        }
+        for asin in ["4813705189", "4088843142"] {
+            routes.page(
+                "amazon",
+                &format!("/dp/{asin}"),
+                html(&format!("mock_sites/amazon_{asin}.html"))?,
+            );
+        }
+        for kindle_id in ["B07L3K7V5N", "B0DJB4QN8R"] {
+            routes.page(
+                "listasin",
+                &format!("/kndlsl/asins/{kindle_id}"),
+                html(&format!("mock_sites/listasin_{kindle_id}.html"))?,
+            );
+        }
+
+        let isbn = "9784813705185";
+        routes.page(
+            "bookoff",
+            &format!("/search/keyword/{isbn}"),
+            html("used_book/bookoff_search.html")?,
+        );
+        routes.page(
+            "bookoff",
+            "/used/0019117467",
+            html("used_book/bookoff_product.html")?,
+        );
+        // 同じ商品の新品の出品 (在庫切れ)
+        routes.page(
+            "bookoff",
+            "/new/0019117467",
+            html("used_book/bookoff_product_oos.html")?,
+        );
+        // ValueBooks は ISBN で検索すると商品ページにリダイレクトする
+        routes.page(
+            "valuebooks",
+            &format!("/search?keyword={isbn}"),
+            Page::Redirect(format!("/{}/bp/VS0051080734", "valuebooks")),
+        );
+        routes.page(
+            "valuebooks",
+            "/bp/VS0051080734",
+            html("used_book/valuebooks_product.html")?,
+        );
+        routes.page(
+            "netoff",
+            &format!("/cmdtyallsearch/?cat=1002&word={isbn}"),
+            html("used_book/netoff_search.html")?,
+        );
+        routes.page(
+            "netoff",
+            "/detail/0012822282/",
+            html("used_book/netoff_product.html")?,
+        );
+        routes.page(
+            "surugaya",
+            &format!("/search?category=&search_word={isbn}"),
+            html("used_book/surugaya_search.html")?,
+        );
+        routes.page(
+            "surugaya",
+            "/product/detail/ZHOR1234567",
+            html("used_book/surugaya_product.html")?,
+        );
+        routes.page(
+            "mottainai",
+            &format!("/search/?q={isbn}"),
+            html("used_book/mottainai_search.html")?,
+        );
+        routes.page(
+            "mottainai",
+            "/item/1012345678/",
+            html("used_book/mottainai_product.html")?,
+        );
+        Ok(routes)
+    }
+}
+
+/// 起動したスタブサーバー
+pub struct MockServer {
+    addr: SocketAddr,
+    routes: Arc<Routes>,
+}
+
+impl MockServer {
+    /// フィクスチャを返すサーバーを空いているポートで起動する
+    ///
+    /// サーバーはテストのランタイムが終わるまで動き続ける。
+    pub async fn start() -> Result<Self> {
+        let routes = Arc::new(Routes::from_fixtures()?);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let app = Router::new()
+            .fallback(serve_page)
+            .with_state(Arc::clone(&routes));
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        Ok(Self { addr, routes })
+    }
+
+    /// 全てのサイトのベース URL をこのサーバーに差し替えた取得方法
+    ///
+    /// 中古本サイトはサイトキー (`/bookoff` など) の下に向ける。
+    pub fn fetcher(&self) -> Arc<dyn Fetcher> {
+        let stores = UsedBookStoreRegistry::all();
+        let fetcher = Site::ALL
+            .into_iter()
+            .map(|site| (site.default_base_url(), prefix(site)))
+            .chain(
+                stores
+                    .iter()
+                    .map(|store| (store.default_base_url(), store.site_key())),
+            )
+            .fold(
+                RebasedFetcher::new(Arc::new(LiveFetcher::new())),
+                |fetcher, (default_base_url, prefix)| {
+                    fetcher
+                        .with_base_url(default_base_url, &format!("http://{}/{prefix}", self.addr))
+                },
+            );
+        Arc::new(fetcher)
+    }
+
+    /// 該当するページがなかったリクエストのパス
+    pub fn unmatched_requests(&self) -> Vec<String> {
+        self.routes
+            .unmatched
+            .lock()
+            .map(|unmatched| unmatched.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// クエリ付きのパス、クエリなしのパスの順にページを探して返す
+async fn serve_page(State(routes): State<Arc<Routes>>, uri: Uri) -> Response {
+    let path_and_query = uri
+        .path_and_query()
+        .map_or_else(|| uri.path().to_string(), ToString::to_string);
+    if let Some(page) = routes
+        .pages
+        .get(&path_and_query)
+        .or_else(|| routes.pages.get(uri.path()))
+    {
+        return page.clone().into_response();
+    }
+    if let Ok(mut unmatched) = routes.unmatched.lock() {
+        unmatched.push(path_and_query);
+    }
+    StatusCode::NOT_FOUND.into_response()
+}
+
+/// 中古本サイト以外のサイトのパス
+fn prefix(site: Site) -> &'static str {
+    match site {
+        Site::Bookmeter => "bookmeter",
+        Site::Amazon => "amazon",
+        Site::Listasin => "listasin",
+    }
+}
+
+fn fixture(name: &str) -> Result<String> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name);
+    std::fs::read_to_string(&path)
+        .map_err(|e| anyhow::anyhow!("failed to read fixture {}: {e}", path.display()))
+}
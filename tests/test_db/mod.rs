@@ -0,0 +1,51 @@
+//! `update_discounts` を動かす統合テスト用の使い捨ての DB
+//!
+//! `update_discounts` はウィッシュリストにない本を削除するため、`DATABASE_URL` の DB は直接使わない。
+//! 同じサーバーに一意な名前の DB を作って `init.sql` を適用し、テストが終わったら
+//! (失敗・panic した場合も) 削除する。`DATABASE_URL` のユーザーには DB の作成権限が必要。
+
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use futures::FutureExt;
+use sea_orm::{ConnectionTrait, Database, DatabaseConnection};
+
+const DATABASE_URL_ENV: &str = "DATABASE_URL";
+
+/// 使い捨ての DB を作って `test` に渡し、終わったら削除する
+pub async fn with_test_database<F, Fut>(test: F) -> Result<()>
+where
+    F: FnOnce(DatabaseConnection) -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let admin_url = std::env::var(DATABASE_URL_ENV)
+        .map_err(|e| anyhow!("DATABASE_URL must be set to run this test: {e}"))?;
+    let admin = Database::connect(&admin_url).await?;
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+    let name = format!("bookmeter_discounts_test_{}_{nanos}", std::process::id());
+    admin
+        .execute_unprepared(&format!("create database {name}"))
+        .await?;
+
+    let outcome = async {
+        let mut url = url::Url::parse(&admin_url)?;
+        url.set_path(&name);
+        let db = Database::connect(url.as_str()).await?;
+        db.execute_unprepared(include_str!("../../init.sql"))
+            .await?;
+        let outcome = AssertUnwindSafe(test(db.clone())).catch_unwind().await;
+        db.close().await?;
+        anyhow::Ok(outcome)
+    }
+    .await;
+
+    admin
+        .execute_unprepared(&format!("drop database if exists {name} with (force)"))
+        .await?;
+    match outcome? {
+        Ok(result) => result,
+        Err(panic) => std::panic::resume_unwind(panic),
+    }
+}
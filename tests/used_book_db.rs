@@ -10,7 +10,7 @@
 
 use anyhow::anyhow;
 use bookmeter_discounts::model::Entity as Book;
-use bookmeter_discounts::used_book::UsedBookSite;
+use bookmeter_discounts::used_book::bookoff::Bookoff;
 use bookmeter_discounts::used_book_offer::Entity as UsedBookOffer;
 use bookmeter_discounts::BookMeterDiscounts;
 use sea_orm::{ActiveValue::Set, ColumnTrait, Database, EntityTrait, ModelTrait, QueryFilter};
@@ -44,6 +44,9 @@ async fn used_book_offer_roundtrip() -> anyhow::Result<()> {
         updated_at: Set(chrono::Utc::now().naive_utc()),
         active_at: Set(None),
         binding_name: Set(Some("文庫".to_string())),
+        point: Set(None),
+        author: Set(None),
+        store_links_checked_at: Set(None),
     })
     .exec(&db)
     .await?;
@@ -63,8 +66,7 @@ async fn used_book_offer_roundtrip() -> anyhow::Result<()> {
     assert!(eligible.iter().any(|b| b.bookmeter_id == bookmeter_id));
 
     // 実サイトからオファーを取得して保存
-    app.update_used_book_offer(&book, UsedBookSite::Bookoff, ISBN)
-        .await?;
+    app.update_used_book_offer(&book, &Bookoff, ISBN).await?;
     let offer = UsedBookOffer::find_by_id((bookmeter_id, "bookoff".to_string()))
         .one(&db)
         .await?
@@ -72,8 +74,7 @@ async fn used_book_offer_roundtrip() -> anyhow::Result<()> {
     assert_eq!(offer.product_id.as_deref(), Some("0016731582"));
 
     // 既知の商品 URL を使った再取得でも product_id が維持されること
-    app.update_used_book_offer(&book, UsedBookSite::Bookoff, ISBN)
-        .await?;
+    app.update_used_book_offer(&book, &Bookoff, ISBN).await?;
     let offer = UsedBookOffer::find_by_id((bookmeter_id, "bookoff".to_string()))
         .one(&db)
         .await?
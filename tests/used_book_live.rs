@@ -8,7 +8,13 @@
 //! ```
 
 use anyhow::anyhow;
-use bookmeter_discounts::used_book::UsedBookSite;
+use bookmeter_discounts::fetcher::LiveFetcher;
+use bookmeter_discounts::used_book::bookoff::Bookoff;
+use bookmeter_discounts::used_book::mottainai::Mottainai;
+use bookmeter_discounts::used_book::netoff::NetOff;
+use bookmeter_discounts::used_book::surugaya::Surugaya;
+use bookmeter_discounts::used_book::valuebooks::ValueBooks;
+use bookmeter_discounts::used_book::UsedBookStore;
 
 /// 海に願いを風に祈りをそして君に誓いを (スターツ出版文庫) の ISBN-13
 const ISBN: &str = "9784813705185";
@@ -16,14 +22,13 @@ const ISBN: &str = "9784813705185";
 #[tokio::test]
 #[ignore = "hits real websites"]
 async fn bookoff_live() -> anyhow::Result<()> {
-    let hit = UsedBookSite::Bookoff
-        .search(ISBN)
+    let fetcher = LiveFetcher::new();
+    let hit = Bookoff
+        .search(&fetcher, ISBN)
         .await?
         .ok_or_else(|| anyhow!("BOOKOFF search should find the book"))?;
     assert_eq!(hit.product_id, "0019117467");
-    let details = UsedBookSite::Bookoff
-        .fetch_details(&hit.product_url)
-        .await?;
+    let details = Bookoff.fetch_details(&fetcher, &hit.product_url).await?;
     assert!(details.price.is_some());
     Ok(())
 }
@@ -31,8 +36,9 @@ async fn bookoff_live() -> anyhow::Result<()> {
 #[tokio::test]
 #[ignore = "hits real websites"]
 async fn valuebooks_live() -> anyhow::Result<()> {
-    let hit = UsedBookSite::ValueBooks
-        .search(ISBN)
+    let fetcher = LiveFetcher::new();
+    let hit = ValueBooks
+        .search(&fetcher, ISBN)
         .await?
         .ok_or_else(|| anyhow!("ValueBooks search should find the book"))?;
     assert!(hit.product_id.starts_with("VS"));
@@ -46,21 +52,51 @@ async fn valuebooks_live() -> anyhow::Result<()> {
 #[tokio::test]
 #[ignore = "hits real websites"]
 async fn netoff_live() -> anyhow::Result<()> {
-    let hit = UsedBookSite::NetOff
-        .search(ISBN)
+    let fetcher = LiveFetcher::new();
+    let hit = NetOff
+        .search(&fetcher, ISBN)
         .await?
         .ok_or_else(|| anyhow!("NetOff search should find the book"))?;
     assert_eq!(hit.product_id, "0012822282");
-    let details = UsedBookSite::NetOff.fetch_details(&hit.product_url).await?;
+    let details = NetOff.fetch_details(&fetcher, &hit.product_url).await?;
     assert!(details.price.is_some());
     Ok(())
 }
 
+#[tokio::test]
+#[ignore = "hits real websites"]
+async fn surugaya_live() -> anyhow::Result<()> {
+    let fetcher = LiveFetcher::new();
+    let hit = Surugaya
+        .search(&fetcher, ISBN)
+        .await?
+        .ok_or_else(|| anyhow!("Surugaya search should find the book"))?;
+    assert!(hit.product_url.contains("/product/detail/"));
+    let details = Surugaya.fetch_details(&fetcher, &hit.product_url).await?;
+    assert!(details.price.is_some() || !details.in_stock);
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "hits real websites"]
+async fn mottainai_live() -> anyhow::Result<()> {
+    let fetcher = LiveFetcher::new();
+    let hit = Mottainai
+        .search(&fetcher, ISBN)
+        .await?
+        .ok_or_else(|| anyhow!("Mottainai search should find the book"))?;
+    assert!(hit.product_url.contains("/item/"));
+    let details = Mottainai.fetch_details(&fetcher, &hit.product_url).await?;
+    assert!(details.price.is_some() || !details.in_stock);
+    Ok(())
+}
+
 #[tokio::test]
 #[ignore = "hits real websites"]
 async fn refresh_offer_with_known_product_live() -> anyhow::Result<()> {
-    let update = UsedBookSite::Bookoff
+    let update = (&Bookoff as &dyn UsedBookStore)
         .refresh_offer(
+            &LiveFetcher::new(),
             ISBN,
             Some((
                 "0019117467",
@@ -0,0 +1,106 @@
+//! ローカルのスタブサーバー (`mock_sites`) に向けて中古本サイトの検索と商品ページ取得を動かすテスト
+//!
+//! `used_book_live` と同じ流れをフィクスチャで確認するため、ネットワークなしで CI でも実行する。
+
+mod mock_sites;
+
+use anyhow::anyhow;
+use bookmeter_discounts::used_book::{UsedBookStore, UsedBookStoreRegistry};
+use mock_sites::MockServer;
+
+/// 海に願いを風に祈りをそして君に誓いを (スターツ出版文庫) の ISBN-13
+const ISBN: &str = "9784813705185";
+
+#[tokio::test]
+async fn search_and_fetch_details_offline() -> anyhow::Result<()> {
+    let server = MockServer::start().await?;
+    let fetcher = server.fetcher();
+    let registry = UsedBookStoreRegistry::all();
+    let expected = [
+        (
+            "bookoff",
+            "0019117467",
+            "https://shopping.bookoff.co.jp/used/0019117467",
+            495,
+        ),
+        (
+            "valuebooks",
+            "VS0051080734",
+            "https://www.valuebooks.jp/bp/VS0051080734",
+            287,
+        ),
+        (
+            "netoff",
+            "0012822282",
+            "https://www.netoff.co.jp/detail/0012822282/",
+            220,
+        ),
+        (
+            "surugaya",
+            "ZHOR1234567",
+            "https://www.suruga-ya.jp/product/detail/ZHOR1234567",
+            330,
+        ),
+        (
+            "mottainai",
+            "1012345678",
+            "https://www.mottainaihonpo.com/item/1012345678/",
+            275,
+        ),
+    ];
+    for (site, product_id, product_url, price) in expected {
+        let store: &dyn UsedBookStore = registry
+            .get(site)
+            .ok_or_else(|| anyhow!("{site} should be registered"))?;
+        let hit = store
+            .search(fetcher.as_ref(), ISBN)
+            .await?
+            .ok_or_else(|| anyhow!("{site} search should find the book"))?;
+        assert_eq!(hit.product_id, product_id, "{site}");
+        // スクレイパーからは本番の URL に見える
+        assert_eq!(hit.product_url, product_url, "{site}");
+        let details = match hit.details {
+            Some(details) => details,
+            None => store.fetch_details(fetcher.as_ref(), product_url).await?,
+        };
+        assert_eq!(details.price, Some(price), "{site}");
+        assert!(details.in_stock, "{site}");
+    }
+    assert_eq!(server.unmatched_requests(), Vec::<String>::new());
+    Ok(())
+}
+
+#[tokio::test]
+async fn refresh_offer_offline() -> anyhow::Result<()> {
+    let server = MockServer::start().await?;
+    let fetcher = server.fetcher();
+    let registry = UsedBookStoreRegistry::all();
+    let bookoff: &dyn UsedBookStore = registry
+        .get("bookoff")
+        .ok_or_else(|| anyhow!("bookoff should be registered"))?;
+
+    // 商品が分かっている場合は商品ページだけを取得する
+    let update = bookoff
+        .refresh_offer(
+            fetcher.as_ref(),
+            ISBN,
+            Some((
+                "0019117467",
+                "https://shopping.bookoff.co.jp/used/0019117467",
+            )),
+        )
+        .await?;
+    assert_eq!(update.product_id.as_deref(), Some("0019117467"));
+    assert_eq!(update.price, Some(495));
+
+    // スタブサーバーにない本は見つからない扱いではなくエラーになる
+    assert!(bookoff
+        .refresh_offer(fetcher.as_ref(), "9784167158057", None)
+        .await
+        .is_err());
+    assert_eq!(
+        server.unmatched_requests(),
+        vec!["/bookoff/search/keyword/9784167158057".to_string()]
+    );
+    Ok(())
+}